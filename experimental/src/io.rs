@@ -21,13 +21,15 @@ use crate::units::{Direction, DrivingSide, Meters, Side, TrafficDirections};
 /// let mut net = load_road_network(String::from("tests/src/aurora_sausage_link/input.osm"), &mut timer).unwrap();
 /// println!("{}", net.to_dot());
 pub fn load_road_network(osm_path: String, timer: &mut Timer) -> Result<RoadNetwork> {
-    let driving_side = osm2streets::DrivingSide::Right; // TODO
     let clip_pts = None;
+    // Right is only used if the region can't be detected
+    let mut opts = streets_reader::Options::default_for_side(osm2streets::DrivingSide::Right);
+    opts.detect_region = true;
 
     let mut street_network = streets_reader::osm_to_street_network(
         &std::fs::read_to_string(osm_path).unwrap(),
         clip_pts,
-        streets_reader::Options::default_for_side(driving_side),
+        opts,
         timer,
     )?;
 
@@ -139,8 +141,12 @@ impl From<&osm2streets::Intersection> for Intersection {
                 _ => IntersectionType::Unknown,
             },
             control: match int.control {
-                // IntersectionType::StopSign => ControlType::Signed, // wrong when it should be uncontrolled
                 osm2streets::ControlType::TrafficSignal => ControlType::Lights,
+                // Intersections start uncontrolled, so signs come from the OSM data or
+                // Transformation::InferIntersectionControl
+                osm2streets::ControlType::StopSign | osm2streets::ControlType::YieldSign => {
+                    ControlType::Signed
+                }
                 _ => ControlType::Uncontrolled,
             },
         }
@@ -9,9 +9,11 @@ pub struct ImportOptions {
     driving_side: DrivingSide,
     debug_each_step: bool,
     dual_carriageway_experiment: bool,
-    cycletrack_snapping_experiment: bool,
     inferred_sidewalks: bool,
     osm2lanes: bool,
+    lane_widths_from_tags: bool,
+    detect_region: bool,
+    areas: bool,
 }
 
 #[wasm_bindgen]
@@ -33,6 +35,9 @@ impl JsStreetNetwork {
         let mut options = streets_reader::Options::default_for_side(input.driving_side);
         options.map_config.inferred_sidewalks = input.inferred_sidewalks;
         options.map_config.osm2lanes = input.osm2lanes;
+        options.map_config.lane_widths_from_tags = input.lane_widths_from_tags;
+        options.detect_region = input.detect_region;
+        options.areas = input.areas;
 
         let clip_pts = None;
         let mut timer = Timer::throwaway();
@@ -45,11 +50,6 @@ impl JsStreetNetwork {
             transformations.retain(|t| !matches!(t, Transformation::MergeShortRoads));
             transformations.push(Transformation::MergeDualCarriageways);
         }
-        if input.cycletrack_snapping_experiment {
-            transformations.push(Transformation::SnapCycleways);
-            transformations.push(Transformation::TrimDeadendCycleways);
-            transformations.push(Transformation::CollapseDegenerateIntersections);
-        }
         if input.debug_each_step {
             street_network.apply_transformations_stepwise_debugging(transformations, &mut timer);
         } else {
@@ -79,6 +79,16 @@ impl JsStreetNetwork {
             .unwrap()
     }
 
+    #[wasm_bindgen(js_name = toPublicTransportStopsGeojson)]
+    pub fn to_public_transport_stops_geojson(&self) -> String {
+        self.inner.to_public_transport_stops_geojson().unwrap()
+    }
+
+    #[wasm_bindgen(js_name = toAreasGeojson)]
+    pub fn to_areas_geojson(&self) -> String {
+        self.inner.to_areas_geojson().unwrap()
+    }
+
     #[wasm_bindgen(js_name = toGraphviz)]
     pub fn to_graphviz(&self) -> String {
         // TODO Should we make the caller do the clone? Is that weird from JS?
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use abstutil::Tags;
+use geom::{Distance, FindClosest, PolyLine, Polygon};
+use serde::{Deserialize, Serialize};
+
+use crate::{osm, OriginalRoad, StreetNetwork};
+
+/// A building, parking lot, or landuse area. These don't affect the road network at all, but are
+/// useful context for rendering it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Area {
+    pub area_type: AreaType,
+    pub polygon: Polygon,
+    /// `None` for areas that didn't come from OSM
+    pub osm_id: Option<osm::OsmID>,
+    pub osm_tags: Tags,
+    /// The road a building faces. Always `None` for other types of areas, or if no road is close
+    /// enough.
+    pub frontage: Option<Frontage>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AreaType {
+    Building,
+    ParkingLot,
+    /// Any `landuse`, or parks, water, woods, and similar
+    Landuse,
+}
+
+impl AreaType {
+    /// Classifies a closed way or multipolygon relation. `None` if it's not an interesting area.
+    pub fn from_tags(tags: &Tags) -> Option<AreaType> {
+        if tags.contains_key("building") && !tags.is("building", "no") {
+            return Some(AreaType::Building);
+        }
+        // Multi-storey garages are usually tagged as buildings too. Underground and on-street
+        // parking don't occupy any visible area.
+        if tags.is("amenity", "parking")
+            && !tags.is_any(
+                "parking",
+                vec!["underground", "multi-storey", "street_side", "lane"],
+            )
+        {
+            return Some(AreaType::ParkingLot);
+        }
+        if tags.contains_key("landuse")
+            || tags.is_any(
+                "leisure",
+                vec!["garden", "nature_reserve", "park", "pitch", "playground"],
+            )
+            || tags.is_any(
+                "natural",
+                vec!["grassland", "scrub", "water", "wetland", "wood"],
+            )
+        {
+            return Some(AreaType::Landuse);
+        }
+        None
+    }
+}
+
+/// Where a building is along a road
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frontage {
+    pub road: OriginalRoad,
+    /// Distance along the road's untrimmed center line, from `i1`
+    pub dist_along: Distance,
+    /// Is the building to the left of the road, looking from `i1` to `i2`? That's the side where
+    /// `lane_specs_ltr` starts.
+    pub left_side: bool,
+}
+
+impl StreetNetwork {
+    /// Link every building to the closest road that it plausibly faces. This has to be redone when
+    /// roads change; `apply_transformations` does so automatically at the end.
+    pub fn link_building_frontages(&mut self) {
+        if !self.areas.iter().any(|a| a.area_type == AreaType::Building) {
+            return;
+        }
+
+        // Buildings don't front onto motorways or railways
+        let mut center_lines = BTreeMap::new();
+        let mut closest = FindClosest::new(&self.gps_bounds.to_bounds());
+        for (id, road) in &self.roads {
+            if road.is_light_rail()
+                || road
+                    .osm_tags
+                    .is_any(osm::HIGHWAY, vec!["motorway", "motorway_link"])
+                || !road
+                    .lane_specs_ltr
+                    .iter()
+                    .any(|l| l.lt.is_walkable() || l.lt.is_for_moving_vehicles())
+            {
+                continue;
+            }
+            if let Ok(pl) = PolyLine::new(road.osm_center_points.clone()) {
+                closest.add(*id, pl.points());
+                center_lines.insert(*id, pl);
+            }
+        }
+
+        let max_dist = Distance::meters(100.0);
+        for area in &mut self.areas {
+            if area.area_type != AreaType::Building {
+                continue;
+            }
+            let center = area.polygon.center();
+            area.frontage = closest.closest_pt(center, max_dist).and_then(|(road, _)| {
+                let pl = &center_lines[&road];
+                let projected = pl.project_pt(center);
+                let (dist_along, road_angle) = pl.dist_along_of_point(projected)?;
+                let mut diff = projected.angle_to(center).normalized_degrees()
+                    - road_angle.normalized_degrees();
+                if diff > 180.0 {
+                    diff -= 360.0;
+                } else if diff <= -180.0 {
+                    diff += 360.0;
+                }
+                Some(Frontage {
+                    road,
+                    dist_along,
+                    // Angles increase clockwise, so a negative difference means the left
+                    left_side: diff < 0.0,
+                })
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_area_types() {
+        let tags = |pairs: &[(&str, &str)]| {
+            let mut tags = Tags::empty();
+            for (k, v) in pairs {
+                tags.insert(*k, *v);
+            }
+            tags
+        };
+
+        assert_eq!(
+            AreaType::from_tags(&tags(&[("building", "house")])),
+            Some(AreaType::Building)
+        );
+        assert_eq!(
+            AreaType::from_tags(&tags(&[("amenity", "parking"), ("building", "yes")])),
+            Some(AreaType::Building)
+        );
+        assert_eq!(
+            AreaType::from_tags(&tags(&[("amenity", "parking")])),
+            Some(AreaType::ParkingLot)
+        );
+        assert_eq!(
+            AreaType::from_tags(&tags(&[("amenity", "parking"), ("parking", "underground")])),
+            None
+        );
+        assert_eq!(
+            AreaType::from_tags(&tags(&[("leisure", "park")])),
+            Some(AreaType::Landuse)
+        );
+        assert_eq!(AreaType::from_tags(&tags(&[("building", "no")])), None);
+        assert_eq!(AreaType::from_tags(&tags(&[("highway", "primary")])), None);
+    }
+}
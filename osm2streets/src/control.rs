@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+
+use geom::{Distance, Pt2D};
+use serde::{Deserialize, Serialize};
+
+use crate::{osm, Direction, OriginalRoad, Road, StreetNetwork};
+
+/// How traffic entering an intersection from one road is controlled. Ordered from weakest to
+/// strongest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ApproachControl {
+    /// Give way to other traffic
+    Yield,
+    /// Come to a complete stop, then give way
+    Stop,
+    TrafficSignal,
+}
+
+impl Road {
+    /// Can vehicles on this road enter intersection `i`? `id` must be this road's ID.
+    pub fn approaches(&self, id: OriginalRoad, i: osm::NodeID) -> bool {
+        let dir = if id.i2 == i {
+            Direction::Fwd
+        } else if id.i1 == i {
+            Direction::Back
+        } else {
+            return false;
+        };
+        self.lane_specs_ltr
+            .iter()
+            .any(|l| l.dir == dir && l.lt.is_for_moving_vehicles())
+    }
+}
+
+impl StreetNetwork {
+    /// Records how traffic from road `r` entering intersection `i` is controlled. If the approach
+    /// already has some control, the stronger one is kept. Nothing happens if vehicles can't
+    /// enter `i` from `r`.
+    pub fn set_approach_control(
+        &mut self,
+        i: osm::NodeID,
+        r: OriginalRoad,
+        control: ApproachControl,
+    ) {
+        if !self
+            .roads
+            .get(&r)
+            .map(|road| road.approaches(r, i))
+            .unwrap_or(false)
+        {
+            return;
+        }
+        if let Some(intersection) = self.intersections.get_mut(&i) {
+            let entry = intersection.approach_controls.entry(r).or_insert(control);
+            *entry = (*entry).max(control);
+        }
+    }
+
+    /// All roads that vehicles can use to enter intersection `i`.
+    pub fn approaching_roads(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
+        self.roads_per_intersection(i)
+            .into_iter()
+            .filter(|r| self.roads[r].approaches(*r, i))
+            .collect()
+    }
+
+    /// The approaches to intersection `i` from roads ranked lower than the biggest road there.
+    /// Empty if every road has the same rank.
+    pub fn minor_approaches(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
+        let major = match self
+            .roads_per_intersection(i)
+            .into_iter()
+            .map(|r| self.roads[&r].rank())
+            .max()
+        {
+            Some(rank) => rank,
+            None => return Vec::new(),
+        };
+        self.approaching_roads(i)
+            .into_iter()
+            .filter(|r| self.roads[r].rank() < major)
+            .collect()
+    }
+
+    /// The approaches to intersection `i` where vehicles must come to a complete stop.
+    pub fn must_stop(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
+        self.intersections[&i]
+            .approach_controls
+            .iter()
+            .filter(|(_, control)| **control == ApproachControl::Stop)
+            .map(|(r, _)| *r)
+            .collect()
+    }
+
+    /// Does every vehicle entering intersection `i` have to stop? If not, any stop signs there
+    /// only apply to the minor roads.
+    pub fn is_all_way_stop(&self, i: osm::NodeID) -> bool {
+        let approaches = self.approaching_roads(i);
+        let controls = &self.intersections[&i].approach_controls;
+        approaches.len() > 1
+            && approaches
+                .iter()
+                .all(|r| controls.get(r) == Some(&ApproachControl::Stop))
+    }
+}
+
+/// Approach controls are keyed by road, so they go stale when roads are renamed, merged, or
+/// removed. Take a snapshot before such a change, then `fix` afterwards.
+pub struct ApproachSnapshot {
+    // (intersection, its position, road, the road's original segments, control)
+    approaches: Vec<(
+        osm::NodeID,
+        Pt2D,
+        OriginalRoad,
+        Vec<OriginalRoad>,
+        ApproachControl,
+    )>,
+}
+
+impl ApproachSnapshot {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        let mut approaches = Vec::new();
+        for (i, intersection) in &streets.intersections {
+            for (r, control) in &intersection.approach_controls {
+                let segments = streets
+                    .roads
+                    .get(r)
+                    .map(|road| road.original_segments.clone())
+                    .unwrap_or_default();
+                approaches.push((*i, intersection.point, *r, segments, *control));
+            }
+        }
+        Self { approaches }
+    }
+
+    /// Move every approach control to the road and intersection that now contain the original
+    /// ones. Controls for roads that were removed, or whose end at the intersection is gone, are
+    /// dropped.
+    ///
+    /// When the intersection was merged into another one, the road's end was moved there. Then
+    /// the control goes to the closest end of the road, within 30m of the old intersection, that
+    /// isn't an end of one of the road's original segments. Ends the road always had are never
+    /// used, so a control at a degenerate intersection that was collapsed is dropped, rather than
+    /// moved to the far end of a short road.
+    pub fn fix(self, streets: &mut StreetNetwork) {
+        if self.approaches.is_empty() {
+            return;
+        }
+        for intersection in streets.intersections.values_mut() {
+            intersection.approach_controls.clear();
+        }
+
+        // When intersections are merged, the surviving one moves a little
+        let max_dist = Distance::meters(30.0);
+        let owners = streets.original_segments_to_roads();
+        for (i, pt, r, segments, control) in self.approaches {
+            let mut candidates = vec![r];
+            // Prefer the original segment touching the intersection
+            candidates.extend(segments.iter().filter(|s| s.i1 == i || s.i2 == i));
+            candidates.extend(segments.iter());
+
+            let found = candidates.into_iter().find_map(|segment| {
+                let road = if streets.roads.contains_key(&segment) {
+                    segment
+                } else {
+                    *owners.get(&segment)?
+                };
+                if road.i1 == i || road.i2 == i {
+                    return Some((i, road));
+                }
+                let original_ends = &streets.roads[&road].original_segments;
+                [road.i1, road.i2]
+                    .into_iter()
+                    .filter(|end| !original_ends.iter().any(|s| s.i1 == *end || s.i2 == *end))
+                    .map(|end| (streets.intersections[&end].point.dist_to(pt), end))
+                    .filter(|(dist, _)| *dist <= max_dist)
+                    .min_by_key(|(dist, _)| *dist)
+                    .map(|(_, end)| (end, road))
+            });
+            match found {
+                Some((new_i, new_r)) => {
+                    streets.set_approach_control(new_i, new_r, control);
+                }
+                None => {
+                    debug!("Dropping {:?} control for {} entering {}", control, r, i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+    use crate::Transformation;
+
+    // A primary road crossing a residential one at 1. The residential road leading south is
+    // one-way away from 1.
+    fn crossroads() -> (StreetNetwork, [OriginalRoad; 4]) {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, -100.0, 0.0);
+        add_intersection(&mut streets, 3, 100.0, 0.0);
+        add_intersection(&mut streets, 4, 0.0, -100.0);
+        add_intersection(&mut streets, 5, 0.0, 100.0);
+        let west = add_road(&mut streets, 10, 2, 1, &[("highway", "primary")]);
+        let east = add_road(&mut streets, 11, 1, 3, &[("highway", "primary")]);
+        let north = add_road(&mut streets, 12, 4, 1, &[]);
+        let south = add_road(&mut streets, 13, 1, 5, &[("oneway", "yes")]);
+        (streets, [west, east, north, south])
+    }
+
+    #[test]
+    fn test_minor_approaches() {
+        let (mut streets, [west, east, north, south]) = crossroads();
+        let i = osm::NodeID(1);
+        assert_eq!(streets.approaching_roads(i).len(), 3);
+        assert!(!streets.approaching_roads(i).contains(&south));
+        // Traffic leaving on the one-way road doesn't approach
+        assert_eq!(streets.minor_approaches(i), vec![north]);
+
+        // When every road has the same rank, none are minor
+        for r in [west, east] {
+            streets
+                .roads
+                .get_mut(&r)
+                .unwrap()
+                .osm_tags
+                .insert("highway", "residential");
+        }
+        assert!(streets.minor_approaches(i).is_empty());
+        // Dead-ends have nothing to be minor to
+        assert!(streets.minor_approaches(osm::NodeID(2)).is_empty());
+    }
+
+    #[test]
+    fn test_is_all_way_stop() {
+        let (mut streets, [west, east, north, _]) = crossroads();
+        let i = osm::NodeID(1);
+        assert!(!streets.is_all_way_stop(i));
+
+        streets.set_approach_control(i, north, ApproachControl::Stop);
+        assert!(!streets.is_all_way_stop(i));
+        assert_eq!(streets.must_stop(i), vec![north]);
+
+        streets.set_approach_control(i, west, ApproachControl::Stop);
+        streets.set_approach_control(i, east, ApproachControl::Yield);
+        assert!(!streets.is_all_way_stop(i));
+        // The stronger control wins
+        streets.set_approach_control(i, east, ApproachControl::Stop);
+        streets.set_approach_control(i, east, ApproachControl::Yield);
+        // The one-way road leaving doesn't need a stop
+        assert!(streets.is_all_way_stop(i));
+
+        // One approach isn't enough
+        streets.set_approach_control(osm::NodeID(2), west, ApproachControl::Stop);
+        assert!(!streets.is_all_way_stop(osm::NodeID(2)));
+    }
+
+    #[test]
+    fn test_fix_after_merge() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 0.0);
+        add_intersection(&mut streets, 3, 105.0, 0.0);
+        add_intersection(&mut streets, 4, 200.0, 0.0);
+        add_intersection(&mut streets, 5, 105.0, 100.0);
+        let a = add_road(&mut streets, 10, 1, 2, &[]);
+        add_road(&mut streets, 11, 2, 3, &[("junction", "intersection")]);
+        add_road(&mut streets, 12, 3, 4, &[]);
+        let c = add_road(&mut streets, 13, 5, 3, &[]);
+        streets.set_approach_control(osm::NodeID(2), a, ApproachControl::Stop);
+        streets.set_approach_control(osm::NodeID(3), c, ApproachControl::Yield);
+
+        // Merging the short road keeps 2, so c ends there now
+        streets.apply_transformations(
+            vec![Transformation::MergeShortRoads],
+            &mut abstutil::Timer::throwaway(),
+        );
+        assert!(!streets.intersections.contains_key(&osm::NodeID(3)));
+        let moved = OriginalRoad::new(13, (5, 2));
+        assert_eq!(streets.road_for_original_segment(c), Some(moved));
+
+        let controls = &streets.intersections[&osm::NodeID(2)].approach_controls;
+        assert_eq!(controls.len(), 2);
+        assert_eq!(controls[&a], ApproachControl::Stop);
+        assert_eq!(controls[&moved], ApproachControl::Yield);
+        assert!(streets.intersections[&osm::NodeID(5)]
+            .approach_controls
+            .is_empty());
+        assert!(streets.validate().is_empty());
+    }
+
+    #[test]
+    fn test_fix_after_collapse() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 20.0, 0.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        let a = add_road(&mut streets, 10, 1, 2, &[]);
+        let b = add_road(&mut streets, 11, 2, 3, &[]);
+        streets.set_approach_control(osm::NodeID(2), a, ApproachControl::Stop);
+        streets.set_approach_control(osm::NodeID(3), b, ApproachControl::TrafficSignal);
+
+        // 2 is in the middle of the merged road now. 1 is close by, but the stop didn't apply
+        // there.
+        streets.apply_transformations(
+            vec![Transformation::CollapseDegenerateIntersections],
+            &mut abstutil::Timer::throwaway(),
+        );
+        let merged = OriginalRoad::new(11, (1, 3));
+        assert_eq!(streets.road_for_original_segment(a), Some(merged));
+        assert!(streets.intersections[&osm::NodeID(1)]
+            .approach_controls
+            .is_empty());
+        let controls = &streets.intersections[&osm::NodeID(3)].approach_controls;
+        assert_eq!(controls.len(), 1);
+        assert_eq!(controls[&merged], ApproachControl::TrafficSignal);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{BufferType, Direction, DrivingSide, LaneSpec, LaneType};
+use std::collections::BTreeSet;
+
+use crate::{BufferType, Direction, DrivingSide, LaneSpec, LaneType, WidthSource};
 
 impl LaneSpec {
     pub fn maybe_add_bike_lanes(
@@ -81,6 +83,9 @@ impl LaneSpec {
                     lt: LaneType::Biking,
                     dir,
                     width: LaneSpec::typical_lane_width(LaneType::Biking),
+                    allowed_turns: BTreeSet::new(),
+                    width_source: WidthSource::Default,
+                    speed_limit: None,
                 };
                 if let Some(buffer) = buffer_type {
                     side.insert(
@@ -89,6 +94,9 @@ impl LaneSpec {
                             lt: LaneType::Buffer(buffer),
                             dir,
                             width: LaneSpec::typical_lane_width(LaneType::Buffer(buffer)),
+                            allowed_turns: BTreeSet::new(),
+                            width_source: WidthSource::Default,
+                            speed_limit: None,
                         },
                     );
                 }
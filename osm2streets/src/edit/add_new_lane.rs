@@ -1,6 +1,8 @@
+use std::collections::BTreeSet;
+
 use abstutil::Tags;
 
-use crate::{Direction, DrivingSide, LaneSpec, LaneType};
+use crate::{Direction, DrivingSide, LaneSpec, LaneType, WidthSource};
 
 impl LaneSpec {
     /// Returns the index where the new lane was inserted
@@ -103,6 +105,9 @@ impl LaneSpec {
                 lt,
                 dir,
                 width: LaneSpec::typical_lane_widths(lt, osm_tags)[0].0,
+                allowed_turns: BTreeSet::new(),
+                width_source: WidthSource::Default,
+                speed_limit: None,
             },
         );
         idx
@@ -0,0 +1,91 @@
+use std::collections::BTreeSet;
+
+use abstutil::Tags;
+
+use crate::{LaneSpec, LaneType, WidthSource};
+
+impl LaneSpec {
+    /// Adds a parking lane at the curb on one side of the road, inside of any sidewalk or
+    /// shoulder. The left side is the start of `lanes_ltr`. Nothing happens if the road has no
+    /// driving lanes or there's already parking at that curb. Returns true if a lane was added.
+    pub fn add_parking_lane(
+        lanes_ltr: &mut Vec<LaneSpec>,
+        left_side: bool,
+        osm_tags: &Tags,
+    ) -> bool {
+        if !lanes_ltr.iter().any(|spec| spec.lt == LaneType::Driving) {
+            return false;
+        }
+
+        // Search from the curb inwards
+        let curb = if left_side {
+            lanes_ltr.iter().position(|spec| !spec.lt.is_walkable())
+        } else {
+            lanes_ltr.iter().rposition(|spec| !spec.lt.is_walkable())
+        };
+        let curb = match curb {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if lanes_ltr[curb].lt == LaneType::Parking {
+            return false;
+        }
+
+        // Park in the same direction as the adjacent traffic
+        let spec = LaneSpec {
+            lt: LaneType::Parking,
+            dir: lanes_ltr[curb].dir,
+            width: LaneSpec::typical_lane_widths(LaneType::Parking, osm_tags)[0].0,
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
+        };
+        lanes_ltr.insert(if left_side { curb } else { curb + 1 }, spec);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_parking_lane() {
+        let mut ok = true;
+        for (description, input_lt, input_dir, left_side, expected_lt, expected_dir) in vec![
+            ("Two-way, left side", "sdds", "vv^^", true, "spdds", "vvv^^"),
+            (
+                "Two-way, right side",
+                "sdds",
+                "vv^^",
+                false,
+                "sddps",
+                "vv^^^",
+            ),
+            ("One-way without sidewalks", "dd", "^^", true, "pdd", "^^^"),
+            (
+                "Already has parking",
+                "spdds",
+                "vvv^^",
+                true,
+                "spdds",
+                "vvv^^",
+            ),
+            ("Cycleway", "b", "^", false, "b", "^"),
+        ] {
+            let input = LaneSpec::create_for_test(input_lt, input_dir);
+            let mut actual_output = input.clone();
+            LaneSpec::add_parking_lane(&mut actual_output, left_side, &Tags::empty());
+            LaneSpec::check_lanes_ltr(
+                &actual_output,
+                description.to_string(),
+                input_lt,
+                input_dir,
+                expected_lt,
+                expected_dir,
+                &mut ok,
+            );
+        }
+        assert!(ok);
+    }
+}
@@ -3,11 +3,14 @@
 
 mod add_bike_lanes;
 mod add_new_lane;
+mod add_parking_lane;
 mod one_ways;
 
+use std::collections::BTreeSet;
+
 use geom::Distance;
 
-use crate::{Direction, LaneSpec, LaneType};
+use crate::{Direction, LaneSpec, LaneType, WidthSource};
 
 impl LaneSpec {
     /// Transforms a string describing lane types and directions, like "spddps" and "vv^^^^^", into
@@ -26,6 +29,9 @@ impl LaneSpec {
                 },
                 // Dummy
                 width: Distance::ZERO,
+                allowed_turns: BTreeSet::new(),
+                width_source: WidthSource::Default,
+                speed_limit: None,
             })
             .collect()
     }
@@ -50,6 +50,8 @@ impl LaneSpec {
         for lane in lanes_ltr {
             if lane.lt == LaneType::Driving {
                 driving_lanes_so_far += 1;
+                // The painted turn arrows don't apply anymore
+                lane.allowed_turns.clear();
                 match oneway_dir {
                     Some(Direction::Fwd) => {
                         // If it's one-way forwards, flip the direction
@@ -0,0 +1,405 @@
+use std::path::Path;
+
+use abstutil::Timer;
+use anyhow::Result;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, Transaction};
+
+use crate::initial::InitialMap;
+use crate::render::Feature;
+use crate::StreetNetwork;
+
+// WGS84
+const SRS_ID: i32 = 4326;
+
+impl StreetNetwork {
+    /// Saves the same layers as the GeoJSON renderings to a GeoPackage file, which is much faster
+    /// for GIS tools to load for large areas. There are four tables -- `roads`, `intersections`,
+    /// `lanes`, and `lane_markings` -- with the same properties as `to_geojson`,
+    /// `to_lane_polygons_geojson`, and `to_lane_markings_geojson`. An existing file is replaced.
+    pub fn save_to_geopackage(&self, output_path: &str, timer: &mut Timer) -> Result<()> {
+        // TODO InitialMap is going away very soon, but we still need it
+        let initial_map = InitialMap::new(self, timer);
+        let layers = vec![
+            ("roads", self.road_features(&initial_map)),
+            ("intersections", self.intersection_features(&initial_map)),
+            ("lanes", self.lane_polygon_features(&initial_map)),
+            ("lane_markings", self.lane_marking_features(&initial_map)?),
+        ];
+
+        if let Some(parent) = Path::new(output_path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if Path::new(output_path).exists() {
+            std::fs::remove_file(output_path)?;
+        }
+
+        timer.start("write GeoPackage");
+        let mut conn = Connection::open(output_path)?;
+        // "GPKG" and version 1.2
+        conn.execute_batch("PRAGMA application_id = 1196444487; PRAGMA user_version = 10200;")?;
+        let tx = conn.transaction()?;
+        create_metadata_tables(&tx)?;
+        for (name, features) in layers {
+            write_layer(&tx, name, features)?;
+        }
+        tx.commit()?;
+        timer.stop("write GeoPackage");
+        Ok(())
+    }
+}
+
+fn create_metadata_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE gpkg_spatial_ref_sys (
+            srs_name TEXT NOT NULL,
+            srs_id INTEGER NOT NULL PRIMARY KEY,
+            organization TEXT NOT NULL,
+            organization_coordsys_id INTEGER NOT NULL,
+            definition TEXT NOT NULL,
+            description TEXT
+        );
+        INSERT INTO gpkg_spatial_ref_sys VALUES
+            ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', NULL),
+            ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', NULL),
+            ('WGS 84 geodetic', 4326, 'EPSG', 4326, 'GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]', NULL);
+
+        CREATE TABLE gpkg_contents (
+            table_name TEXT NOT NULL PRIMARY KEY,
+            data_type TEXT NOT NULL,
+            identifier TEXT UNIQUE,
+            description TEXT DEFAULT '',
+            last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            min_x DOUBLE,
+            min_y DOUBLE,
+            max_x DOUBLE,
+            max_y DOUBLE,
+            srs_id INTEGER,
+            CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+        );
+
+        CREATE TABLE gpkg_geometry_columns (
+            table_name TEXT NOT NULL,
+            column_name TEXT NOT NULL,
+            geometry_type_name TEXT NOT NULL,
+            srs_id INTEGER NOT NULL,
+            z TINYINT NOT NULL,
+            m TINYINT NOT NULL,
+            CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+            CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+            CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+        );",
+    )?;
+    Ok(())
+}
+
+fn write_layer(tx: &Transaction, name: &str, features: Vec<Feature>) -> Result<()> {
+    // Every property used by any feature becomes a column. The type comes from the first value
+    // seen.
+    let mut columns: Vec<(String, &'static str)> = Vec::new();
+    for (_, props) in &features {
+        for (key, value) in props {
+            if columns.iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            let sql_type = match value {
+                serde_json::Value::Null => continue,
+                serde_json::Value::Bool(_) => "BOOLEAN",
+                serde_json::Value::Number(n) if n.is_f64() => "DOUBLE",
+                serde_json::Value::Number(_) => "INTEGER",
+                _ => "TEXT",
+            };
+            columns.push((key.clone(), sql_type));
+        }
+    }
+
+    let mut create = format!(
+        "CREATE TABLE \"{}\" (fid INTEGER PRIMARY KEY AUTOINCREMENT, geom GEOMETRY",
+        name
+    );
+    for (key, sql_type) in &columns {
+        create.push_str(&format!(", {} {}", column_name(key), sql_type));
+    }
+    create.push(')');
+    tx.execute(&create, [])?;
+
+    let mut insert = format!("INSERT INTO \"{}\" (geom", name);
+    for (key, _) in &columns {
+        insert.push_str(&format!(", {}", column_name(key)));
+    }
+    insert.push_str(") VALUES (?");
+    insert.push_str(&", ?".repeat(columns.len()));
+    insert.push(')');
+
+    let mut layer_bounds = Bounds::new();
+    {
+        let mut stmt = tx.prepare(&insert)?;
+        for (geometry, props) in features {
+            let mut bounds = Bounds::new();
+            bounds.update(&geometry.value);
+            layer_bounds.union(&bounds);
+
+            let mut values = vec![Value::Blob(to_gpkg_blob(&geometry.value, &bounds))];
+            for (key, _) in &columns {
+                values.push(match props.get(key) {
+                    None | Some(serde_json::Value::Null) => Value::Null,
+                    Some(serde_json::Value::Bool(x)) => Value::Integer(i64::from(*x)),
+                    Some(serde_json::Value::Number(n)) => {
+                        if let Some(x) = n.as_i64() {
+                            Value::Integer(x)
+                        } else {
+                            Value::Real(n.as_f64().unwrap())
+                        }
+                    }
+                    Some(serde_json::Value::String(x)) => Value::Text(x.clone()),
+                    Some(x) => Value::Text(x.to_string()),
+                });
+            }
+            stmt.execute(params_from_iter(values))?;
+        }
+    }
+
+    let (min_x, min_y, max_x, max_y) = if layer_bounds.is_empty() {
+        (None, None, None, None)
+    } else {
+        (
+            Some(layer_bounds.min_x),
+            Some(layer_bounds.min_y),
+            Some(layer_bounds.max_x),
+            Some(layer_bounds.max_y),
+        )
+    };
+    tx.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id) VALUES (?1, 'features', ?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![name, min_x, min_y, max_x, max_y, SRS_ID],
+    )?;
+    tx.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', 'GEOMETRY', ?2, 0, 0)",
+        rusqlite::params![name, SRS_ID],
+    )?;
+    Ok(())
+}
+
+// The quoted column for a property. `fid` and `geom` are already used for the feature ID and
+// geometry, so properties with those names get a prefix. (Column names aren't case-sensitive.)
+fn column_name(key: &str) -> String {
+    let name = if key.eq_ignore_ascii_case("fid") || key.eq_ignore_ascii_case("geom") {
+        format!("property_{}", key)
+    } else {
+        key.to_string()
+    };
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+struct Bounds {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl Bounds {
+    fn new() -> Self {
+        Self {
+            min_x: f64::MAX,
+            min_y: f64::MAX,
+            max_x: f64::MIN,
+            max_y: f64::MIN,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min_x > self.max_x
+    }
+
+    fn union(&mut self, other: &Bounds) {
+        self.min_x = self.min_x.min(other.min_x);
+        self.min_y = self.min_y.min(other.min_y);
+        self.max_x = self.max_x.max(other.max_x);
+        self.max_y = self.max_y.max(other.max_y);
+    }
+
+    fn update(&mut self, geometry: &geojson::Value) {
+        let mut add = |pt: &[f64]| {
+            self.min_x = self.min_x.min(pt[0]);
+            self.min_y = self.min_y.min(pt[1]);
+            self.max_x = self.max_x.max(pt[0]);
+            self.max_y = self.max_y.max(pt[1]);
+        };
+        match geometry {
+            geojson::Value::Point(pt) => add(pt),
+            geojson::Value::MultiPoint(pts) | geojson::Value::LineString(pts) => {
+                pts.iter().for_each(|pt| add(pt))
+            }
+            geojson::Value::MultiLineString(lines) | geojson::Value::Polygon(lines) => {
+                lines.iter().flatten().for_each(|pt| add(pt))
+            }
+            geojson::Value::MultiPolygon(polygons) => {
+                polygons.iter().flatten().flatten().for_each(|pt| add(pt))
+            }
+            geojson::Value::GeometryCollection(list) => {
+                for geometry in list {
+                    self.update(&geometry.value);
+                }
+            }
+        }
+    }
+}
+
+// The GeoPackage binary format is a small header, followed by standard WKB
+fn to_gpkg_blob(geometry: &geojson::Value, bounds: &Bounds) -> Vec<u8> {
+    let mut out = vec![b'G', b'P', 0];
+    if bounds.is_empty() {
+        // Little-endian, no envelope, empty geometry
+        out.push(0b0001_0001);
+        out.extend_from_slice(&SRS_ID.to_le_bytes());
+    } else {
+        // Little-endian, with an [minx, maxx, miny, maxy] envelope
+        out.push(0b0000_0011);
+        out.extend_from_slice(&SRS_ID.to_le_bytes());
+        for x in [bounds.min_x, bounds.max_x, bounds.min_y, bounds.max_y] {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    write_wkb(&mut out, geometry);
+    out
+}
+
+fn write_wkb(out: &mut Vec<u8>, geometry: &geojson::Value) {
+    // Little-endian
+    out.push(1);
+    match geometry {
+        geojson::Value::Point(pt) => {
+            out.extend_from_slice(&1_u32.to_le_bytes());
+            write_pt(out, pt);
+        }
+        geojson::Value::LineString(pts) => {
+            out.extend_from_slice(&2_u32.to_le_bytes());
+            write_pts(out, pts);
+        }
+        geojson::Value::Polygon(rings) => {
+            out.extend_from_slice(&3_u32.to_le_bytes());
+            write_rings(out, rings);
+        }
+        geojson::Value::MultiPoint(pts) => {
+            out.extend_from_slice(&4_u32.to_le_bytes());
+            out.extend_from_slice(&(pts.len() as u32).to_le_bytes());
+            for pt in pts {
+                write_wkb(out, &geojson::Value::Point(pt.clone()));
+            }
+        }
+        geojson::Value::MultiLineString(lines) => {
+            out.extend_from_slice(&5_u32.to_le_bytes());
+            out.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+            for line in lines {
+                write_wkb(out, &geojson::Value::LineString(line.clone()));
+            }
+        }
+        geojson::Value::MultiPolygon(polygons) => {
+            out.extend_from_slice(&6_u32.to_le_bytes());
+            out.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+            for rings in polygons {
+                write_wkb(out, &geojson::Value::Polygon(rings.clone()));
+            }
+        }
+        geojson::Value::GeometryCollection(list) => {
+            out.extend_from_slice(&7_u32.to_le_bytes());
+            out.extend_from_slice(&(list.len() as u32).to_le_bytes());
+            for geometry in list {
+                write_wkb(out, &geometry.value);
+            }
+        }
+    }
+}
+
+fn write_rings(out: &mut Vec<u8>, rings: &[Vec<Vec<f64>>]) {
+    out.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+    for ring in rings {
+        write_pts(out, ring);
+    }
+}
+
+fn write_pts(out: &mut Vec<u8>, pts: &[Vec<f64>]) {
+    out.extend_from_slice(&(pts.len() as u32).to_le_bytes());
+    for pt in pts {
+        write_pt(out, pt);
+    }
+}
+
+fn write_pt(out: &mut Vec<u8>, pt: &[f64]) {
+    out.extend_from_slice(&pt[0].to_le_bytes());
+    out.extend_from_slice(&pt[1].to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::LonLat;
+
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+
+    #[test]
+    fn test_save_to_geopackage() {
+        let mut streets = StreetNetwork::blank();
+        streets.gps_bounds.update(LonLat::new(-122.30, 47.60));
+        streets.gps_bounds.update(LonLat::new(-122.29, 47.61));
+        add_intersection(&mut streets, 1, 100.0, 100.0);
+        add_intersection(&mut streets, 2, 0.0, 100.0);
+        add_intersection(&mut streets, 3, 200.0, 100.0);
+        add_road(&mut streets, 10, 2, 1, &[]);
+        add_road(&mut streets, 11, 1, 3, &[]);
+
+        let path = std::env::temp_dir().join("osm2streets_test_save_to_geopackage.gpkg");
+        let path = path.to_str().unwrap();
+        streets
+            .save_to_geopackage(path, &mut Timer::throwaway())
+            .unwrap();
+
+        let conn = Connection::open(path).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM roads"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM intersections"), 3);
+        assert!(count("SELECT COUNT(*) FROM lanes") > 0);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM gpkg_contents WHERE data_type = 'features'"),
+            4
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM roads WHERE osm_way_id = 10 AND src_i = 2 AND dst_i = 1"),
+            1
+        );
+
+        let blob: Vec<u8> = conn
+            .query_row("SELECT geom FROM roads LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(&blob[0..2], b"GP");
+        drop(conn);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reserved_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tx = conn.transaction().unwrap();
+        create_metadata_tables(&tx).unwrap();
+
+        let mut props = serde_json::Map::new();
+        props.insert("fid".to_string(), 42.into());
+        props.insert("Geom".to_string(), "point".into());
+        props.insert("name".to_string(), "x".into());
+        let feature = (
+            geojson::Geometry::new(geojson::Value::Point(vec![1.0, 2.0])),
+            props,
+        );
+        write_layer(&tx, "layer", vec![feature]).unwrap();
+
+        let row: (i64, i64, String, String) = tx
+            .query_row(
+                "SELECT fid, property_fid, property_Geom, name FROM layer",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(row, (1, 42, "point".to_string(), "x".to_string()));
+    }
+}
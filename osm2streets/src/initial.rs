@@ -6,11 +6,16 @@ use std::collections::{BTreeMap, BTreeSet};
 use abstutil::{Tags, Timer};
 use geom::{Circle, Distance, PolyLine, Polygon, Pt2D};
 
-use crate::{osm, ControlType, InputRoad, IntersectionComplexity, OriginalRoad, StreetNetwork};
+use crate::{
+    osm, ControlType, InputRoad, IntersectionComplexity, OriginalRoad, StreetNetwork, Violation,
+};
 
 pub struct InitialMap {
     pub roads: BTreeMap<OriginalRoad, Road>,
     pub intersections: BTreeMap<osm::NodeID, Intersection>,
+    /// Roads and intersections whose geometry couldn't be built. Broken roads are skipped
+    /// entirely; broken intersections get a placeholder polygon.
+    pub errors: Vec<Violation>,
 }
 
 pub struct Road {
@@ -65,6 +70,7 @@ impl InitialMap {
         let mut m = InitialMap {
             roads: BTreeMap::new(),
             intersections: BTreeMap::new(),
+            errors: Vec::new(),
         };
 
         for (id, i) in &streets.intersections {
@@ -84,12 +90,30 @@ impl InitialMap {
 
         for (id, road) in &streets.roads {
             let id = *id;
-            // TODO Neither of these should still be happening. If they are, flush out the problem
+            // TODO None of these should still be happening. If they are, flush out the problem
             if id.i1 == id.i2 {
-                panic!("There's a loop {}", id);
+                error!("Skipping loop {}", id);
+                m.errors.push(Violation::Loop { road: id });
+                continue;
+            }
+            if let Err(err) = PolyLine::new(road.osm_center_points.clone()) {
+                error!("Skipping {} with broken geometry: {}", id, err);
+                m.errors.push(Violation::DegenerateGeometry {
+                    road: id,
+                    error: err.to_string(),
+                });
+                continue;
             }
-            if PolyLine::new(road.osm_center_points.clone()).is_err() {
-                panic!("There's broken geom {}", id);
+            if let Some(i) = [id.i1, id.i2]
+                .into_iter()
+                .find(|i| !m.intersections.contains_key(i))
+            {
+                error!("Skipping {}, because {} is missing", id, i);
+                m.errors.push(Violation::MissingIntersection {
+                    road: id,
+                    intersection: i,
+                });
+                continue;
             }
 
             m.intersections.get_mut(&id.i1).unwrap().roads.insert(id);
@@ -120,6 +144,10 @@ impl InitialMap {
                 }
                 Err(err) => {
                     error!("Can't make intersection geometry for {}: {}", i.id, err);
+                    m.errors.push(Violation::IntersectionGeometry {
+                        intersection: i.id,
+                        error: err.to_string(),
+                    });
 
                     // If we haven't removed disconnected roads, we may have dangling nodes around.
                     if let Some(r) = i.roads.iter().next() {
@@ -152,7 +180,10 @@ impl InitialMap {
             if i.control != ControlType::Border {
                 continue;
             }
-            let r = m.roads.get_mut(i.roads.iter().next().unwrap()).unwrap();
+            let r = match i.roads.iter().next() {
+                Some(r) => m.roads.get_mut(r).unwrap(),
+                None => continue,
+            };
             if r.trimmed_center_pts.length() >= min_len {
                 continue;
             }
@@ -172,12 +203,21 @@ impl InitialMap {
                 .iter()
                 .map(|r| m.roads[r].to_input_road())
                 .collect::<Vec<_>>();
-            let results = crate::intersection_polygon(
+            let results = match crate::intersection_polygon(
                 i.id,
                 input_roads,
                 &streets.intersections[&i.id].trim_roads_for_merging,
-            )
-            .unwrap();
+            ) {
+                Ok(results) => results,
+                Err(err) => {
+                    error!("Can't make border geometry for {}: {}", i.id, err);
+                    m.errors.push(Violation::IntersectionGeometry {
+                        intersection: i.id,
+                        error: err.to_string(),
+                    });
+                    continue;
+                }
+            };
             i.polygon = results.intersection_polygon;
             for (r, (pl, _)) in results.trimmed_center_pts {
                 m.roads.get_mut(&r).unwrap().trimmed_center_pts = pl;
@@ -1,17 +1,28 @@
+use std::collections::BTreeSet;
 use std::iter;
 
 use abstutil::Tags;
 use geom::Distance;
 
-use crate::{osm, BufferType, Direction, DrivingSide, LaneSpec, LaneType, MapConfig};
+use crate::{osm, BufferType, Direction, DrivingSide, LaneSpec, LaneType, MapConfig, WidthSource};
 
-/// Purely from OSM tags, determine the lanes that a road segment has. This is the "classic"
-/// implementation -- the default, but on its way out.
+/// Purely from OSM tags, determine the lanes that a road segment has.
 pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
-    if cfg.osm2lanes {
-        return super::osm2lanes::get_lane_specs_ltr_experimental(tags, cfg);
+    let mut lanes = if cfg.osm2lanes {
+        super::osm2lanes::get_lane_specs_ltr_experimental(tags, cfg)
+    } else {
+        get_lane_specs_ltr_classic(tags, cfg)
+    };
+    LaneSpec::apply_default_widths(&mut lanes, cfg);
+    if cfg.lane_widths_from_tags {
+        LaneSpec::apply_width_tags(&mut lanes, tags);
     }
+    LaneSpec::parse_lane_speed_limits(&mut lanes, tags);
+    lanes
+}
 
+/// This is the "classic" implementation -- the default, but on its way out.
+fn get_lane_specs_ltr_classic(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
     // TODO This hides a potentially expensive (on a hot-path) clone
     let mut tags = tags.clone();
     // This'll do weird things for the special cases of railways and cycleways/footways, but the
@@ -23,11 +34,17 @@ pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
         lt,
         dir: Direction::Fwd,
         width: LaneSpec::typical_lane_widths(lt, &tags)[0].0,
+        allowed_turns: BTreeSet::new(),
+        width_source: WidthSource::Default,
+        speed_limit: None,
     };
     let back = |lt: LaneType| LaneSpec {
         lt,
         dir: Direction::Back,
         width: LaneSpec::typical_lane_widths(lt, &tags)[0].0,
+        allowed_turns: BTreeSet::new(),
+        width_source: WidthSource::Default,
+        speed_limit: None,
     };
 
     // Easy special cases first.
@@ -305,7 +322,8 @@ pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
         fwd_side.push(fwd(LaneType::Sidewalk));
         back_side.push(back(LaneType::Sidewalk));
     } else if tags.is(osm::SIDEWALK, "separate") && cfg.inferred_sidewalks {
-        // TODO Need to snap separate sidewalks to ways. Until then, just do this.
+        // Separate sidewalks aren't imported when inferring, so just assume they're there. When
+        // they are imported, Transformation::SnapSidewalks turns them into lanes instead.
         fwd_side.push(fwd(LaneType::Sidewalk));
         if !back_side.is_empty() {
             back_side.push(back(LaneType::Sidewalk));
@@ -331,9 +349,13 @@ pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
         .and_then(|num| num.parse::<f64>().ok())
     {
         if cfg.driving_side == DrivingSide::Right {
-            back_side.last_mut().unwrap().width = Distance::meters(x);
+            let lane = back_side.last_mut().unwrap();
+            lane.width = Distance::meters(x);
+            lane.width_source = WidthSource::Tagged;
         } else {
-            fwd_side.last_mut().unwrap().width = Distance::meters(x);
+            let lane = fwd_side.last_mut().unwrap();
+            lane.width = Distance::meters(x);
+            lane.width_source = WidthSource::Tagged;
         }
     }
     if let Some(x) = tags
@@ -341,9 +363,13 @@ pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
         .and_then(|num| num.parse::<f64>().ok())
     {
         if cfg.driving_side == DrivingSide::Right {
-            fwd_side.last_mut().unwrap().width = Distance::meters(x);
+            let lane = fwd_side.last_mut().unwrap();
+            lane.width = Distance::meters(x);
+            lane.width_source = WidthSource::Tagged;
         } else {
-            back_side.last_mut().unwrap().width = Distance::meters(x);
+            let lane = back_side.last_mut().unwrap();
+            lane.width = Distance::meters(x);
+            lane.width_source = WidthSource::Tagged;
         }
     }
 
@@ -381,13 +407,15 @@ pub fn get_lane_specs_ltr(tags: &Tags, cfg: &MapConfig) -> Vec<LaneSpec> {
         }
     }
 
-    LaneSpec::assemble_ltr(fwd_side, back_side, cfg.driving_side)
+    let mut lanes = LaneSpec::assemble_ltr(fwd_side, back_side, cfg.driving_side);
+    LaneSpec::parse_turn_lanes(&mut lanes, &tags);
+    lanes
 }
 
 // See https://wiki.openstreetmap.org/wiki/Proposed_features/cycleway:separation#Typical_values.
 // Lots of these mappings are pretty wacky right now. We need more BufferTypes.
 #[allow(clippy::ptr_arg)] // Can't chain with `tags.get("foo").and_then` otherwise
-fn osm_separation_type(x: &String) -> Option<BufferType> {
+pub(crate) fn osm_separation_type(x: &String) -> Option<BufferType> {
     match x.as_ref() {
         "bollard" | "vertical_panel" => Some(BufferType::FlexPosts),
         "kerb" | "separation_kerb" => Some(BufferType::Curb),
@@ -426,6 +454,10 @@ pub(crate) fn infer_sidewalk_tags(tags: &mut Tags, cfg: &MapConfig) {
         || tags.is("foot", "no")
         || tags.is(osm::HIGHWAY, "service")
         || tags.is_any(osm::HIGHWAY, vec!["cycleway", "pedestrian", "track"])
+        || tags
+            .get(osm::HIGHWAY)
+            .map(|x| cfg.highways_without_sidewalks.contains(x))
+            .unwrap_or(false)
     {
         tags.insert(osm::SIDEWALK, "none");
     } else if tags.is("oneway", "yes") {
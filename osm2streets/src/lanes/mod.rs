@@ -2,16 +2,20 @@ mod classic;
 mod osm2lanes;
 #[cfg(test)]
 mod tests;
+mod turn_lanes;
+mod widths;
 
+use std::collections::BTreeSet;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
 use abstutil::Tags;
-use geom::Distance;
+use geom::{Distance, Speed};
 
 use crate::{osm, DrivingSide};
 pub use classic::get_lane_specs_ltr;
+pub(crate) use classic::osm_separation_type;
 
 pub const NORMAL_LANE_THICKNESS: Distance = Distance::const_meters(2.5);
 const SERVICE_ROAD_LANE_THICKNESS: Distance = Distance::const_meters(1.5);
@@ -197,11 +201,67 @@ impl LaneType {
     }
 }
 
+/// A direction painted on a lane, from `turn:lanes`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TurnDirection {
+    SharpLeft,
+    Left,
+    SlightLeft,
+    Through,
+    SlightRight,
+    Right,
+    SharpRight,
+    Reverse,
+    MergeToLeft,
+    MergeToRight,
+}
+
+impl TurnDirection {
+    /// Parses one value from `turn:lanes`. `none` and unknown values give `None`.
+    pub fn from_osm(x: &str) -> Option<TurnDirection> {
+        match x {
+            "sharp_left" => Some(TurnDirection::SharpLeft),
+            "left" => Some(TurnDirection::Left),
+            "slight_left" => Some(TurnDirection::SlightLeft),
+            "through" => Some(TurnDirection::Through),
+            "slight_right" => Some(TurnDirection::SlightRight),
+            "right" => Some(TurnDirection::Right),
+            "sharp_right" => Some(TurnDirection::SharpRight),
+            "reverse" => Some(TurnDirection::Reverse),
+            "merge_to_left" => Some(TurnDirection::MergeToLeft),
+            "merge_to_right" => Some(TurnDirection::MergeToRight),
+            _ => None,
+        }
+    }
+}
+
+/// Where a lane's width came from
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum WidthSource {
+    /// A typical width for the type of lane
+    #[default]
+    Default,
+    /// Tagged in OSM for this lane specifically
+    Tagged,
+    /// Stretched or squeezed from a default, so the whole carriageway matches a tagged width
+    Scaled,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LaneSpec {
     pub lt: LaneType,
     pub dir: Direction,
     pub width: Distance,
+    /// Where vehicles in this lane may go at the end of the road. Empty if there are no
+    /// restrictions, or they aren't known.
+    #[serde(default)]
+    pub allowed_turns: BTreeSet<TurnDirection>,
+    #[serde(default)]
+    pub width_source: WidthSource,
+    /// From `maxspeed:lanes`, when this lane's limit differs from the rest of the road. `None`
+    /// means the road's `SpeedLimit` applies.
+    #[serde(default)]
+    pub speed_limit: Option<Speed>,
 }
 
 impl LaneSpec {
@@ -302,6 +362,56 @@ impl LaneSpec {
         }
     }
 
+    /// Reads a tag like `turn:lanes:forward`, which lists the lanes going one way, starting from
+    /// the driver's left. `key` is the tag without the direction suffix; on one-way roads, it's
+    /// used by itself too. Returns the index of each lane with its entry. Bike lanes are only
+    /// included when the tag has an entry for them; otherwise the entries just cover driving and
+    /// bus lanes. Tags with any other number of entries are ignored.
+    pub(crate) fn lanes_per_direction<'a>(
+        lanes_ltr: &[LaneSpec],
+        tags: &'a Tags,
+        key: &str,
+    ) -> Vec<(usize, &'a str)> {
+        let oneway = LaneSpec::oneway_for_driving(lanes_ltr);
+        let mut result = Vec::new();
+        for (dir, suffix) in [(Direction::Fwd, "forward"), (Direction::Back, "backward")] {
+            let value = match tags.get(&format!("{}:{}", key, suffix)) {
+                Some(value) => value,
+                None if oneway == Some(dir) => match tags.get(key) {
+                    Some(value) => value,
+                    None => continue,
+                },
+                None => continue,
+            };
+            let parts: Vec<&str> = value.split('|').collect();
+
+            let mut indices: Vec<usize> = lanes_ltr
+                .iter()
+                .enumerate()
+                .filter(|(_, lane)| {
+                    lane.dir == dir
+                        && matches!(
+                            lane.lt,
+                            LaneType::Driving | LaneType::Bus | LaneType::Biking
+                        )
+                })
+                .map(|(idx, _)| idx)
+                .collect();
+            // lanes_ltr is from the point of view of somebody facing forwards
+            if dir == Direction::Back {
+                indices.reverse();
+            }
+            if parts.len() != indices.len() {
+                indices.retain(|idx| lanes_ltr[*idx].lt != LaneType::Biking);
+            }
+            if parts.len() != indices.len() {
+                continue;
+            }
+            result.extend(indices.into_iter().zip(parts));
+        }
+        result
+    }
+
     /// None if bidirectional. If it's one-way, which direction is that relative to the road?
     /// (Usually forwards)
     pub fn oneway_for_driving(lanes: &[LaneSpec]) -> Option<Direction> {
@@ -1,10 +1,12 @@
+use std::collections::BTreeSet;
+
 use anyhow::Result;
 use osm2lanes::road::Designated;
 
 use abstutil::Tags;
 use geom::Distance;
 
-use crate::{osm, Direction, DrivingSide, LaneSpec, LaneType, MapConfig};
+use crate::{osm, Direction, DrivingSide, LaneSpec, LaneType, MapConfig, WidthSource};
 
 /// Purely from OSM tags, determine the lanes that a road segment has. This is an experimental
 /// implementation that uses https://github.com/a-b-street/osm2lanes for the heavy lifting. Most of
@@ -17,6 +19,9 @@ pub fn get_lane_specs_ltr_experimental(orig_tags: &Tags, cfg: &MapConfig) -> Vec
             lt: LaneType::LightRail,
             dir: Direction::Fwd,
             width: LaneSpec::typical_lane_width(LaneType::LightRail),
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
         }];
     }
 
@@ -39,6 +44,9 @@ pub fn get_lane_specs_ltr_experimental(orig_tags: &Tags, cfg: &MapConfig) -> Vec
                 lt: LaneType::Driving,
                 dir: Direction::Fwd,
                 width: Distance::meters(1.0),
+                allowed_turns: BTreeSet::new(),
+                width_source: WidthSource::Default,
+                speed_limit: None,
             }]
         }
     }
@@ -115,12 +123,15 @@ fn inner_get_lane_specs_ltr(orig_tags: &Tags, cfg: &MapConfig) -> Result<Vec<Lan
     {
         // TODO Make sure this is a sidewalk!
         result[0].width = Distance::meters(x);
+        result[0].width_source = WidthSource::Tagged;
     }
     if let Some(x) = orig_tags
         .get("sidewalk:right:width")
         .and_then(|num| num.parse::<f64>().ok())
     {
-        result.last_mut().unwrap().width = Distance::meters(x);
+        let lane = result.last_mut().unwrap();
+        lane.width = Distance::meters(x);
+        lane.width_source = WidthSource::Tagged;
     }
 
     // osm2streets needs bidirectional lanes like sidewalks to still have a direction. So fix
@@ -144,6 +155,9 @@ fn inner_get_lane_specs_ltr(orig_tags: &Tags, cfg: &MapConfig) -> Result<Vec<Lan
         }
     }
 
+    // osm2lanes doesn't handle turn lanes yet
+    LaneSpec::parse_turn_lanes(&mut result, orig_tags);
+
     Ok(result)
 }
 
@@ -222,7 +236,14 @@ fn transform_lane(
 
     let single_lane = |lt, dir| {
         let width = Distance::meters(lane.width(locale, highway_type).val());
-        Ok(vec![LaneSpec { lt, dir, width }])
+        Ok(vec![LaneSpec {
+            lt,
+            dir,
+            width,
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
+        }])
     };
 
     match lane {
@@ -299,11 +320,17 @@ fn bidirectional_lane(lt: LaneType, total_width: Distance, cfg: &MapConfig) -> V
             lt,
             dir: dir1,
             width: total_width / 2.0,
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
         },
         LaneSpec {
             lt,
             dir: dir2,
             width: total_width / 2.0,
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
         },
     ]
 }
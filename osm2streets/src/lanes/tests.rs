@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
+
 use abstutil::Tags;
 use geom::Distance;
 
-use crate::{get_lane_specs_ltr, Direction, DrivingSide, MapConfig};
+use crate::{
+    get_lane_specs_ltr, Direction, DrivingSide, LaneType, MapConfig, MarkingStyle,
+    SpeedLimitDefaults, TurnDirection, WidthSource,
+};
 
 // osm2lanes has a more extensive unit test suite, so why does this one exist? This also checks the
 // translation from osm2lanes output into osm2streets. This is particularly useful during migration
@@ -173,6 +178,12 @@ fn test_osm_to_specs() {
             turn_on_red: true,
             // Flip this temporarily to work on the new integration
             osm2lanes: false,
+            lane_widths_from_tags: false,
+            default_speed_limits: SpeedLimitDefaults::generic(),
+            country_code: None,
+            default_lane_widths: BTreeMap::new(),
+            highways_without_sidewalks: Vec::new(),
+            marking_style: MarkingStyle::YellowCenterLines,
             find_dog_legs_experiment: false,
             merge_osm_ways: Vec::new(),
         };
@@ -201,6 +212,128 @@ fn test_osm_to_specs() {
     assert!(ok);
 }
 
+#[test]
+fn test_turn_lanes() {
+    use TurnDirection::*;
+
+    let cfg = MapConfig::default_for_side(DrivingSide::Right);
+    // The arrows on each driving lane, from left to right
+    let turns = |input: Vec<&str>| -> Vec<Vec<TurnDirection>> {
+        get_lane_specs_ltr(&tags(input), &cfg)
+            .into_iter()
+            .filter(|lane| lane.lt == LaneType::Driving)
+            .map(|lane| lane.allowed_turns.into_iter().collect())
+            .collect()
+    };
+
+    assert_eq!(
+        turns(vec![
+            "highway=primary",
+            "oneway=yes",
+            "lanes=3",
+            "turn:lanes=left|through|through;right",
+        ]),
+        vec![vec![Left], vec![Through], vec![Through, Right]]
+    );
+    // Backwards lanes are listed from the point of view of somebody driving that way
+    assert_eq!(
+        turns(vec![
+            "highway=primary",
+            "lanes=4",
+            "turn:lanes:forward=left|through",
+            "turn:lanes:backward=left;through|right",
+        ]),
+        vec![vec![Right], vec![Left, Through], vec![Left], vec![Through]]
+    );
+    assert_eq!(
+        turns(vec![
+            "highway=primary",
+            "oneway=yes",
+            "lanes=2",
+            "turn:lanes=none|right",
+        ]),
+        vec![vec![], vec![Right]]
+    );
+    // The wrong number of lanes
+    assert_eq!(
+        turns(vec![
+            "highway=primary",
+            "oneway=yes",
+            "lanes=2",
+            "turn:lanes=left|through|right",
+        ]),
+        vec![Vec::<TurnDirection>::new(), Vec::new()]
+    );
+}
+
+#[test]
+fn test_width_tags() {
+    let mut cfg = MapConfig::default_for_side(DrivingSide::Right);
+    cfg.lane_widths_from_tags = true;
+    // Each lane's type, width in meters, and where the width came from
+    let widths = |input: Vec<&str>| -> Vec<(char, f64, WidthSource)> {
+        get_lane_specs_ltr(&tags(input), &cfg)
+            .into_iter()
+            .map(|lane| {
+                // Round to centimeters
+                let meters = (lane.width.inner_meters() * 100.0).round() / 100.0;
+                (lane.lt.to_char(), meters, lane.width_source)
+            })
+            .collect()
+    };
+
+    use WidthSource::*;
+    assert_eq!(
+        widths(vec![
+            "highway=residential",
+            "sidewalk=both",
+            "sidewalk:left:width=2",
+            "lanes=2",
+            "width:lanes=3|3.5",
+        ]),
+        vec![
+            ('s', 2.0, Tagged),
+            ('d', 3.0, Tagged),
+            ('d', 3.5, Tagged),
+            ('s', 1.5, Default)
+        ]
+    );
+    // Only the total is known, so both lanes are squeezed equally
+    assert_eq!(
+        widths(vec![
+            "highway=residential",
+            "sidewalk=none",
+            "lanes=2",
+            "width=5",
+        ]),
+        vec![('d', 2.5, Scaled), ('d', 2.5, Scaled)]
+    );
+    // The bike lane's width is taken out of the total first
+    assert_eq!(
+        widths(vec![
+            "highway=residential",
+            "sidewalk=none",
+            "oneway=yes",
+            "cycleway:right=lane",
+            "cycleway:right:width=1.5",
+            "width=10'",
+        ]),
+        vec![('d', 1.55, Scaled), ('b', 1.5, Tagged)]
+    );
+
+    // Without the option, the tags are ignored
+    assert_eq!(
+        get_lane_specs_ltr(
+            &tags(vec!["highway=residential", "sidewalk=none", "width=5"]),
+            &MapConfig::default_for_side(DrivingSide::Right)
+        )
+        .into_iter()
+        .map(|lane| lane.width_source)
+        .collect::<Vec<_>>(),
+        vec![Default, Default]
+    );
+}
+
 fn tags(kv: Vec<&str>) -> Tags {
     let mut tags = Tags::empty();
     for pair in kv {
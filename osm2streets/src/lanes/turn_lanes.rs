@@ -0,0 +1,16 @@
+use abstutil::Tags;
+
+use crate::{LaneSpec, TurnDirection};
+
+impl LaneSpec {
+    /// Fills out `allowed_turns` from `turn:lanes`, `turn:lanes:forward`, and
+    /// `turn:lanes:backward`.
+    pub(crate) fn parse_turn_lanes(lanes_ltr: &mut [LaneSpec], tags: &Tags) {
+        for (idx, part) in LaneSpec::lanes_per_direction(lanes_ltr, tags, "turn:lanes") {
+            lanes_ltr[idx].allowed_turns = part
+                .split(';')
+                .filter_map(|x| TurnDirection::from_osm(x.trim()))
+                .collect();
+        }
+    }
+}
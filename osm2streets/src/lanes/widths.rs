@@ -0,0 +1,155 @@
+use abstutil::Tags;
+use geom::Distance;
+
+use crate::{LaneSpec, LaneType, MapConfig, WidthSource};
+
+impl LaneSpec {
+    /// Uses the widths from `MapConfig::default_lane_widths` for lanes that have the typical
+    /// default.
+    pub(crate) fn apply_default_widths(lanes_ltr: &mut [LaneSpec], cfg: &MapConfig) {
+        for lane in lanes_ltr {
+            if let Some(width) = cfg.default_lane_widths.get(&lane.lt) {
+                if lane.width_source == WidthSource::Default
+                    && lane.width == LaneSpec::typical_lane_width(lane.lt)
+                {
+                    lane.width = *width;
+                }
+            }
+        }
+    }
+
+    /// Replaces default widths with the ones tagged in OSM. Individual lanes come from
+    /// `width:lanes` (and its `:forward` and `:backward` variants), `sidewalk:*:width`, and
+    /// `cycleway:*:width`. If the width of the whole carriageway is tagged with
+    /// `width:carriageway`, `width`, or `est_width`, then the other lanes are scaled to fill the
+    /// rest of it. Sidewalks and shoulders aren't part of the carriageway.
+    pub(crate) fn apply_width_tags(lanes_ltr: &mut [LaneSpec], tags: &Tags) {
+        if lanes_ltr.is_empty() {
+            return;
+        }
+        let set = |lane: &mut LaneSpec, width: Distance| {
+            lane.width = width;
+            lane.width_source = WidthSource::Tagged;
+        };
+
+        for (idx, part) in LaneSpec::lanes_per_direction(lanes_ltr, tags, "width:lanes") {
+            if let Some(width) = parse_width(part) {
+                set(&mut lanes_ltr[idx], width);
+            }
+        }
+        let road_lanes: Vec<usize> = lanes_ltr
+            .iter()
+            .enumerate()
+            .filter(|(_, lane)| matches!(lane.lt, LaneType::Driving | LaneType::Bus))
+            .map(|(idx, _)| idx)
+            .collect();
+        // On two-way roads, plain `width:lanes` lists every lane from left to right
+        if LaneSpec::oneway_for_driving(lanes_ltr).is_none() {
+            if let Some(value) = tags.get("width:lanes") {
+                let parts: Vec<&str> = value.split('|').collect();
+                if parts.len() == road_lanes.len() {
+                    for (idx, part) in road_lanes.iter().zip(parts) {
+                        if let Some(width) = parse_width(part) {
+                            set(&mut lanes_ltr[*idx], width);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (idx, side) in [(0, "left"), (lanes_ltr.len() - 1, "right")] {
+            if lanes_ltr[idx].lt != LaneType::Sidewalk {
+                continue;
+            }
+            if let Some(width) = first_width(
+                tags,
+                &[
+                    format!("sidewalk:{}:width", side).as_str(),
+                    "sidewalk:both:width",
+                    "sidewalk:width",
+                ],
+            ) {
+                set(&mut lanes_ltr[idx], width);
+            }
+        }
+
+        // Bike lanes on either side of the road. Cycleways without any driving lanes only have a
+        // total width.
+        if let (Some(first), Some(last)) = (road_lanes.first(), road_lanes.last()) {
+            for (range, side) in [(0..*first, "left"), (*last + 1..lanes_ltr.len(), "right")] {
+                if let Some(width) = first_width(
+                    tags,
+                    &[
+                        format!("cycleway:{}:width", side).as_str(),
+                        "cycleway:both:width",
+                        "cycleway:width",
+                    ],
+                ) {
+                    for lane in &mut lanes_ltr[range] {
+                        if lane.lt == LaneType::Biking {
+                            set(lane, width);
+                        }
+                    }
+                }
+            }
+        }
+
+        let total = match first_width(tags, &["width:carriageway", "width", "est_width"]) {
+            Some(total) => total,
+            None => return,
+        };
+        let in_carriageway =
+            |lane: &LaneSpec| !matches!(lane.lt, LaneType::Sidewalk | LaneType::Shoulder);
+        let mut tagged = Distance::ZERO;
+        let mut untagged = Distance::ZERO;
+        for lane in lanes_ltr.iter().filter(|lane| in_carriageway(lane)) {
+            if lane.width_source == WidthSource::Tagged {
+                tagged += lane.width;
+            } else {
+                untagged += lane.width;
+            }
+        }
+        // If the tagged lanes already take up the whole width, the tags disagree. Trust the
+        // individual lanes.
+        if untagged == Distance::ZERO || total <= tagged {
+            return;
+        }
+        let factor = (total - tagged).inner_meters() / untagged.inner_meters();
+        for lane in lanes_ltr.iter_mut() {
+            if in_carriageway(lane) && lane.width_source != WidthSource::Tagged {
+                lane.width *= factor;
+                lane.width_source = WidthSource::Scaled;
+            }
+        }
+    }
+}
+
+// The first of these keys with a valid width
+fn first_width(tags: &Tags, keys: &[&str]) -> Option<Distance> {
+    keys.iter()
+        .find_map(|key| tags.get(key).and_then(|value| parse_width(value)))
+}
+
+/// Parses widths like `3.5`, `3.5 m`, `12 ft`, or `12'6"`. Values without units are in meters.
+fn parse_width(value: &str) -> Option<Distance> {
+    let value = value.trim().replace(',', ".");
+    let width = if let Some(feet) = value.strip_suffix("ft") {
+        Distance::feet(feet.trim().parse().ok()?)
+    } else if let Some((feet, inches)) = value.split_once('\'') {
+        let inches = inches.trim_end_matches('"').trim();
+        let inches = if inches.is_empty() {
+            0.0
+        } else {
+            inches.parse::<f64>().ok()?
+        };
+        Distance::feet(feet.trim().parse::<f64>().ok()? + inches / 12.0)
+    } else {
+        let meters = value.strip_suffix('m').unwrap_or(value.as_str());
+        Distance::meters(meters.trim().parse().ok()?)
+    };
+    if width > Distance::ZERO {
+        Some(width)
+    } else {
+        None
+    }
+}
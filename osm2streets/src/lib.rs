@@ -13,25 +13,55 @@ use serde::{Deserialize, Serialize};
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags};
 use geom::{Angle, Distance, GPSBounds, PolyLine, Polygon, Pt2D};
 
+pub use self::areas::{Area, AreaType, Frontage};
+pub use self::control::{ApproachControl, ApproachSnapshot};
 pub use self::geometry::{intersection_polygon, InputRoad};
 pub use self::lanes::{
-    get_lane_specs_ltr, BufferType, Direction, LaneSpec, LaneType, NORMAL_LANE_THICKNESS,
-    SIDEWALK_THICKNESS,
+    get_lane_specs_ltr, BufferType, Direction, LaneSpec, LaneType, TurnDirection, WidthSource,
+    NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
 };
+pub use self::provenance::{ProvenanceLog, TransformationRecord};
+pub use self::regions::RegionProfile;
+pub use self::restrictions::{
+    parse_turn_restrictions, RestrictionConditions, RestrictionSnapshot, TimeWindow, VehicleType,
+    Weekday,
+};
+pub use self::routing::{RoutingEdge, RoutingGraph, TravelMode};
+pub use self::speed::{SpeedLimit, SpeedLimitDefaults, SpeedLimitSource};
 pub use self::transform::Transformation;
+pub use self::transit::{
+    PublicTransportStop, SnappedStop, StopPlacement, StopType, TransitKind, TransitMatcher,
+    TransitNetwork, TransitRoute, TransitStop,
+};
+pub use self::turns::{LaneID, Turn, TurnType};
 pub use self::types::{
-    ControlType, DrivingSide, IntersectionComplexity, MapConfig, NamePerLanguage,
+    ControlType, DrivingSide, IntersectionComplexity, MapConfig, MarkingStyle, NamePerLanguage,
 };
+pub use self::validate::Violation;
 
+mod areas;
+mod control;
 mod edit;
 mod geometry;
+#[cfg(not(target_arch = "wasm32"))]
+mod gpkg;
 pub mod initial;
 mod lanes;
 pub mod osm;
 mod pathfinding;
+mod provenance;
+mod regions;
 mod render;
+mod restrictions;
+mod routing;
+mod speed;
+#[cfg(test)]
+mod test_utils;
 mod transform;
+mod transit;
+mod turns;
 mod types;
+mod validate;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StreetNetwork {
@@ -49,9 +79,18 @@ pub struct StreetNetwork {
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
     pub config: MapConfig,
+    /// Public transit stops and routes, if they were imported
+    #[serde(default)]
+    pub transit: TransitNetwork,
+    /// Buildings, parking lots, and landuse, if they were imported
+    #[serde(default)]
+    pub areas: Vec<Area>,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub debug_steps: RefCell<Vec<DebugStreets>>,
+    /// If this is set, `apply_transformations` records what each step changes here.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub provenance: Option<ProvenanceLog>,
 }
 
 #[derive(Clone, Debug)]
@@ -67,7 +106,8 @@ pub struct DebugStreets {
 }
 
 /// A way to refer to roads across many maps and over time. Also trivial to relate with OSM to find
-/// upstream problems.
+/// upstream problems. Transformations change the IDs of roads they merge; use
+/// `Road::original_segments` to track them.
 //
 // - Using LonLat is more indirect, and f64's need to be trimmed and compared carefully with epsilon
 //   checks.
@@ -124,23 +164,39 @@ impl OriginalRoad {
 
     // TODO Doesn't handle two roads between the same pair of intersections
     pub fn common_endpt(&self, other: OriginalRoad) -> osm::NodeID {
+        match self.maybe_common_endpt(other) {
+            Some(i) => i,
+            None => panic!("{:?} and {:?} have no common_endpt", self, other),
+        }
+    }
+
+    /// Like `common_endpt`, but returns `None` if the roads don't meet.
+    pub fn maybe_common_endpt(&self, other: OriginalRoad) -> Option<osm::NodeID> {
         #![allow(clippy::suspicious_operation_groupings)]
         if self.i1 == other.i1 || self.i1 == other.i2 {
-            return self.i1;
+            return Some(self.i1);
         }
         if self.i2 == other.i1 || self.i2 == other.i2 {
-            return self.i2;
+            return Some(self.i2);
         }
-        panic!("{:?} and {:?} have no common_endpt", self, other);
+        None
     }
 
     pub fn other_side(&self, i: osm::NodeID) -> osm::NodeID {
+        match self.maybe_other_side(i) {
+            Some(other) => other,
+            None => panic!("{} doesn't have {} on either side", self, i),
+        }
+    }
+
+    /// Like `other_side`, but returns `None` if the road doesn't touch `i`.
+    pub fn maybe_other_side(&self, i: osm::NodeID) -> Option<osm::NodeID> {
         if self.i1 == i {
-            self.i2
+            Some(self.i2)
         } else if self.i2 == i {
-            self.i1
+            Some(self.i1)
         } else {
-            panic!("{} doesn't have {} on either side", self, i);
+            None
         }
     }
 }
@@ -154,12 +210,19 @@ impl StreetNetwork {
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
             config: MapConfig::default_for_side(DrivingSide::Right),
+            transit: TransitNetwork::default(),
+            areas: Vec::new(),
 
             debug_steps: RefCell::new(Vec::new()),
+            provenance: None,
         }
     }
 
-    pub fn insert_road(&mut self, id: OriginalRoad, road: Road) {
+    /// If the road doesn't have any `original_segments` yet, `id` is recorded as the only one.
+    pub fn insert_road(&mut self, id: OriginalRoad, mut road: Road) {
+        if road.original_segments.is_empty() {
+            road.original_segments.push(id);
+        }
         self.roads.insert(id, road);
         for i in [id.i1, id.i2] {
             self.intersections.get_mut(&i).unwrap().roads.push(id);
@@ -194,6 +257,34 @@ impl StreetNetwork {
         }
     }
 
+    /// Find the road that an original OSM way segment (identified by its ID before any
+    /// transformations) has become part of. Returns `None` if the segment was removed or merged
+    /// into an intersection.
+    pub fn road_for_original_segment(&self, segment: OriginalRoad) -> Option<OriginalRoad> {
+        if let Some(road) = self.roads.get(&segment) {
+            if road.original_segments.contains(&segment) {
+                return Some(segment);
+            }
+        }
+        self.roads
+            .iter()
+            .find(|(_, road)| road.original_segments.contains(&segment))
+            .map(|(id, _)| *id)
+    }
+
+    /// Maps every original OSM way segment to the road it's now part of. Use this to carry
+    /// annotations keyed by `OriginalRoad` from one import (or a network before transformations)
+    /// over to the current roads.
+    pub fn original_segments_to_roads(&self) -> BTreeMap<OriginalRoad, OriginalRoad> {
+        let mut result = BTreeMap::new();
+        for (id, road) in &self.roads {
+            for segment in &road.original_segments {
+                result.insert(*segment, *id);
+            }
+        }
+        result
+    }
+
     // This always returns roads oriented in clockwise order around the intersection
     // TODO Consider not cloning. Many callers will have to change
     pub fn roads_per_intersection(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
@@ -273,7 +364,10 @@ impl StreetNetwork {
                 boundary_polygon: self.boundary_polygon.clone(),
                 gps_bounds: self.gps_bounds.clone(),
                 config: self.config.clone(),
+                transit: self.transit.clone(),
+                areas: self.areas.clone(),
                 debug_steps: RefCell::new(Vec::new()),
+                provenance: None,
             },
             points: Vec::new(),
             polylines: Vec::new(),
@@ -304,11 +398,26 @@ impl StreetNetwork {
         }
     }
 
-    // Restore the invariant that an intersection's roads are ordered clockwise
+    // Restore the invariant that an intersection's roads are ordered clockwise. If that fails, the
+    // old order is kept, and `validate` reports `Violation::CantSortRoads`.
     //
     // TODO This doesn't handle trim_roads_for_merging
     fn sort_roads(&mut self, i: osm::NodeID) {
-        let intersection = self.intersections.get_mut(&i).unwrap();
+        match self.calculate_sorted_roads(i) {
+            Ok(roads) => {
+                self.intersections.get_mut(&i).unwrap().roads = roads;
+            }
+            Err(err) => {
+                error!("Can't sort the roads of {}: {}", i, err);
+            }
+        }
+    }
+
+    pub(crate) fn calculate_sorted_roads(&self, i: osm::NodeID) -> Result<Vec<OriginalRoad>> {
+        let intersection = &self.intersections[&i];
+        if intersection.roads.is_empty() {
+            return Ok(Vec::new());
+        }
 
         // (ID, polyline pointing to the intersection, sorting point that's filled out later)
         let mut road_centers = Vec::new();
@@ -318,11 +427,11 @@ impl StreetNetwork {
             // road.center_pts is unadjusted; it doesn't handle unequal widths yet. But that
             // shouldn't matter for sorting.
             let center_pl = if r.i1 == i {
-                PolyLine::must_new(road.osm_center_points.clone()).reversed()
+                PolyLine::new(road.osm_center_points.clone())?.reversed()
             } else if r.i2 == i {
-                PolyLine::must_new(road.osm_center_points.clone())
+                PolyLine::new(road.osm_center_points.clone())?
             } else {
-                panic!("Incident road {r} doesn't have an endpoint at {i}");
+                bail!("Incident road {r} doesn't have an endpoint at {i}");
             };
             endpoints_for_center.push(center_pl.last_pt());
 
@@ -355,7 +464,7 @@ impl StreetNetwork {
                 .normalized_degrees() as i64
         });
 
-        intersection.roads = road_centers.into_iter().map(|(r, _, _)| r).collect();
+        Ok(road_centers.into_iter().map(|(r, _, _)| r).collect())
     }
 }
 
@@ -410,9 +519,12 @@ pub struct Road {
     /// happened.
     pub osm_center_points: Vec<Pt2D>,
     pub osm_tags: Tags,
-    pub turn_restrictions: Vec<(RestrictionType, OriginalRoad)>,
-    /// (via, to). For turn restrictions where 'via' is an entire road. Only BanTurns.
-    pub complicated_turn_restrictions: Vec<(OriginalRoad, OriginalRoad)>,
+    pub turn_restrictions: Vec<(RestrictionType, OriginalRoad, RestrictionConditions)>,
+    /// (via, to, conditions). For turn restrictions where 'via' is an entire road. Only BanTurns.
+    pub complicated_turn_restrictions: Vec<(OriginalRoad, OriginalRoad, RestrictionConditions)>,
+    /// The grade from i1 to i2, so 0.05 means a 5% uphill. Calculated from elevation data during
+    /// import. When a degenerate intersection is collapsed, the grades of the two roads are
+    /// averaged by length.
     pub percent_incline: f64,
     /// Is there a tagged crosswalk near each end of the road?
     pub crosswalk_forward: bool,
@@ -423,10 +535,24 @@ pub struct Road {
     /// Barrier nodes along this road's original center line.
     pub barrier_nodes: Vec<Pt2D>,
     /// Crossing nodes along this road's original center line.
-    pub crossing_nodes: Vec<(Pt2D, CrossingType)>,
+    pub crossing_nodes: Vec<(osm::NodeID, Pt2D, CrossingType)>,
+    /// Bus stops, platforms, and tram stops serving this road, mapped in OSM
+    #[serde(default)]
+    pub public_transport_stops: Vec<PublicTransportStop>,
 
     /// Derived from osm_tags. Not automatically updated.
     pub lane_specs_ltr: Vec<LaneSpec>,
+    /// Derived from osm_tags. Not automatically updated. When a degenerate intersection is
+    /// collapsed, the lower limit of the two roads in each direction is kept.
+    #[serde(default)]
+    pub speed_limit: SpeedLimit,
+
+    /// The OSM way segments, as originally split at intersections, that make up this road. The ID
+    /// of a road changes as transformations merge things, but these stay stable across
+    /// transformations and re-imports of the same OSM data. Ordered from `i1` to `i2` when
+    /// possible. Empty for roads saved before this was tracked.
+    #[serde(default)]
+    pub original_segments: Vec<OriginalRoad>,
 }
 
 impl Road {
@@ -436,6 +562,7 @@ impl Road {
         let _ = PolyLine::new(osm_center_points.clone())?;
 
         let lane_specs_ltr = get_lane_specs_ltr(&osm_tags, config);
+        let speed_limit = SpeedLimit::from_tags(&osm_tags, config);
 
         Ok(Self {
             osm_center_points,
@@ -449,8 +576,12 @@ impl Road {
             crosswalk_backward: true,
             barrier_nodes: Vec::new(),
             crossing_nodes: Vec::new(),
+            public_transport_stops: Vec::new(),
 
             lane_specs_ltr,
+            speed_limit,
+            // Filled out by insert_road
+            original_segments: Vec::new(),
         })
     }
 
@@ -479,6 +610,10 @@ impl Road {
         self.osm_tags.is(osm::HIGHWAY, "service")
     }
 
+    pub fn rank(&self) -> osm::RoadRank {
+        osm::RoadRank::from_highway(self.osm_tags.get(osm::HIGHWAY).map_or("", |x| x.as_str()))
+    }
+
     pub fn is_cycleway(&self) -> bool {
         // Don't repeat the logic looking at the tags, just see what lanes we'll create
         let mut bike = false;
@@ -616,6 +751,9 @@ pub enum CrossingType {
     Signalized,
     /// Not part of a traffic signal
     Unsignalized,
+    /// Nothing is painted on the road. This includes `crossing=no`, where people aren't meant to
+    /// cross.
+    Unmarked,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -625,6 +763,14 @@ pub struct Intersection {
     pub point: Pt2D,
     pub complexity: IntersectionComplexity,
     pub control: ControlType,
+    /// How traffic entering from each road is controlled, if known. Roads without an entry may
+    /// still be controlled by `control`.
+    #[serde(
+        default,
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub approach_controls: BTreeMap<OriginalRoad, ApproachControl>,
     pub elevation: Distance,
 
     /// All roads connected to this intersection. They may be incoming or outgoing relative to this
@@ -641,6 +787,7 @@ impl Intersection {
             point,
             complexity,
             control,
+            approach_controls: BTreeMap::new(),
             // Filled out later
             roads: Vec::new(),
             elevation: Distance::ZERO,
@@ -664,8 +811,8 @@ pub struct TurnRestriction(pub OriginalRoad, pub RestrictionType, pub OriginalRo
 
 impl RestrictionType {
     pub fn new(restriction: &str) -> Option<RestrictionType> {
-        // TODO There's a huge space of things not represented yet: time conditions, bus-only, no
-        // right turn on red...
+        // Who and when the restriction applies to is handled by RestrictionConditions. TODO No
+        // right turn on red isn't represented yet.
 
         // There are so many possibilities:
         // https://taginfo.openstreetmap.org/keys/restriction#values
@@ -1,7 +1,8 @@
-use geom::Distance;
+use geom::{Distance, Duration};
 use petgraph::graphmap::DiGraphMap;
 
-use crate::{osm, Direction, LaneType, OriginalRoad, StreetNetwork};
+use crate::routing::travel_speed;
+use crate::{osm, Direction, LaneType, OriginalRoad, StreetNetwork, TravelMode};
 
 // A/B Street's map_model has lots of pathfinding support at both a road segment and lane level.
 // This is a delibrately simple subset of functionality for now.
@@ -25,8 +26,25 @@ impl StreetNetwork {
         .cloned()
     }
 
-    /// Calculates a driving path between intersections. The result says which direction to cross
-    /// each road.
+    /// Calculates a rough driving time between intersections at the speed limit, excluding the
+    /// turning movement through intersections.
+    pub fn path_time_to(&self, from: osm::NodeID, to: osm::NodeID) -> Option<Duration> {
+        let mut graph = DiGraphMap::new();
+        for (id, r) in &self.roads {
+            graph.add_edge(id.i1, id.i2, (*id, Direction::Fwd));
+            if r.oneway_for_driving().is_none() {
+                graph.add_edge(id.i2, id.i1, (*id, Direction::Back));
+            }
+        }
+        petgraph::algo::dijkstra(&graph, from, Some(to), |(_, _, (r, dir))| {
+            self.driving_time(*r, *dir)
+        })
+        .get(&to)
+        .map(|seconds| Duration::seconds(*seconds))
+    }
+
+    /// Calculates the quickest driving path between intersections at the speed limit. The result
+    /// says which direction to cross each road.
     pub fn simple_path(
         &self,
         from: osm::NodeID,
@@ -57,9 +75,8 @@ impl StreetNetwork {
             &graph,
             from,
             |i| i == to,
-            // TODO Expensive!
-            |(_, _, (r, _))| self.roads[r].length(),
-            |_| Distance::ZERO,
+            |(_, _, (r, dir))| self.driving_time(*r, *dir),
+            |_| 0.0,
         )?;
         let roads: Vec<(OriginalRoad, Direction)> = path
             .windows(2)
@@ -67,4 +84,48 @@ impl StreetNetwork {
             .collect();
         Some(roads)
     }
+
+    // Seconds to drive along a road in one direction at the speed limit
+    fn driving_time(&self, r: OriginalRoad, dir: Direction) -> f64 {
+        let road = &self.roads[&r];
+        // TODO Expensive!
+        (road.length() / travel_speed(self, road, dir, TravelMode::Driving)).inner_seconds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::Speed;
+
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+
+    #[test]
+    fn test_prefer_faster_roads() {
+        // A slow direct road from 1 to 3, and a longer, faster one through 2
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 100.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        let direct = add_road(&mut streets, 10, 1, 3, &[("maxspeed", "10")]);
+        let r1 = add_road(&mut streets, 11, 1, 2, &[("maxspeed", "50")]);
+        let r2 = add_road(&mut streets, 12, 3, 2, &[("maxspeed", "50")]);
+
+        let from = osm::NodeID(1);
+        let to = osm::NodeID(3);
+        assert_eq!(
+            streets.simple_path(from, to, &[LaneType::Driving]),
+            Some(vec![(r1, Direction::Fwd), (r2, Direction::Back)])
+        );
+        let time = streets.path_time_to(from, to).unwrap();
+        let expected = streets.roads[&r1].length() / Speed::km_per_hour(50.0)
+            + streets.roads[&r2].length() / Speed::km_per_hour(50.0);
+        assert!((time.inner_seconds() - expected.inner_seconds()).abs() < 0.01);
+
+        // The shortest path is still the direct road
+        let dist = streets.path_dist_to(from, to).unwrap();
+        assert!(
+            (dist.inner_meters() - streets.roads[&direct].length().inner_meters()).abs() < 0.01
+        );
+    }
 }
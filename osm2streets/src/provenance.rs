@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{osm, OriginalRoad, StreetNetwork};
+
+/// A record of what each `Transformation` did to a `StreetNetwork`. Set
+/// `StreetNetwork::provenance` to `Some(ProvenanceLog::new())` before applying transformations to
+/// fill this out.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProvenanceLog {
+    pub steps: Vec<TransformationRecord>,
+}
+
+/// What one `Transformation` changed. Roads and intersections that exist before and after
+/// with the same ID and without absorbing anything else aren't mentioned.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransformationRecord {
+    pub transformation: String,
+    pub removed_roads: Vec<OriginalRoad>,
+    pub created_roads: Vec<OriginalRoad>,
+    /// For every road that was created or absorbed other roads, the roads existing before this
+    /// step that it came from. Roads just renamed by the step have exactly one source.
+    pub road_sources: BTreeMap<OriginalRoad, Vec<OriginalRoad>>,
+    pub removed_intersections: Vec<osm::NodeID>,
+    pub created_intersections: Vec<osm::NodeID>,
+    /// Removed intersections that were merged into a surviving intersection, because a short road
+    /// between them was removed.
+    pub merged_intersections: BTreeMap<osm::NodeID, osm::NodeID>,
+}
+
+impl ProvenanceLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Describe everything that happened to the roads made from one OSM way, in order.
+    pub fn explain_way(&self, way: osm::WayID) -> Vec<String> {
+        let mut result = Vec::new();
+        for step in &self.steps {
+            for r in &step.removed_roads {
+                if r.osm_way_id != way {
+                    continue;
+                }
+                let successors: Vec<String> = step
+                    .road_sources
+                    .iter()
+                    .filter(|(_, sources)| sources.contains(r))
+                    .map(|(new, _)| new.to_string())
+                    .collect();
+                if successors.is_empty() {
+                    result.push(format!("{}: removed {}", step.transformation, r));
+                } else {
+                    result.push(format!(
+                        "{}: {} became part of {}",
+                        step.transformation,
+                        r,
+                        successors.join(", ")
+                    ));
+                }
+            }
+            for (new, sources) in &step.road_sources {
+                if new.osm_way_id == way && sources.iter().any(|r| *r != *new) {
+                    let sources: Vec<String> = sources.iter().map(|r| r.to_string()).collect();
+                    result.push(format!(
+                        "{}: {} made from {}",
+                        step.transformation,
+                        new,
+                        sources.join(", ")
+                    ));
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The IDs in a `StreetNetwork` just before a transformation, to compare against afterwards
+pub(crate) struct Snapshot {
+    // Road ID to original segments
+    roads: BTreeMap<OriginalRoad, Vec<OriginalRoad>>,
+    intersections: BTreeSet<osm::NodeID>,
+}
+
+impl Snapshot {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        Self {
+            roads: streets
+                .roads
+                .iter()
+                .map(|(id, road)| (*id, road.original_segments.clone()))
+                .collect(),
+            intersections: streets.intersections.keys().cloned().collect(),
+        }
+    }
+
+    /// The road before this snapshot that each original segment belonged to
+    fn segment_owners(&self) -> BTreeMap<OriginalRoad, OriginalRoad> {
+        let mut owners = BTreeMap::new();
+        for (id, segments) in &self.roads {
+            for segment in segments {
+                owners.insert(*segment, *id);
+            }
+        }
+        owners
+    }
+
+    pub fn diff(&self, transformation: &str, streets: &StreetNetwork) -> TransformationRecord {
+        let owners = self.segment_owners();
+
+        let mut road_sources = BTreeMap::new();
+        for (id, road) in &streets.roads {
+            let sources: BTreeSet<OriginalRoad> = road
+                .original_segments
+                .iter()
+                .filter_map(|segment| owners.get(segment).cloned())
+                .collect();
+            let unchanged =
+                sources.len() == 1 && sources.contains(id) && self.roads.contains_key(id);
+            if !unchanged {
+                road_sources.insert(*id, sources.into_iter().collect());
+            }
+        }
+
+        let removed_roads: Vec<OriginalRoad> = self
+            .roads
+            .keys()
+            .filter(|id| !streets.roads.contains_key(id))
+            .cloned()
+            .collect();
+        let created_roads = streets
+            .roads
+            .keys()
+            .filter(|id| !self.roads.contains_key(id))
+            .cloned()
+            .collect();
+        let removed_intersections: Vec<osm::NodeID> = self
+            .intersections
+            .iter()
+            .filter(|i| !streets.intersections.contains_key(i))
+            .cloned()
+            .collect();
+        let created_intersections = streets
+            .intersections
+            .keys()
+            .filter(|i| !self.intersections.contains(i))
+            .cloned()
+            .collect();
+
+        // A removed intersection was merged into a surviving one if a road between them was
+        // removed, and some other road that used to touch the removed intersection now touches
+        // the surviving one.
+        let mut merged_intersections = BTreeMap::new();
+        for i in &removed_intersections {
+            for (new_id, sources) in &road_sources {
+                for old_id in sources {
+                    if old_id.i1 != *i && old_id.i2 != *i {
+                        continue;
+                    }
+                    for candidate in [new_id.i1, new_id.i2] {
+                        if candidate == old_id.i1
+                            || candidate == old_id.i2
+                            || !streets.intersections.contains_key(&candidate)
+                        {
+                            continue;
+                        }
+                        let connects = |r: &OriginalRoad| {
+                            (r.i1 == *i && r.i2 == candidate) || (r.i2 == *i && r.i1 == candidate)
+                        };
+                        // If the road between them became part of another road, then the
+                        // intersection was collapsed, not merged
+                        if removed_roads.iter().any(connects)
+                            && !road_sources.values().flatten().any(connects)
+                        {
+                            merged_intersections.insert(*i, candidate);
+                        }
+                    }
+                }
+            }
+        }
+
+        TransformationRecord {
+            transformation: transformation.to_string(),
+            removed_roads,
+            created_roads,
+            road_sources,
+            removed_intersections,
+            created_intersections,
+            merged_intersections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+    use crate::Transformation;
+
+    #[test]
+    fn test_explain_collapse() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 120.0, 0.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        let r1 = add_road(&mut streets, 10, 1, 2, &[]);
+        let r2 = add_road(&mut streets, 11, 2, 3, &[]);
+
+        streets.provenance = Some(ProvenanceLog::new());
+        streets.apply_transformations(
+            vec![Transformation::CollapseDegenerateIntersections],
+            &mut Timer::throwaway(),
+        );
+        let log = streets.provenance.take().unwrap();
+        assert_eq!(log.steps.len(), 1);
+        let step = &log.steps[0];
+        let merged = OriginalRoad::new(10, (1, 3));
+        assert_eq!(step.removed_roads, vec![r1, r2]);
+        assert_eq!(step.created_roads, vec![merged]);
+        assert_eq!(step.road_sources[&merged], vec![r1, r2]);
+        assert_eq!(step.removed_intersections, vec![osm::NodeID(2)]);
+        // The intersection disappeared because the roads were joined, not because a road was
+        // shrunk to nothing
+        assert!(step.merged_intersections.is_empty());
+
+        let name = &step.transformation;
+        assert_eq!(
+            log.explain_way(osm::WayID(10)),
+            vec![
+                format!("{}: {} became part of {}", name, r1, merged),
+                format!("{}: {} made from {}, {}", name, merged, r1, r2),
+            ]
+        );
+        assert_eq!(
+            log.explain_way(osm::WayID(11)),
+            vec![format!("{}: {} became part of {}", name, r2, merged)]
+        );
+    }
+
+    #[test]
+    fn test_merged_intersection() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 5.0, 0.0);
+        add_intersection(&mut streets, 3, 100.0, 0.0);
+        add_intersection(&mut streets, 4, -100.0, 0.0);
+        let short = add_road(&mut streets, 10, 1, 2, &[("junction", "intersection")]);
+        let long = add_road(&mut streets, 20, 2, 3, &[]);
+        add_road(&mut streets, 21, 4, 1, &[]);
+
+        // Merging the short road keeps 1 and attaches everything at 2 to it
+        streets.provenance = Some(ProvenanceLog::new());
+        streets.apply_transformations(
+            vec![Transformation::MergeShortRoads],
+            &mut Timer::throwaway(),
+        );
+        let log = streets.provenance.take().unwrap();
+        assert_eq!(log.steps.len(), 1);
+        let record = &log.steps[0];
+        let moved = OriginalRoad::new(20, (1, 3));
+        assert_eq!(record.removed_roads, vec![short, long]);
+        assert_eq!(record.created_roads, vec![moved]);
+        assert_eq!(record.road_sources[&moved], vec![long]);
+        assert_eq!(
+            record.merged_intersections,
+            BTreeMap::from([(osm::NodeID(2), osm::NodeID(1))])
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use geom::{Distance, GPSBounds, LonLat};
+
+use crate::{DrivingSide, LaneType, MapConfig, MarkingStyle, SpeedLimitDefaults};
+
+/// Regional conventions for one country. These fill in the parts of `MapConfig` that depend on
+/// where the map is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionProfile {
+    /// An ISO 3166-1 alpha-2 code, like "GB"
+    pub country_code: String,
+    pub driving_side: DrivingSide,
+    pub turn_on_red: bool,
+    pub street_parking_spot_length: Distance,
+    pub marking_style: MarkingStyle,
+    /// Overrides for the typical width of some lane types
+    pub lane_widths: BTreeMap<LaneType, Distance>,
+    /// `highway` types that usually don't have sidewalks when nothing is tagged
+    pub highways_without_sidewalks: Vec<String>,
+    pub speed_limits: SpeedLimitDefaults,
+}
+
+impl RegionProfile {
+    /// The profile for an ISO 3166-1 alpha-2 country code. Returns `None` for countries that
+    /// aren't covered by `lookup`.
+    pub fn for_country(code: &str) -> Option<Self> {
+        let code = code.to_ascii_uppercase();
+        if !COUNTRIES.contains(&code.as_str()) {
+            return None;
+        }
+        let code = code.as_str();
+
+        let driving_side = if matches!(
+            code,
+            "GB" | "IE" | "AU" | "NZ" | "JP" | "HK" | "SG" | "IN" | "ZA"
+        ) {
+            DrivingSide::Left
+        } else {
+            DrivingSide::Right
+        };
+        let north_america = matches!(code, "US" | "CA" | "MX");
+        let street_parking_spot_length = if north_america || matches!(code, "AU" | "NZ" | "ZA") {
+            Distance::meters(8.0)
+        } else {
+            Distance::meters(6.0)
+        };
+        let marking_style = if north_america {
+            MarkingStyle::YellowCenterLines
+        } else {
+            MarkingStyle::WhiteCenterLines
+        };
+        // Outside North America, lanes are usually a bit narrower
+        let mut lane_widths = BTreeMap::new();
+        if !north_america {
+            lane_widths.insert(LaneType::Driving, Distance::meters(3.0));
+            lane_widths.insert(LaneType::Bus, Distance::meters(3.25));
+            lane_widths.insert(LaneType::Parking, Distance::meters(2.0));
+        }
+        // Minor rural roads in continental Europe rarely have sidewalks
+        let highways_without_sidewalks = if matches!(
+            code,
+            "NL" | "BE" | "CH" | "AT" | "DK" | "CZ" | "PT" | "DE" | "PL" | "FR" | "IT" | "ES"
+        ) {
+            vec!["unclassified".to_string()]
+        } else {
+            Vec::new()
+        };
+
+        Some(Self {
+            country_code: code.to_string(),
+            driving_side,
+            turn_on_red: matches!(code, "US" | "CA"),
+            street_parking_spot_length,
+            marking_style,
+            lane_widths,
+            highways_without_sidewalks,
+            speed_limits: SpeedLimitDefaults::for_country(code)
+                .unwrap_or_else(SpeedLimitDefaults::generic),
+        })
+    }
+
+    /// Finds the country containing a point. This uses coarse, built-in boundaries, so it
+    /// doesn't need network access, but points near borders may be misjudged.
+    pub fn lookup(pt: LonLat) -> Option<Self> {
+        let code = country_at(pt)?;
+        Self::for_country(&code)
+    }
+
+    /// Finds the country containing the center of a map.
+    pub fn for_gps_bounds(gps_bounds: &GPSBounds) -> Option<Self> {
+        Self::lookup(LonLat::new(
+            (gps_bounds.min_lon + gps_bounds.max_lon) / 2.0,
+            (gps_bounds.min_lat + gps_bounds.max_lat) / 2.0,
+        ))
+    }
+
+    /// Overwrites the regional settings in `cfg`. Everything else is left alone.
+    pub fn apply(&self, cfg: &mut MapConfig) {
+        cfg.country_code = Some(self.country_code.clone());
+        cfg.driving_side = self.driving_side;
+        cfg.turn_on_red = self.turn_on_red;
+        cfg.street_parking_spot_length = self.street_parking_spot_length;
+        cfg.marking_style = self.marking_style;
+        cfg.default_lane_widths = self.lane_widths.clone();
+        cfg.highways_without_sidewalks = self.highways_without_sidewalks.clone();
+        cfg.default_speed_limits = self.speed_limits.clone();
+    }
+}
+
+impl MapConfig {
+    /// The default config, adjusted for a region
+    pub fn for_region(profile: &RegionProfile) -> Self {
+        let mut cfg = Self::default_for_side(profile.driving_side);
+        profile.apply(&mut cfg);
+        cfg
+    }
+}
+
+// Every country in boundaries.geojson
+const COUNTRIES: [&str; 25] = [
+    "GB", "IE", "NL", "BE", "CH", "AT", "DK", "CZ", "PT", "DE", "PL", "FR", "IT", "ES", "US", "CA",
+    "MX", "AU", "NZ", "JP", "TW", "HK", "SG", "IN", "ZA",
+];
+
+// Boundaries are checked in order, so where the simplified outlines overlap, the first one wins.
+fn country_at(pt: LonLat) -> Option<String> {
+    let geojson: geojson::GeoJson = match include_str!("boundaries.geojson").parse() {
+        Ok(geojson) => geojson,
+        Err(err) => {
+            error!("Can't parse country boundaries: {}", err);
+            return None;
+        }
+    };
+    let collection = match geojson {
+        geojson::GeoJson::FeatureCollection(collection) => collection,
+        _ => return None,
+    };
+    let pt = [pt.x(), pt.y()];
+    for feature in collection.features {
+        let polygons = match feature.geometry.map(|geometry| geometry.value) {
+            Some(geojson::Value::Polygon(rings)) => vec![rings],
+            Some(geojson::Value::MultiPolygon(polygons)) => polygons,
+            _ => continue,
+        };
+        // Holes are ignored; none of the outlines have any
+        if polygons
+            .iter()
+            .any(|rings| !rings.is_empty() && ring_contains(&rings[0], pt))
+        {
+            if let Some(code) = feature
+                .properties
+                .as_ref()
+                .and_then(|props| props.get("country"))
+                .and_then(|value| value.as_str())
+            {
+                return Some(code.to_string());
+            }
+        }
+    }
+    None
+}
+
+// Even-odd ray casting
+fn ring_contains(ring: &[Vec<f64>], pt: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if (a[1] > pt[1]) != (b[1] > pt[1])
+            && pt[0] < (b[0] - a[0]) * (pt[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        for (lon, lat, code, side) in [
+            (-0.1276, 51.5072, "GB", DrivingSide::Left),
+            (-5.93, 54.6, "GB", DrivingSide::Left),
+            (-6.26, 53.35, "IE", DrivingSide::Left),
+            (13.405, 52.52, "DE", DrivingSide::Right),
+            (2.3522, 48.8566, "FR", DrivingSide::Right),
+            (-122.3321, 47.6062, "US", DrivingSide::Right),
+            (-123.1207, 49.2827, "CA", DrivingSide::Right),
+            (151.2093, -33.8688, "AU", DrivingSide::Left),
+            (174.7633, -36.8485, "NZ", DrivingSide::Left),
+            (139.6917, 35.6895, "JP", DrivingSide::Left),
+        ] {
+            let profile = RegionProfile::lookup(LonLat::new(lon, lat)).unwrap();
+            assert_eq!(profile.country_code, code);
+            assert_eq!(profile.driving_side, side);
+        }
+        // The middle of the Atlantic
+        assert!(RegionProfile::lookup(LonLat::new(-40.0, 40.0)).is_none());
+    }
+}
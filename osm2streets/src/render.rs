@@ -4,9 +4,22 @@ use std::path::Path;
 
 use abstutil::Timer;
 use anyhow::Result;
-use geom::{ArrowCap, Distance, Line, PolyLine};
+use geom::{Angle, ArrowCap, Distance, Line, PolyLine, Pt2D};
 
-use crate::{DebugStreets, Direction, LaneType, StreetNetwork};
+use crate::initial::InitialMap;
+use crate::{
+    osm, ApproachControl, ControlType, CrossingType, DebugStreets, Direction, DrivingSide,
+    LaneType, OriginalRoad, StreetNetwork, TurnDirection,
+};
+
+/// How far a crosswalk extends along the road
+const CROSSWALK_DEPTH: Distance = Distance::const_meters(3.0);
+
+/// A geometry in WGS84 with its properties
+pub(crate) type Feature = (
+    geojson::Geometry,
+    serde_json::Map<String, serde_json::Value>,
+);
 
 impl StreetNetwork {
     /// Saves the plain GeoJSON rendering to a file.
@@ -21,11 +34,41 @@ impl StreetNetwork {
     /// Generates a plain GeoJSON rendering with one polygon per road and intersection.
     pub fn to_geojson(&self, timer: &mut Timer) -> Result<String> {
         // TODO InitialMap is going away very soon, but we still need it
-        let initial_map = crate::initial::InitialMap::new(self, timer);
+        let initial_map = InitialMap::new(self, timer);
 
-        let mut pairs = Vec::new();
+        let mut pairs = self.road_features(&initial_map);
+        pairs.extend(self.intersection_features(&initial_map));
+
+        let obj = geom::geometries_with_properties_to_geojson(pairs);
+        let output = serde_json::to_string_pretty(&obj)?;
+        Ok(output)
+    }
+
+    /// Generates a polygon per lane, with a property indicating type.
+    pub fn to_lane_polygons_geojson(&self, timer: &mut Timer) -> Result<String> {
+        // TODO InitialMap is going away very soon, but we still need it
+        let initial_map = InitialMap::new(self, timer);
+
+        let obj =
+            geom::geometries_with_properties_to_geojson(self.lane_polygon_features(&initial_map));
+        let output = serde_json::to_string_pretty(&obj)?;
+        Ok(output)
+    }
+
+    /// Generate polygons representing lane markings, with a property indicating type.
+    pub fn to_lane_markings_geojson(&self, timer: &mut Timer) -> Result<String> {
+        // TODO InitialMap is going away very soon, but we still need it
+        let initial_map = InitialMap::new(self, timer);
 
-        // Add a polygon per road
+        let obj =
+            geom::geometries_with_properties_to_geojson(self.lane_marking_features(&initial_map)?);
+        let output = serde_json::to_string_pretty(&obj)?;
+        Ok(output)
+    }
+
+    /// A polygon per road
+    pub(crate) fn road_features(&self, initial_map: &InitialMap) -> Vec<Feature> {
+        let mut pairs = Vec::new();
         for (id, road) in &initial_map.roads {
             pairs.push((
                 road.trimmed_center_pts
@@ -39,8 +82,12 @@ impl StreetNetwork {
                 ]),
             ));
         }
+        pairs
+    }
 
-        // Polygon per intersection
+    /// A polygon per intersection
+    pub(crate) fn intersection_features(&self, initial_map: &InitialMap) -> Vec<Feature> {
+        let mut pairs = Vec::new();
         for (id, intersection) in &initial_map.intersections {
             pairs.push((
                 intersection.polygon.to_geojson(Some(&self.gps_bounds)),
@@ -56,24 +103,24 @@ impl StreetNetwork {
                 ]),
             ));
         }
-
-        let obj = geom::geometries_with_properties_to_geojson(pairs);
-        let output = serde_json::to_string_pretty(&obj)?;
-        Ok(output)
+        pairs
     }
 
-    /// Generates a polygon per lane, with a property indicating type.
-    pub fn to_lane_polygons_geojson(&self, timer: &mut Timer) -> Result<String> {
-        // TODO InitialMap is going away very soon, but we still need it
-        let initial_map = crate::initial::InitialMap::new(self, timer);
-
+    /// A polygon per lane, with a property indicating type
+    pub(crate) fn lane_polygon_features(&self, initial_map: &InitialMap) -> Vec<Feature> {
         let mut pairs = Vec::new();
 
         for (id, road) in &self.roads {
-            for (lane, pl) in road.lane_specs_ltr.iter().zip(
-                road.get_lane_center_lines(&initial_map.roads[id].trimmed_center_pts)
-                    .into_iter(),
-            ) {
+            // Roads with broken geometry are skipped
+            let trimmed_center_pts = match initial_map.roads.get(id) {
+                Some(r) => &r.trimmed_center_pts,
+                None => continue,
+            };
+            for (lane, pl) in road
+                .lane_specs_ltr
+                .iter()
+                .zip(road.get_lane_center_lines(trimmed_center_pts).into_iter())
+            {
                 pairs.push((
                     pl.make_polygons(lane.width)
                         .to_geojson(Some(&self.gps_bounds)),
@@ -86,25 +133,24 @@ impl StreetNetwork {
                 ));
             }
         }
-
-        let obj = geom::geometries_with_properties_to_geojson(pairs);
-        let output = serde_json::to_string_pretty(&obj)?;
-        Ok(output)
+        pairs
     }
 
-    /// Generate polygons representing lane markings, with a property indicating type.
-    pub fn to_lane_markings_geojson(&self, timer: &mut Timer) -> Result<String> {
-        // TODO InitialMap is going away very soon, but we still need it
-        let initial_map = crate::initial::InitialMap::new(self, timer);
-
+    /// Polygons representing lane markings, with a property indicating type
+    pub(crate) fn lane_marking_features(&self, initial_map: &InitialMap) -> Result<Vec<Feature>> {
         let gps_bounds = Some(&self.gps_bounds);
 
         let mut pairs = Vec::new();
+        let crosswalks = self.crosswalks(initial_map);
+        let center_line_color = self.config.marking_style.center_line_color();
 
         for (id, road) in &self.roads {
+            let trimmed_center_pts = match initial_map.roads.get(id) {
+                Some(r) => &r.trimmed_center_pts,
+                None => continue,
+            };
             // Always oriented in the direction of the road
-            let mut lane_centers =
-                road.get_lane_center_lines(&initial_map.roads[id].trimmed_center_pts);
+            let mut lane_centers = road.get_lane_center_lines(trimmed_center_pts);
 
             for (idx, pair) in road.lane_specs_ltr.windows(2).enumerate() {
                 // Generate a "center line" between lanes of different directions
@@ -120,7 +166,10 @@ impl StreetNetwork {
                     ) {
                         pairs.push((
                             poly.to_geojson(gps_bounds),
-                            make_props(&[("type", "center line".into())]),
+                            make_props(&[
+                                ("type", "center line".into()),
+                                ("color", center_line_color.into()),
+                            ]),
                         ));
                     }
                     continue;
@@ -174,6 +223,33 @@ impl StreetNetwork {
                 }
             }
 
+            // Turn arrows just before the stop line
+            for (lane, center) in road.lane_specs_ltr.iter().zip(lane_centers.iter()) {
+                if lane.allowed_turns.is_empty() {
+                    continue;
+                }
+                let arrow_len = Distance::meters(3.0);
+                let thickness = Distance::meters(0.25);
+                let setback = stop_setback(&crosswalks, *id, lane.dir, trimmed_center_pts.length());
+                let dist = center.length() - setback - Distance::meters(2.0) - arrow_len;
+                if dist <= Distance::ZERO {
+                    continue;
+                }
+                let (pt, angle) = center.must_dist_along(dist);
+                for turn in &lane.allowed_turns {
+                    let arrow =
+                        turn_arrow_shape(pt, angle, *turn, arrow_len, self.config.driving_side)
+                            .make_arrow(thickness * 2.0, ArrowCap::Triangle);
+                    pairs.push((
+                        arrow.to_geojson(gps_bounds),
+                        make_props(&[
+                            ("type", "turn arrow".into()),
+                            ("turn", format!("{:?}", turn).into()),
+                        ]),
+                    ));
+                }
+            }
+
             // Add stripes to show buffers. Ignore the type of the buffer for now -- we need to
             // decide all the types and how to render them.
             for (lane, center) in road.lane_specs_ltr.iter().zip(lane_centers.iter()) {
@@ -219,20 +295,295 @@ impl StreetNetwork {
             }
         }
 
-        let obj = geom::geometries_with_properties_to_geojson(pairs);
+        // Zebra stripes for unsignalized crosswalks, two lines for signalized ones, and nothing
+        // for unmarked crossings
+        for (r, dist, kind, node) in &crosswalks {
+            let marking_type = match kind {
+                CrossingType::Signalized => "crosswalk line",
+                CrossingType::Unsignalized => "crosswalk stripe",
+                CrossingType::Unmarked => continue,
+            };
+            let road = &self.roads[r];
+            let (pt, angle) = initial_map.roads[r]
+                .trimmed_center_pts
+                .must_dist_along(*dist);
+            // Only cross the carriageway, not the sidewalks
+            let mut left_edge = road.total_width() / 2.0;
+            for lane in road
+                .lane_specs_ltr
+                .iter()
+                .take_while(|l| l.lt.is_walkable())
+            {
+                left_edge -= lane.width;
+            }
+            let mut right_edge = road.total_width() / 2.0;
+            for lane in road
+                .lane_specs_ltr
+                .iter()
+                .rev()
+                .take_while(|l| l.lt.is_walkable())
+            {
+                right_edge -= lane.width;
+            }
+            let left = pt.project_away(left_edge, angle.rotate_degs(-90.0));
+            let right = pt.project_away(right_edge, angle.rotate_degs(90.0));
+            let across = match Line::new(left, right) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+
+            let mut props = make_props(&[
+                ("type", marking_type.into()),
+                ("crossing_type", format!("{:?}", kind).into()),
+                ("osm_way_id", r.osm_way_id.0.into()),
+            ]);
+            if let Some(node) = node {
+                props.insert("osm_node_id".to_string(), node.0.into());
+                props.insert("osm_link".to_string(), node.to_string().into());
+            }
+
+            if *kind == CrossingType::Signalized {
+                for side in [angle, angle.opposite()] {
+                    let line = Line::must_new(
+                        left.project_away(CROSSWALK_DEPTH / 2.0, side),
+                        right.project_away(CROSSWALK_DEPTH / 2.0, side),
+                    );
+                    pairs.push((
+                        line.make_polygons(Distance::meters(0.25))
+                            .to_geojson(gps_bounds),
+                        props.clone(),
+                    ));
+                }
+            } else {
+                for poly in across.to_polyline().dashed_lines(
+                    CROSSWALK_DEPTH,
+                    Distance::meters(0.5),
+                    Distance::meters(0.5),
+                ) {
+                    pairs.push((poly.to_geojson(gps_bounds), props.clone()));
+                }
+            }
+        }
+
+        // Stop lines across the lanes entering a controlled intersection
+        for (i, intersection) in &self.intersections {
+            let all_way = self.is_all_way_stop(*i);
+            for (r, control) in &intersection.approach_controls {
+                let road = match self.roads.get(r) {
+                    Some(road) => road,
+                    None => continue,
+                };
+                let trimmed_center_pts = match initial_map.roads.get(r) {
+                    Some(initial) => &initial.trimmed_center_pts,
+                    None => continue,
+                };
+                let dir = if r.i2 == *i {
+                    Direction::Fwd
+                } else {
+                    Direction::Back
+                };
+                let setback = stop_setback(&crosswalks, *r, dir, trimmed_center_pts.length());
+                for (lane, center) in road
+                    .lane_specs_ltr
+                    .iter()
+                    .zip(road.get_lane_center_lines(trimmed_center_pts).into_iter())
+                {
+                    if lane.dir != dir || !lane.lt.is_for_moving_vehicles() {
+                        continue;
+                    }
+                    // Point toward the intersection
+                    let center = if dir == Direction::Back {
+                        center.reversed()
+                    } else {
+                        center
+                    };
+                    let thickness = Distance::meters(0.5);
+                    if center.length() <= setback + thickness {
+                        continue;
+                    }
+                    let (pt, angle) =
+                        center.must_dist_along(center.length() - setback - thickness / 2.0);
+                    let line = Line::must_new(
+                        pt.project_away(lane.width / 2.0, angle.rotate_degs(90.0)),
+                        pt.project_away(lane.width / 2.0, angle.rotate_degs(-90.0)),
+                    );
+                    let props = make_props(&[
+                        (
+                            "type",
+                            if *control == ApproachControl::Yield {
+                                "yield line"
+                            } else {
+                                "stop line"
+                            }
+                            .into(),
+                        ),
+                        ("control", format!("{:?}", control).into()),
+                        ("osm_way_id", r.osm_way_id.0.into()),
+                        ("all_way", all_way.into()),
+                    ]);
+                    if *control == ApproachControl::Yield {
+                        for poly in line.to_polyline().dashed_lines(
+                            thickness,
+                            Distance::meters(0.6),
+                            Distance::meters(0.3),
+                        ) {
+                            pairs.push((poly.to_geojson(gps_bounds), props.clone()));
+                        }
+                    } else {
+                        pairs.push((line.make_polygons(thickness).to_geojson(gps_bounds), props));
+                    }
+                }
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    /// Generates a point per bus stop, platform, and tram stop mapped in OSM.
+    pub fn to_public_transport_stops_geojson(&self) -> Result<String> {
+        let obj =
+            geom::geometries_with_properties_to_geojson(self.public_transport_stop_features());
+        let output = serde_json::to_string_pretty(&obj)?;
+        Ok(output)
+    }
+
+    /// A point per public transport stop, with properties describing where it is on the road
+    pub(crate) fn public_transport_stop_features(&self) -> Vec<Feature> {
+        let mut pairs = Vec::new();
+        for (id, road) in &self.roads {
+            for stop in &road.public_transport_stops {
+                pairs.push((
+                    stop.position.to_geojson(Some(&self.gps_bounds)),
+                    make_props(&[
+                        ("type", format!("{:?}", stop.stop_type).into()),
+                        (
+                            "name",
+                            stop.name.clone().map(|x| x.into()).unwrap_or_default(),
+                        ),
+                        ("osm_way_id", id.osm_way_id.0.into()),
+                        ("dist_along", stop.dist_along.inner_meters().into()),
+                        ("direction", format!("{:?}", stop.dir).into()),
+                        ("placement", format!("{:?}", stop.placement).into()),
+                        ("osm_link", stop.osm_node_id.to_string().into()),
+                    ]),
+                ));
+            }
+        }
+        pairs
+    }
+
+    /// Generates a polygon per building, parking lot, and landuse area, if they were imported.
+    pub fn to_areas_geojson(&self) -> Result<String> {
+        let obj = geom::geometries_with_properties_to_geojson(self.area_features());
         let output = serde_json::to_string_pretty(&obj)?;
         Ok(output)
     }
 
+    /// A polygon per area, with buildings describing the road they face
+    pub(crate) fn area_features(&self) -> Vec<Feature> {
+        let mut pairs = Vec::new();
+        for area in &self.areas {
+            let mut props = make_props(&[("type", format!("{:?}", area.area_type).into())]);
+            if let Some(id) = area.osm_id {
+                props.insert("osm_link".to_string(), id.to_string().into());
+            }
+            if let Some(frontage) = area.frontage {
+                props.insert(
+                    "frontage_osm_way_id".to_string(),
+                    frontage.road.osm_way_id.0.into(),
+                );
+                props.insert(
+                    "frontage_dist_along".to_string(),
+                    frontage.dist_along.inner_meters().into(),
+                );
+                props.insert(
+                    "frontage_side".to_string(),
+                    if frontage.left_side { "left" } else { "right" }.into(),
+                );
+            }
+            pairs.push((area.polygon.to_geojson(Some(&self.gps_bounds)), props));
+        }
+        pairs
+    }
+
+    /// Where to draw crosswalks: the road, the distance along its trimmed center line, the type,
+    /// and the OSM node for crossings that're mapped. A crossing node near the end of a road
+    /// replaces the crosswalk that `crosswalk_forward` and `crosswalk_backward` assume there.
+    fn crosswalks(
+        &self,
+        initial_map: &InitialMap,
+    ) -> Vec<(OriginalRoad, Distance, CrossingType, Option<osm::NodeID>)> {
+        // Crosswalks at the end of a road stay just outside the intersection
+        let end_buffer = CROSSWALK_DEPTH / 2.0 + Distance::meters(0.5);
+        let near_end = Distance::meters(15.0);
+
+        let mut results = Vec::new();
+        for (id, road) in &self.roads {
+            if !road.is_driveable() {
+                continue;
+            }
+            let pl = match initial_map.roads.get(id) {
+                Some(r) => &r.trimmed_center_pts,
+                None => continue,
+            };
+            let len = pl.length();
+            if len <= end_buffer * 2.0 {
+                continue;
+            }
+
+            let mut mapped_at_end = [false, false];
+            for (node, pt, kind) in &road.crossing_nodes {
+                let dist = match pl.dist_along_of_point(pl.project_pt(*pt)) {
+                    Some((dist, _)) => dist,
+                    None => continue,
+                };
+                if dist < near_end {
+                    mapped_at_end[0] = true;
+                }
+                if len - dist < near_end {
+                    mapped_at_end[1] = true;
+                }
+                // Crossings on the part of the road inside the intersection get pushed out of it
+                let dist = dist.max(end_buffer).min(len - end_buffer);
+                results.push((*id, dist, *kind, Some(*node)));
+            }
+
+            if !road.lane_specs_ltr.iter().any(|l| l.lt.is_walkable()) {
+                continue;
+            }
+            let far_end = len - end_buffer;
+            for (i, assumed, mapped, dist) in [
+                (id.i1, road.crosswalk_backward, mapped_at_end[0], end_buffer),
+                (id.i2, road.crosswalk_forward, mapped_at_end[1], far_end),
+            ] {
+                let intersection = &self.intersections[&i];
+                // Roads that just continue into each other don't need a crosswalk
+                if !assumed || mapped || intersection.is_border() || intersection.roads.len() < 3 {
+                    continue;
+                }
+                let kind = if intersection.control == ControlType::TrafficSignal {
+                    CrossingType::Signalized
+                } else {
+                    CrossingType::Unsignalized
+                };
+                results.push((*id, dist, kind, None));
+            }
+        }
+        results
+    }
+
     /// For an intersection, show the clockwise ordering of roads around it
     pub fn debug_clockwise_ordering_geojson(&self, timer: &mut Timer) -> Result<String> {
-        let initial_map = crate::initial::InitialMap::new(self, timer);
+        let initial_map = InitialMap::new(self, timer);
 
         let mut pairs = Vec::new();
 
         for (i, intersection) in &self.intersections {
             for (idx, r) in intersection.roads.iter().enumerate() {
-                let pl = &initial_map.roads[r].trimmed_center_pts;
+                let pl = match initial_map.roads.get(r) {
+                    Some(road) => &road.trimmed_center_pts,
+                    None => continue,
+                };
                 let pt = if r.i1 == *i {
                     pl.first_pt()
                 } else {
@@ -278,6 +629,82 @@ impl DebugStreets {
     }
 }
 
+// How far before the end of a road vehicles going `dir` stop. They leave a little room before the
+// intersection, or stop before a crosswalk painted there.
+fn stop_setback(
+    crosswalks: &[(OriginalRoad, Distance, CrossingType, Option<osm::NodeID>)],
+    road: OriginalRoad,
+    dir: Direction,
+    road_length: Distance,
+) -> Distance {
+    crosswalks
+        .iter()
+        .filter(|(crosswalk_road, _, kind, _)| {
+            *crosswalk_road == road && *kind != CrossingType::Unmarked
+        })
+        .map(|(_, dist, ..)| {
+            if dir == Direction::Fwd {
+                road_length - *dist
+            } else {
+                *dist
+            }
+        })
+        .filter(|dist| *dist < Distance::meters(10.0))
+        .fold(Distance::meters(1.0), |setback, dist| {
+            setback.max(dist + CROSSWALK_DEPTH / 2.0 + Distance::meters(0.5))
+        })
+}
+
+// The center line of a turn arrow painted on a lane, starting at `pt` and pointing along `angle`
+fn turn_arrow_shape(
+    pt: Pt2D,
+    angle: Angle,
+    turn: TurnDirection,
+    len: Distance,
+    driving_side: DrivingSide,
+) -> PolyLine {
+    // Angles increase clockwise, so left is negative
+    let bend = match turn {
+        TurnDirection::Through => {
+            return PolyLine::must_new(vec![pt, pt.project_away(len, angle)]);
+        }
+        TurnDirection::MergeToLeft => {
+            return PolyLine::must_new(vec![pt, pt.project_away(len, angle.rotate_degs(-20.0))]);
+        }
+        TurnDirection::MergeToRight => {
+            return PolyLine::must_new(vec![pt, pt.project_away(len, angle.rotate_degs(20.0))]);
+        }
+        TurnDirection::Reverse => {
+            // U-turns go toward the middle of the road
+            let side = if driving_side == DrivingSide::Right {
+                -90.0
+            } else {
+                90.0
+            };
+            let top = pt.project_away(len * 0.6, angle);
+            let across = top.project_away(len * 0.3, angle.rotate_degs(side));
+            return PolyLine::must_new(vec![
+                pt,
+                top,
+                across,
+                across.project_away(len * 0.4, angle.opposite()),
+            ]);
+        }
+        TurnDirection::SharpLeft => -135.0,
+        TurnDirection::Left => -90.0,
+        TurnDirection::SlightLeft => -45.0,
+        TurnDirection::SlightRight => 45.0,
+        TurnDirection::Right => 90.0,
+        TurnDirection::SharpRight => 135.0,
+    };
+    let stem = pt.project_away(len * 0.6, angle);
+    PolyLine::must_new(vec![
+        pt,
+        stem,
+        stem.project_away(len * 0.4, angle.rotate_degs(bend)),
+    ])
+}
+
 fn make_props(list: &[(&str, serde_json::Value)]) -> serde_json::Map<String, serde_json::Value> {
     let mut props = serde_json::Map::new();
     for (x, y) in list {
@@ -285,3 +712,87 @@ fn make_props(list: &[(&str, serde_json::Value)]) -> serde_json::Map<String, ser
     }
     props
 }
+
+#[cfg(test)]
+mod tests {
+    use geom::LonLat;
+
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+
+    #[test]
+    fn test_crosswalk_markings() {
+        // A T-junction at node 2, with a crossing of each type mapped along the main road
+        let mut streets = StreetNetwork::blank();
+        streets.gps_bounds.update(LonLat::new(-122.30, 47.60));
+        streets.gps_bounds.update(LonLat::new(-122.29, 47.61));
+        add_intersection(&mut streets, 1, 0.0, 100.0);
+        add_intersection(&mut streets, 2, 100.0, 100.0);
+        add_intersection(&mut streets, 3, 200.0, 100.0);
+        add_intersection(&mut streets, 4, 100.0, 200.0);
+        let west = add_road(&mut streets, 10, 1, 2, &[]);
+        let east = add_road(&mut streets, 11, 2, 3, &[]);
+        let south = add_road(&mut streets, 12, 2, 4, &[]);
+        streets.roads.get_mut(&west).unwrap().crossing_nodes = vec![
+            (
+                osm::NodeID(20),
+                Pt2D::new(50.0, 100.0),
+                CrossingType::Unsignalized,
+            ),
+            // Replaces the crosswalk assumed at the junction
+            (
+                osm::NodeID(21),
+                Pt2D::new(95.0, 100.0),
+                CrossingType::Unmarked,
+            ),
+        ];
+        streets.roads.get_mut(&east).unwrap().crossing_nodes = vec![(
+            osm::NodeID(22),
+            Pt2D::new(150.0, 100.0),
+            CrossingType::Signalized,
+        )];
+
+        let initial_map = InitialMap::new(&streets, &mut Timer::throwaway());
+        let features = streets.lane_marking_features(&initial_map).unwrap();
+        let crosswalks = |way: OriginalRoad| -> Vec<(String, Option<i64>)> {
+            features
+                .iter()
+                .filter_map(|(_, props)| {
+                    let marking_type = props.get("type")?.as_str()?;
+                    if !marking_type.starts_with("crosswalk")
+                        || props.get("osm_way_id")?.as_i64() != Some(way.osm_way_id.0)
+                    {
+                        return None;
+                    }
+                    Some((
+                        marking_type.to_string(),
+                        props.get("osm_node_id").and_then(|id| id.as_i64()),
+                    ))
+                })
+                .collect()
+        };
+
+        // Only zebra stripes from the marked crossing; nothing for the unmarked one or at the
+        // junction
+        let west_crosswalks = crosswalks(west);
+        assert!(!west_crosswalks.is_empty());
+        assert!(west_crosswalks
+            .iter()
+            .all(|pair| *pair == ("crosswalk stripe".to_string(), Some(20))));
+
+        // Two lines for the signalized crossing
+        let east_crosswalks = crosswalks(east);
+        assert_eq!(
+            east_crosswalks
+                .iter()
+                .filter(|pair| **pair == ("crosswalk line".to_string(), Some(22)))
+                .count(),
+            2
+        );
+
+        // The side road still has the crosswalk assumed at the junction
+        assert!(crosswalks(south)
+            .iter()
+            .any(|pair| *pair == ("crosswalk stripe".to_string(), None)));
+    }
+}
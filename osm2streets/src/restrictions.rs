@@ -0,0 +1,658 @@
+use std::collections::BTreeMap;
+
+use abstutil::Tags;
+use geom::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::{OriginalRoad, RestrictionType, StreetNetwork};
+
+/// Who and when a turn restriction applies to. The default applies to everybody, all the time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RestrictionConditions {
+    /// If non-empty, the restriction only applies to these vehicles, from `restriction:<vehicle>`
+    pub only_for: Vec<VehicleType>,
+    /// From `except`
+    pub except: Vec<VehicleType>,
+    /// If non-empty, the restriction only applies during these times, from
+    /// `restriction:conditional` or `day_on`, `day_off`, `hour_on`, and `hour_off`
+    pub time_windows: Vec<TimeWindow>,
+    /// The restriction doesn't apply during these times, from `restriction:conditional=none @ ...`
+    pub lifted_during: Vec<TimeWindow>,
+}
+
+/// The vehicle categories used by OSM access and restriction tags. Some include others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum VehicleType {
+    MotorVehicle,
+    Motorcar,
+    Hgv,
+    Psv,
+    Bus,
+    Moped,
+    Bicycle,
+    Emergency,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Weekday {
+    Mo,
+    Tu,
+    We,
+    Th,
+    Fr,
+    Sa,
+    Su,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    /// Empty means every day
+    pub days: Vec<Weekday>,
+    /// Since midnight. If the start is after the end, the window wraps past midnight. `None` means
+    /// all day.
+    pub hours: Option<(Duration, Duration)>,
+}
+
+impl RestrictionConditions {
+    pub fn is_unconditional(&self) -> bool {
+        self.only_for.is_empty() && self.except.is_empty() && !self.is_time_dependent()
+    }
+
+    pub fn is_time_dependent(&self) -> bool {
+        !self.time_windows.is_empty() || !self.lifted_during.is_empty()
+    }
+
+    /// Does the restriction apply to this vehicle at some point? Ignores time.
+    pub fn applies_to(&self, vehicle: VehicleType) -> bool {
+        (self.only_for.is_empty() || self.only_for.iter().any(|x| vehicle.is_kind_of(*x)))
+            && !self.except.iter().any(|x| vehicle.is_kind_of(*x))
+    }
+}
+
+impl VehicleType {
+    pub fn parse(value: &str) -> Option<VehicleType> {
+        match value.trim() {
+            "motor_vehicle" => Some(VehicleType::MotorVehicle),
+            "motorcar" => Some(VehicleType::Motorcar),
+            "hgv" => Some(VehicleType::Hgv),
+            "psv" => Some(VehicleType::Psv),
+            "bus" => Some(VehicleType::Bus),
+            "moped" | "mofa" => Some(VehicleType::Moped),
+            "bicycle" => Some(VehicleType::Bicycle),
+            "emergency" => Some(VehicleType::Emergency),
+            _ => None,
+        }
+    }
+
+    /// Is this vehicle included in a category? `Bus` is a kind of `Psv`, for example.
+    pub fn is_kind_of(self, category: VehicleType) -> bool {
+        self == category
+            || match category {
+                VehicleType::MotorVehicle => self != VehicleType::Bicycle,
+                VehicleType::Psv => self == VehicleType::Bus,
+                _ => false,
+            }
+    }
+}
+
+impl Weekday {
+    pub fn parse(value: &str) -> Option<Weekday> {
+        // Handle both "Mo" and "Monday"
+        match value.trim().get(0..2)?.to_lowercase().as_str() {
+            "mo" => Some(Weekday::Mo),
+            "tu" => Some(Weekday::Tu),
+            "we" => Some(Weekday::We),
+            "th" => Some(Weekday::Th),
+            "fr" => Some(Weekday::Fr),
+            "sa" => Some(Weekday::Sa),
+            "su" => Some(Weekday::Su),
+            _ => None,
+        }
+    }
+
+    fn all() -> [Weekday; 7] {
+        [
+            Weekday::Mo,
+            Weekday::Tu,
+            Weekday::We,
+            Weekday::Th,
+            Weekday::Fr,
+            Weekday::Sa,
+            Weekday::Su,
+        ]
+    }
+
+    // Inclusive, wrapping around the end of the week
+    fn range(from: Weekday, to: Weekday) -> Vec<Weekday> {
+        let all = Weekday::all();
+        let mut result = Vec::new();
+        let mut idx = from as usize;
+        loop {
+            result.push(all[idx]);
+            if all[idx] == to {
+                return result;
+            }
+            idx = (idx + 1) % all.len();
+        }
+    }
+}
+
+/// Interprets the tags of a `type=restriction` relation. A relation can describe a few
+/// restrictions, for different vehicles or times.
+pub fn parse_turn_restrictions(tags: &Tags) -> Vec<(RestrictionType, RestrictionConditions)> {
+    let except: Vec<VehicleType> = tags
+        .get("except")
+        .map(|x| x.split(';').filter_map(VehicleType::parse).collect())
+        .unwrap_or_default();
+
+    // Older tagging for time windows
+    let mut legacy_times = Vec::new();
+    let days = match (tags.get("day_on"), tags.get("day_off")) {
+        (Some(on), Some(off)) => Weekday::parse(on)
+            .zip(Weekday::parse(off))
+            .map(|(on, off)| Weekday::range(on, off)),
+        (Some(on), None) => Weekday::parse(on).map(|day| vec![day]),
+        _ => Some(Vec::new()),
+    };
+    let hours = match (tags.get("hour_on"), tags.get("hour_off")) {
+        (Some(on), Some(off)) => parse_time(on).zip(parse_time(off)).map(Some),
+        _ => Some(None),
+    };
+    match (days, hours) {
+        (Some(days), Some(hours)) => {
+            if !days.is_empty() || hours.is_some() {
+                legacy_times.push(TimeWindow { days, hours });
+            }
+        }
+        _ => {
+            warn!(
+                "Can't parse turn restriction times day_on={:?}, day_off={:?}, hour_on={:?}, \
+                 hour_off={:?}",
+                tags.get("day_on"),
+                tags.get("day_off"),
+                tags.get("hour_on"),
+                tags.get("hour_off")
+            );
+        }
+    }
+
+    let mut results = Vec::new();
+    // Windows when the unconditional restriction for some vehicle doesn't apply
+    let mut lifted: Vec<(Option<VehicleType>, Vec<TimeWindow>)> = Vec::new();
+    for (key, value) in tags.inner() {
+        let suffix = if key == "restriction" {
+            ""
+        } else if let Some(suffix) = key.strip_prefix("restriction:") {
+            suffix
+        } else {
+            continue;
+        };
+        let (vehicle, conditional) = match suffix.strip_suffix("conditional") {
+            Some(rest) => (rest.trim_end_matches(':'), true),
+            None => (suffix, false),
+        };
+        let vehicle = if vehicle.is_empty() {
+            None
+        } else if let Some(v) = VehicleType::parse(vehicle) {
+            Some(v)
+        } else {
+            warn!("Unknown vehicle in turn restriction {}={}", key, value);
+            continue;
+        };
+
+        if !conditional {
+            if let Some(rt) = RestrictionType::new(value) {
+                results.push((
+                    rt,
+                    RestrictionConditions {
+                        only_for: vehicle.into_iter().collect(),
+                        except: except.clone(),
+                        time_windows: legacy_times.clone(),
+                        lifted_during: Vec::new(),
+                    },
+                ));
+            }
+            continue;
+        }
+
+        for part in split_outside_parens(value) {
+            let parsed = part.split_once('@').and_then(|(restriction, condition)| {
+                Some((restriction.trim(), parse_condition(condition)?))
+            });
+            let (restriction, times) = match parsed {
+                Some(x) => x,
+                None => {
+                    warn!("Can't parse turn restriction {}={}", key, value);
+                    continue;
+                }
+            };
+            if restriction == "none" {
+                lifted.push((vehicle, times));
+            } else if let Some(rt) = RestrictionType::new(restriction) {
+                results.push((
+                    rt,
+                    RestrictionConditions {
+                        only_for: vehicle.into_iter().collect(),
+                        except: except.clone(),
+                        time_windows: times,
+                        lifted_during: Vec::new(),
+                    },
+                ));
+            }
+        }
+    }
+
+    for (vehicle, times) in lifted {
+        for (_, conditions) in &mut results {
+            if conditions.only_for == vehicle.into_iter().collect::<Vec<_>>()
+                && conditions.time_windows.is_empty()
+            {
+                conditions.lifted_during.extend(times.clone());
+            }
+        }
+    }
+
+    results
+}
+
+// Conditional values look like "no_left_turn @ (Mo-Fr 07:00-09:00); no_u_turn @ Sa"
+fn split_outside_parens(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 => {
+                parts.push(value[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts.retain(|x| !x.is_empty());
+    parts
+}
+
+// Only handles the subset of the opening_hours syntax commonly used for turn restrictions, like
+// "Mo-Fr 07:00-09:00,16:00-18:00; Sa 10:00-14:00"
+fn parse_condition(condition: &str) -> Option<Vec<TimeWindow>> {
+    let condition = condition.trim();
+    let condition = condition
+        .strip_prefix('(')
+        .and_then(|x| x.strip_suffix(')'))
+        .unwrap_or(condition);
+
+    let mut windows = Vec::new();
+    for rule in condition.split(';') {
+        let mut days = Vec::new();
+        let mut hours = Vec::new();
+        for token in rule.split_whitespace() {
+            if token.starts_with(|c: char| c.is_ascii_digit()) {
+                for range in token.split(',') {
+                    let (start, end) = range.split_once('-')?;
+                    hours.push((parse_time(start)?, parse_time(end)?));
+                }
+            } else {
+                for range in token.split(',') {
+                    match range.split_once('-') {
+                        Some((from, to)) => {
+                            days.extend(Weekday::range(Weekday::parse(from)?, Weekday::parse(to)?));
+                        }
+                        None => {
+                            if range.len() != 2 {
+                                // Things like "PH", "wet", or "weight>7.5" aren't handled
+                                return None;
+                            }
+                            days.push(Weekday::parse(range)?);
+                        }
+                    }
+                }
+            }
+        }
+
+        if hours.is_empty() {
+            if days.is_empty() {
+                return None;
+            }
+            windows.push(TimeWindow { days, hours: None });
+        } else {
+            for hours in hours {
+                windows.push(TimeWindow {
+                    days: days.clone(),
+                    hours: Some(hours),
+                });
+            }
+        }
+    }
+    Some(windows)
+}
+
+// "7", "07:00", or "24:00"
+fn parse_time(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (hours, minutes) = match value.split_once(':') {
+        Some((h, m)) => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None => (value.parse::<u32>().ok()?, 0),
+    };
+    if hours > 24 || minutes >= 60 {
+        return None;
+    }
+    Some(Duration::hours(hours as usize) + Duration::minutes(minutes as usize))
+}
+
+/// All turn restrictions and the original segments of every road, captured just before changing a
+/// `StreetNetwork`. Every `Transformation` uses this automatically; other code changing roads
+/// (like clipping) can too.
+pub struct RestrictionSnapshot {
+    segments: BTreeMap<OriginalRoad, Vec<OriginalRoad>>,
+    // (from, type, to, conditions)
+    simple: Vec<(
+        OriginalRoad,
+        RestrictionType,
+        OriginalRoad,
+        RestrictionConditions,
+    )>,
+    // (from, via, to, conditions)
+    complicated: Vec<(
+        OriginalRoad,
+        OriginalRoad,
+        OriginalRoad,
+        RestrictionConditions,
+    )>,
+}
+
+impl RestrictionSnapshot {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        let mut segments = BTreeMap::new();
+        let mut simple = Vec::new();
+        let mut complicated = Vec::new();
+        for (id, road) in &streets.roads {
+            segments.insert(*id, road.original_segments.clone());
+            for (rt, to, conditions) in &road.turn_restrictions {
+                simple.push((*id, *rt, *to, conditions.clone()));
+            }
+            for (via, to, conditions) in &road.complicated_turn_restrictions {
+                complicated.push((*id, *via, *to, conditions.clone()));
+            }
+        }
+        Self {
+            segments,
+            simple,
+            complicated,
+        }
+    }
+
+    /// After some change, rewrite every turn restriction to use the current road IDs. Roads that
+    /// were removed lose their restrictions, unless they were merged into another road.
+    /// Restrictions that no longer make sense, because the roads no longer meet or were removed,
+    /// are dropped. `transformation` describes the change for logging.
+    pub fn fix(self, transformation: &str, streets: &mut StreetNetwork) {
+        // Start with the restrictions the transformation kept or adjusted itself, then recover
+        // the ones belonging to removed roads
+        let mut simple = Vec::new();
+        let mut complicated = Vec::new();
+        for (id, road) in &mut streets.roads {
+            for (rt, to, conditions) in road.turn_restrictions.drain(..) {
+                simple.push((*id, rt, to, conditions));
+            }
+            for (via, to, conditions) in road.complicated_turn_restrictions.drain(..) {
+                complicated.push((*id, via, to, conditions));
+            }
+        }
+        for restriction in self.simple {
+            if !streets.roads.contains_key(&restriction.0) {
+                simple.push(restriction);
+            }
+        }
+        for restriction in self.complicated {
+            if !streets.roads.contains_key(&restriction.0) {
+                complicated.push(restriction);
+            }
+        }
+
+        let owners = streets.original_segments_to_roads();
+        // The current roads that some road before the transformation became part of. It may have
+        // been split into several.
+        let resolve = |id: OriginalRoad| -> Vec<OriginalRoad> {
+            if streets.roads.contains_key(&id) {
+                return vec![id];
+            }
+            let mut result: Vec<OriginalRoad> = self
+                .segments
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| vec![id])
+                .into_iter()
+                .filter_map(|segment| owners.get(&segment).cloned())
+                .collect();
+            result.sort();
+            result.dedup();
+            result
+        };
+
+        let mut fixed_simple = Vec::new();
+        for (from, rt, to, conditions) in simple {
+            let mut found = None;
+            for f in resolve(from) {
+                for t in resolve(to) {
+                    if f != t && f.has_common_endpoint(t) && found.is_none() {
+                        found = Some((f, t));
+                    }
+                }
+            }
+            if let Some((f, t)) = found {
+                fixed_simple.push((f, (rt, t, conditions)));
+            } else {
+                warn!(
+                    "After {}, dropping turn restriction from {} to {}, because the roads were \
+                     removed or no longer meet",
+                    transformation, from, to
+                );
+            }
+        }
+
+        let mut fixed_complicated = Vec::new();
+        for (from, via, to, conditions) in complicated {
+            let froms = resolve(from);
+            let vias = resolve(via);
+            let tos = resolve(to);
+            let mut found = None;
+            for f in &froms {
+                for v in &vias {
+                    for t in &tos {
+                        if f != v
+                            && v != t
+                            && f.has_common_endpoint(*v)
+                            && v.has_common_endpoint(*t)
+                            && found.is_none()
+                        {
+                            found = Some((*f, *v, *t));
+                        }
+                    }
+                }
+            }
+            if let Some((f, v, t)) = found {
+                fixed_complicated.push((f, (v, t, conditions)));
+                continue;
+            }
+
+            // If the via road was removed or merged into one of the others, the restriction
+            // becomes a simple one
+            let mut found = None;
+            for f in &froms {
+                for t in &tos {
+                    if f != t && f.has_common_endpoint(*t) && found.is_none() {
+                        found = Some((*f, *t));
+                    }
+                }
+            }
+            if let Some((f, t)) = found {
+                fixed_simple.push((f, (RestrictionType::BanTurns, t, conditions)));
+            } else {
+                warn!(
+                    "After {}, dropping turn restriction from {} to {} via {}, because the roads \
+                     were removed or no longer meet",
+                    transformation, from, to, via
+                );
+            }
+        }
+
+        for (from, restriction) in fixed_simple {
+            let list = &mut streets.roads.get_mut(&from).unwrap().turn_restrictions;
+            if !list.contains(&restriction) {
+                list.push(restriction);
+            }
+        }
+        for (from, restriction) in fixed_complicated {
+            let list = &mut streets
+                .roads
+                .get_mut(&from)
+                .unwrap()
+                .complicated_turn_restrictions;
+            if !list.contains(&restriction) {
+                list.push(restriction);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm;
+    use crate::test_utils::{add_intersection, add_road};
+
+    #[test]
+    fn test_parse_turn_restrictions() {
+        let mut tags = Tags::empty();
+        tags.insert("type", "restriction");
+        tags.insert("restriction", "no_left_turn");
+        tags.insert("except", "bicycle;psv");
+        tags.insert("restriction:conditional", "none @ (Sa,Su)");
+        tags.insert(
+            "restriction:hgv:conditional",
+            "no_right_turn @ (Mo-Fr 07:00-09:30)",
+        );
+        let results = parse_turn_restrictions(&tags);
+        assert_eq!(results.len(), 2);
+
+        let (rt, conditions) = &results[0];
+        assert_eq!(*rt, RestrictionType::BanTurns);
+        assert!(conditions.applies_to(VehicleType::Motorcar));
+        assert!(!conditions.applies_to(VehicleType::Bus));
+        assert!(!conditions.applies_to(VehicleType::Bicycle));
+        assert_eq!(
+            conditions.lifted_during,
+            vec![TimeWindow {
+                days: vec![Weekday::Sa, Weekday::Su],
+                hours: None,
+            }]
+        );
+
+        let (_, conditions) = &results[1];
+        assert!(conditions.applies_to(VehicleType::Hgv));
+        assert!(!conditions.applies_to(VehicleType::Motorcar));
+        assert_eq!(
+            conditions.time_windows,
+            vec![TimeWindow {
+                days: Weekday::range(Weekday::Mo, Weekday::Fr),
+                hours: Some((
+                    Duration::hours(7),
+                    Duration::hours(9) + Duration::minutes(30)
+                )),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fix_after_collapse() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 0.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        add_intersection(&mut streets, 4, 200.0, 100.0);
+        let a = add_road(&mut streets, 10, 1, 2, &[]);
+        let b = add_road(&mut streets, 11, 2, 3, &[]);
+        let side = add_road(&mut streets, 12, 3, 4, &[]);
+        let conditions = RestrictionConditions::default();
+        streets.roads.get_mut(&b).unwrap().turn_restrictions.push((
+            RestrictionType::BanTurns,
+            side,
+            conditions.clone(),
+        ));
+        streets
+            .roads
+            .get_mut(&side)
+            .unwrap()
+            .turn_restrictions
+            .push((RestrictionType::OnlyAllowTurns, b, conditions.clone()));
+
+        // Join a and b into one road, like collapsing the degenerate intersection between them
+        let snapshot = RestrictionSnapshot::new(&streets);
+        let mut road = streets.remove_road(&a);
+        let road_b = streets.remove_road(&b);
+        road.osm_center_points.extend(road_b.osm_center_points);
+        road.osm_center_points.dedup();
+        road.original_segments.extend(road_b.original_segments);
+        streets.delete_intersection(osm::NodeID(2));
+        let merged = OriginalRoad::new(10, (1, 3));
+        streets.insert_road(merged, road);
+        snapshot.fix("test", &mut streets);
+
+        // Both the restriction from the removed road and the one pointing at it now use the
+        // merged road
+        assert_eq!(
+            streets.roads[&merged].turn_restrictions,
+            vec![(RestrictionType::BanTurns, side, conditions.clone())]
+        );
+        assert_eq!(
+            streets.roads[&side].turn_restrictions,
+            vec![(RestrictionType::OnlyAllowTurns, merged, conditions)]
+        );
+    }
+
+    #[test]
+    fn test_fix_via_road_merged() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 0.0);
+        add_intersection(&mut streets, 3, 105.0, 0.0);
+        add_intersection(&mut streets, 4, 105.0, 100.0);
+        add_intersection(&mut streets, 5, 100.0, -100.0);
+        let a = add_road(&mut streets, 10, 1, 2, &[]);
+        let via = add_road(&mut streets, 11, 2, 3, &[]);
+        let c = add_road(&mut streets, 12, 3, 4, &[]);
+        let gone = add_road(&mut streets, 13, 2, 5, &[]);
+        let conditions = RestrictionConditions::default();
+        let road = streets.roads.get_mut(&a).unwrap();
+        road.complicated_turn_restrictions
+            .push((via, c, conditions.clone()));
+        road.turn_restrictions
+            .push((RestrictionType::BanTurns, gone, conditions.clone()));
+
+        // Merge the short via road, so c starts where it used to, and remove another road
+        let snapshot = RestrictionSnapshot::new(&streets);
+        streets.remove_road(&via);
+        streets.remove_road(&gone);
+        streets.delete_intersection(osm::NodeID(5));
+        let mut road = streets.remove_road(&c);
+        road.osm_center_points[0] = streets.intersections[&osm::NodeID(2)].point;
+        streets.delete_intersection(osm::NodeID(3));
+        let moved = OriginalRoad::new(12, (2, 4));
+        streets.insert_road(moved, road);
+        snapshot.fix("test", &mut streets);
+
+        // The via road is gone, so the restriction becomes a simple one. The restriction onto the
+        // removed road is dropped.
+        let road = &streets.roads[&a];
+        assert!(road.complicated_turn_restrictions.is_empty());
+        assert_eq!(
+            road.turn_restrictions,
+            vec![(RestrictionType::BanTurns, moved, conditions)]
+        );
+        assert!(streets.validate().is_empty());
+    }
+}
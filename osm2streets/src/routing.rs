@@ -0,0 +1,554 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use geom::{Distance, Duration, Speed};
+use petgraph::graphmap::DiGraphMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    osm, Direction, LaneType, OriginalRoad, RestrictionConditions, RestrictionType, Road,
+    StreetNetwork, VehicleType,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TravelMode {
+    Driving,
+    Biking,
+    Walking,
+    Bus,
+}
+
+impl TravelMode {
+    pub fn all() -> Vec<TravelMode> {
+        vec![
+            TravelMode::Driving,
+            TravelMode::Biking,
+            TravelMode::Walking,
+            TravelMode::Bus,
+        ]
+    }
+
+    fn can_use_lane(self, lt: LaneType, road: &Road) -> bool {
+        match self {
+            TravelMode::Driving => lt == LaneType::Driving,
+            TravelMode::Bus => lt == LaneType::Driving || lt == LaneType::Bus,
+            TravelMode::Biking => {
+                lt == LaneType::Biking
+                    || lt == LaneType::SharedUse
+                    // Cyclists can share general traffic lanes, except on motorways
+                    || (lt == LaneType::Driving
+                        && !road
+                            .osm_tags
+                            .is_any(osm::HIGHWAY, vec!["motorway", "motorway_link"])
+                        && !road.osm_tags.is("bicycle", "no"))
+            }
+            TravelMode::Walking => lt.is_walkable(),
+        }
+    }
+
+    /// Which vehicle turn restrictions should be checked against. Pedestrians ignore them.
+    fn vehicle_type(self) -> Option<VehicleType> {
+        match self {
+            TravelMode::Driving => Some(VehicleType::Motorcar),
+            TravelMode::Biking => Some(VehicleType::Bicycle),
+            TravelMode::Walking => None,
+            TravelMode::Bus => Some(VehicleType::Bus),
+        }
+    }
+
+    /// How fast this mode moves regardless of the speed limit
+    fn max_speed(self) -> Option<Speed> {
+        match self {
+            TravelMode::Driving | TravelMode::Bus => None,
+            TravelMode::Biking => Some(Speed::miles_per_hour(10.0)),
+            TravelMode::Walking => Some(Speed::meters_per_second(1.34)),
+        }
+    }
+}
+
+/// One way to cross a road in one direction using some mode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoutingEdge {
+    pub road: OriginalRoad,
+    pub dir: Direction,
+    pub mode: TravelMode,
+    pub from: osm::NodeID,
+    pub to: osm::NodeID,
+    pub length: Distance,
+    /// The time to cross the road at the speed limit, or the mode's own top speed if that's slower
+    pub cost: Duration,
+}
+
+/// A graph for routing each `TravelMode` over a `StreetNetwork`, respecting turn restrictions
+/// that apply to that mode all the time.
+/// Build it once and query it many times with `route`, or save it with `save_csv` for other routing
+/// engines.
+pub struct RoutingGraph {
+    pub edges: Vec<RoutingEdge>,
+    /// Each entry is a sequence of indices into `edges` that can't be followed in that order. There
+    /// are 2 edges for simple turn restrictions, and 3 for restrictions via a road.
+    pub banned_sequences: Vec<Vec<usize>>,
+    graphs: BTreeMap<TravelMode, DiGraphMap<Node, f64>>,
+}
+
+// The graph is edge-expanded, so turns between edges can be banned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Node {
+    // Start a route at an intersection
+    Departure(osm::NodeID),
+    // Finish a route at an intersection
+    Arrival(osm::NodeID),
+    // Having crossed an edge
+    Edge(usize),
+    // Having crossed the second edge after crossing the first. Only used for restrictions via a
+    // road, when the turn out of the second edge depends on the first.
+    Via(usize, usize),
+}
+
+impl RoutingGraph {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        let mut edges = Vec::new();
+        for (id, road) in &streets.roads {
+            let length = road.length();
+            for mode in TravelMode::all() {
+                let mut fwd = false;
+                let mut back = false;
+                for lane in &road.lane_specs_ltr {
+                    if !mode.can_use_lane(lane.lt, road) {
+                        continue;
+                    }
+                    // Pedestrians can walk either way on a sidewalk
+                    if mode == TravelMode::Walking || lane.dir == Direction::Fwd {
+                        fwd = true;
+                    }
+                    if mode == TravelMode::Walking || lane.dir == Direction::Back {
+                        back = true;
+                    }
+                }
+
+                for (enabled, dir, from, to) in [
+                    (fwd, Direction::Fwd, id.i1, id.i2),
+                    (back, Direction::Back, id.i2, id.i1),
+                ] {
+                    if enabled {
+                        let cost = length / travel_speed(streets, road, dir, mode);
+                        edges.push(RoutingEdge {
+                            road: *id,
+                            dir,
+                            mode,
+                            from,
+                            to,
+                            length,
+                            cost,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut edges_from: BTreeMap<(TravelMode, osm::NodeID), Vec<usize>> = BTreeMap::new();
+        let mut edges_on: BTreeMap<(TravelMode, OriginalRoad), Vec<usize>> = BTreeMap::new();
+        for (idx, edge) in edges.iter().enumerate() {
+            edges_from
+                .entry((edge.mode, edge.from))
+                .or_default()
+                .push(idx);
+            edges_on
+                .entry((edge.mode, edge.road))
+                .or_default()
+                .push(idx);
+        }
+        let mut banned_sequences = Vec::new();
+        for (from_road, road) in &streets.roads {
+            for mode in TravelMode::all() {
+                let vehicle = match mode.vehicle_type() {
+                    Some(x) => x,
+                    None => continue,
+                };
+                // The graph is static, so ignore restrictions that only apply sometimes
+                let applies = |conditions: &RestrictionConditions| {
+                    conditions.applies_to(vehicle) && !conditions.is_time_dependent()
+                };
+                let from_edges = edges_on
+                    .get(&(mode, *from_road))
+                    .cloned()
+                    .unwrap_or_default();
+
+                for (rt, to_road, conditions) in &road.turn_restrictions {
+                    if !applies(conditions) {
+                        continue;
+                    }
+                    for f in &from_edges {
+                        for t in edges_from.get(&(mode, edges[*f].to)).unwrap_or(&Vec::new()) {
+                            let banned = match rt {
+                                RestrictionType::BanTurns => edges[*t].road == *to_road,
+                                RestrictionType::OnlyAllowTurns => {
+                                    // Only applies where the two roads meet
+                                    (to_road.i1 == edges[*f].to || to_road.i2 == edges[*f].to)
+                                        && edges[*t].road != *to_road
+                                }
+                            };
+                            if banned {
+                                banned_sequences.push(vec![*f, *t]);
+                            }
+                        }
+                    }
+                }
+
+                for (via_road, to_road, conditions) in &road.complicated_turn_restrictions {
+                    if !applies(conditions) {
+                        continue;
+                    }
+                    for f in &from_edges {
+                        for v in edges_from.get(&(mode, edges[*f].to)).unwrap_or(&Vec::new()) {
+                            if edges[*v].road != *via_road {
+                                continue;
+                            }
+                            for t in edges_from.get(&(mode, edges[*v].to)).unwrap_or(&Vec::new()) {
+                                if edges[*t].road == *to_road {
+                                    banned_sequences.push(vec![*f, *v, *t]);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut graphs = BTreeMap::new();
+        for mode in TravelMode::all() {
+            graphs.insert(mode, DiGraphMap::new());
+        }
+        for (idx, edge) in edges.iter().enumerate() {
+            let graph = graphs.get_mut(&edge.mode).unwrap();
+            graph.add_edge(
+                Node::Departure(edge.from),
+                Node::Edge(idx),
+                edge.cost.inner_seconds(),
+            );
+            graph.add_edge(Node::Edge(idx), Node::Arrival(edge.to), 0.0);
+        }
+
+        let banned_pairs: BTreeSet<(usize, usize)> = banned_sequences
+            .iter()
+            .filter(|seq| seq.len() == 2)
+            .map(|seq| (seq[0], seq[1]))
+            .collect();
+        for (idx, edge) in edges.iter().enumerate() {
+            let graph = graphs.get_mut(&edge.mode).unwrap();
+            for next in edges_from.get(&(edge.mode, edge.to)).unwrap_or(&Vec::new()) {
+                if !banned_pairs.contains(&(idx, *next)) {
+                    graph.add_edge(
+                        Node::Edge(idx),
+                        Node::Edge(*next),
+                        edges[*next].cost.inner_seconds(),
+                    );
+                }
+            }
+        }
+
+        // For restrictions via a road, coming from the first edge leads to a copy of the second
+        // edge, which can't turn onto the third
+        let mut via_bans: BTreeMap<(usize, usize), BTreeSet<usize>> = BTreeMap::new();
+        for seq in &banned_sequences {
+            if seq.len() == 3 {
+                via_bans.entry((seq[0], seq[1])).or_default().insert(seq[2]);
+            }
+        }
+        for ((first, second), banned) in via_bans {
+            let graph = graphs.get_mut(&edges[first].mode).unwrap();
+            if graph
+                .remove_edge(Node::Edge(first), Node::Edge(second))
+                .is_none()
+            {
+                // A simple restriction already bans this
+                continue;
+            }
+            let via = Node::Via(first, second);
+            graph.add_edge(Node::Edge(first), via, edges[second].cost.inner_seconds());
+            graph.add_edge(via, Node::Arrival(edges[second].to), 0.0);
+            let next_edges: Vec<(Node, f64)> = graph
+                .edges(Node::Edge(second))
+                .filter_map(|(_, next, cost)| match next {
+                    Node::Edge(idx) if !banned.contains(&idx) => Some((next, *cost)),
+                    _ => None,
+                })
+                .collect();
+            for (next, cost) in next_edges {
+                graph.add_edge(via, next, cost);
+            }
+        }
+
+        Self {
+            edges,
+            banned_sequences,
+            graphs,
+        }
+    }
+
+    /// Finds the fastest route between two intersections for some mode. Returns the roads crossed
+    /// (and which direction) and the total cost.
+    pub fn route(
+        &self,
+        mode: TravelMode,
+        from: osm::NodeID,
+        to: osm::NodeID,
+    ) -> Option<(Vec<(OriginalRoad, Direction)>, Duration)> {
+        let graph = &self.graphs[&mode];
+        let (cost, path) = petgraph::algo::astar(
+            graph,
+            Node::Departure(from),
+            |node| node == Node::Arrival(to),
+            |(_, _, cost)| *cost,
+            |_| 0.0,
+        )?;
+        let steps = path
+            .into_iter()
+            .filter_map(|node| match node {
+                Node::Edge(idx) | Node::Via(_, idx) => {
+                    Some((self.edges[idx].road, self.edges[idx].dir))
+                }
+                Node::Departure(_) | Node::Arrival(_) => None,
+            })
+            .collect();
+        Some((steps, Duration::seconds(cost)))
+    }
+
+    /// Writes `edges.csv` and `restrictions.csv` to a directory. Each edge has an ID, referenced
+    /// by the restrictions. Restrictions list a sequence of 2 or 3 edges that can't be followed.
+    /// The road of an edge is identified by `osm_way_id`, `src_i`, and `dst_i`, like
+    /// `OriginalRoad`.
+    pub fn save_csv(&self, output_dir: &str) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+
+        let mut file = File::create(Path::new(output_dir).join("edges.csv"))?;
+        writeln!(
+            file,
+            "edge_id,mode,osm_way_id,src_i,dst_i,from_node,to_node,direction,length_meters,cost_seconds"
+        )?;
+        for (idx, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                file,
+                "{},{:?},{},{},{},{},{},{:?},{},{}",
+                idx,
+                edge.mode,
+                edge.road.osm_way_id.0,
+                edge.road.i1.0,
+                edge.road.i2.0,
+                edge.from.0,
+                edge.to.0,
+                edge.dir,
+                edge.length.inner_meters(),
+                edge.cost.inner_seconds()
+            )?;
+        }
+
+        let mut file = File::create(Path::new(output_dir).join("restrictions.csv"))?;
+        writeln!(file, "from_edge,via_edge,to_edge")?;
+        for seq in &self.banned_sequences {
+            if seq.len() == 2 {
+                writeln!(file, "{},,{}", seq[0], seq[1])?;
+            } else {
+                writeln!(file, "{},{},{}", seq[0], seq[1], seq[2])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// How fast a mode crosses a road in one direction
+pub(crate) fn travel_speed(
+    streets: &StreetNetwork,
+    road: &Road,
+    dir: Direction,
+    mode: TravelMode,
+) -> Speed {
+    // Where there's no limit, assume people drive at the advisory speed or a typical one
+    let limit = road
+        .speed_limit
+        .get(dir)
+        .or(road.speed_limit.advisory)
+        .unwrap_or_else(|| streets.config.default_speed_limits.for_road(&road.osm_tags));
+    match mode.max_speed() {
+        Some(max) if max < limit => max,
+        _ => limit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+
+    // A straight road from 1 to 3 through 2, then a turn at 3 to 4. There's a side road from 2 to
+    // 5.
+    fn network() -> (StreetNetwork, [OriginalRoad; 4]) {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 0.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        add_intersection(&mut streets, 4, 200.0, 100.0);
+        add_intersection(&mut streets, 5, 100.0, 100.0);
+        let a = add_road(&mut streets, 10, 1, 2, &[]);
+        let b = add_road(&mut streets, 11, 2, 3, &[]);
+        let c = add_road(&mut streets, 12, 3, 4, &[]);
+        let side = add_road(&mut streets, 13, 2, 5, &[]);
+        (streets, [a, b, c, side])
+    }
+
+    fn route_roads(
+        graph: &RoutingGraph,
+        mode: TravelMode,
+        from: i64,
+        to: i64,
+    ) -> Option<Vec<(OriginalRoad, Direction)>> {
+        graph
+            .route(mode, osm::NodeID(from), osm::NodeID(to))
+            .map(|(steps, _)| steps)
+    }
+
+    #[test]
+    fn test_route() {
+        let (mut streets, [a, b, c, _]) = network();
+        let graph = RoutingGraph::new(&streets);
+        // Every road can be crossed both ways by every mode
+        assert_eq!(graph.edges.len(), 4 * 2 * TravelMode::all().len());
+        assert!(graph.banned_sequences.is_empty());
+
+        let (steps, cost) = graph
+            .route(TravelMode::Driving, osm::NodeID(1), osm::NodeID(4))
+            .unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                (a, Direction::Fwd),
+                (b, Direction::Fwd),
+                (c, Direction::Fwd)
+            ]
+        );
+        assert!(cost > Duration::ZERO);
+        // Walking the same way is slower
+        let (_, walking_cost) = graph
+            .route(TravelMode::Walking, osm::NodeID(1), osm::NodeID(4))
+            .unwrap();
+        assert!(walking_cost > cost);
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 3, 1),
+            Some(vec![(b, Direction::Back), (a, Direction::Back)])
+        );
+
+        // One-way roads can only be driven one way, but pedestrians can still walk both ways
+        let road = streets.roads.remove(&b).unwrap();
+        let mut tags = road.osm_tags.clone();
+        tags.insert("oneway", "yes");
+        streets.roads.insert(
+            b,
+            Road::new(road.osm_center_points.clone(), tags, &streets.config).unwrap(),
+        );
+        let graph = RoutingGraph::new(&streets);
+        assert!(route_roads(&graph, TravelMode::Driving, 3, 1).is_none());
+        assert!(route_roads(&graph, TravelMode::Driving, 1, 3).is_some());
+        assert!(route_roads(&graph, TravelMode::Walking, 3, 1).is_some());
+    }
+
+    #[test]
+    fn test_restrictions() {
+        let (mut streets, [a, b, c, side]) = network();
+        streets.roads.get_mut(&a).unwrap().turn_restrictions.push((
+            RestrictionType::BanTurns,
+            side,
+            RestrictionConditions::default(),
+        ));
+        streets
+            .roads
+            .get_mut(&a)
+            .unwrap()
+            .complicated_turn_restrictions
+            .push((b, c, RestrictionConditions::default()));
+        let graph = RoutingGraph::new(&streets);
+
+        // U-turns are allowed, so the banned movements can be avoided by turning around
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 1, 5),
+            Some(vec![
+                (a, Direction::Fwd),
+                (b, Direction::Fwd),
+                (b, Direction::Back),
+                (side, Direction::Fwd)
+            ])
+        );
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 1, 4),
+            Some(vec![
+                (a, Direction::Fwd),
+                (b, Direction::Fwd),
+                (b, Direction::Back),
+                (b, Direction::Fwd),
+                (c, Direction::Fwd)
+            ])
+        );
+        // The restriction via b only applies when coming from a
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 1, 3),
+            Some(vec![(a, Direction::Fwd), (b, Direction::Fwd)])
+        );
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 2, 4),
+            Some(vec![(b, Direction::Fwd), (c, Direction::Fwd)])
+        );
+        // Pedestrians ignore turn restrictions
+        assert_eq!(
+            route_roads(&graph, TravelMode::Walking, 1, 5),
+            Some(vec![(a, Direction::Fwd), (side, Direction::Fwd)])
+        );
+
+        // Restrictions that exempt cyclists don't affect them
+        streets.roads.get_mut(&a).unwrap().turn_restrictions[0].2 = RestrictionConditions {
+            except: vec![VehicleType::Bicycle],
+            ..Default::default()
+        };
+        let graph = RoutingGraph::new(&streets);
+        assert_eq!(
+            route_roads(&graph, TravelMode::Driving, 1, 5)
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(
+            route_roads(&graph, TravelMode::Biking, 1, 5),
+            Some(vec![(a, Direction::Fwd), (side, Direction::Fwd)])
+        );
+    }
+
+    #[test]
+    fn test_save_csv() {
+        let (streets, [a, ..]) = network();
+        let graph = RoutingGraph::new(&streets);
+        let dir = std::env::temp_dir().join("osm2streets_test_save_csv");
+        let dir = dir.to_str().unwrap();
+        graph.save_csv(dir).unwrap();
+
+        let edges = std::fs::read_to_string(format!("{dir}/edges.csv")).unwrap();
+        let mut lines = edges.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "edge_id,mode,osm_way_id,src_i,dst_i,from_node,to_node,direction,length_meters,cost_seconds"
+        );
+        // The road going backwards is still identified by its own endpoints
+        let idx = graph
+            .edges
+            .iter()
+            .position(|edge| {
+                edge.road == a && edge.mode == TravelMode::Driving && edge.dir == Direction::Back
+            })
+            .unwrap();
+        assert!(lines
+            .nth(idx)
+            .unwrap()
+            .starts_with(&format!("{idx},Driving,10,1,2,2,1,Back,")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::BTreeMap;
+
+use abstutil::Tags;
+use geom::Speed;
+use serde::{Deserialize, Serialize};
+
+use crate::{osm, Direction, LaneSpec, MapConfig};
+
+/// The speed limit along a road, parsed from OSM tags or assumed from the type of road. The
+/// default leaves every limit unset with `SpeedLimitSource::Default`, meaning they're unknown, like
+/// for roads saved before speed limits were parsed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeedLimit {
+    /// For traffic going forwards. `None` means there's explicitly no limit, like on parts of
+    /// German motorways.
+    pub forward: Option<Speed>,
+    /// For traffic going backwards
+    pub backward: Option<Speed>,
+    /// A recommended speed below the limit, like before a sharp curve
+    pub advisory: Option<Speed>,
+    /// If only one direction is tagged, the other one may still be a default.
+    pub source: SpeedLimitSource,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedLimitSource {
+    /// A number or `none` is tagged
+    Tagged,
+    /// The general limit in a zone like `DE:urban`, from `maxspeed` or `maxspeed:type`
+    Zone,
+    /// Nothing is tagged, so this is the default from `MapConfig` for the type of road
+    #[default]
+    Default,
+}
+
+// Schrittgeschwindigkeit is usually taken to be somewhere from 4 to 7 km/h
+const WALKING_PACE: f64 = 7.0;
+
+impl SpeedLimit {
+    pub fn from_tags(tags: &Tags, cfg: &MapConfig) -> Self {
+        let (limit, source) = tags
+            .get(osm::MAXSPEED)
+            .and_then(|value| parse_maxspeed(value))
+            .or_else(|| {
+                ["maxspeed:type", "source:maxspeed", "zone:maxspeed"]
+                    .into_iter()
+                    .find_map(|key| tags.get(key).and_then(|value| parse_zone(value)))
+                    .map(|limit| (limit, SpeedLimitSource::Zone))
+            })
+            .unwrap_or_else(|| {
+                (
+                    Some(cfg.default_speed_limits.for_road(tags)),
+                    SpeedLimitSource::Default,
+                )
+            });
+        let mut result = SpeedLimit {
+            forward: limit,
+            backward: limit,
+            advisory: tags
+                .get("maxspeed:advisory")
+                .and_then(|value| parse_maxspeed(value))
+                .and_then(|(limit, _)| limit),
+            source,
+        };
+
+        for (key, dir) in [
+            ("maxspeed:forward", Direction::Fwd),
+            ("maxspeed:backward", Direction::Back),
+        ] {
+            if let Some((limit, source)) = tags.get(key).and_then(|value| parse_maxspeed(value)) {
+                if dir == Direction::Fwd {
+                    result.forward = limit;
+                } else {
+                    result.backward = limit;
+                }
+                if result.source == SpeedLimitSource::Default {
+                    result.source = source;
+                }
+            }
+        }
+        result
+    }
+
+    /// The limit for traffic going one way along the road. `None` if there's no limit.
+    pub fn get(&self, dir: Direction) -> Option<Speed> {
+        match dir {
+            Direction::Fwd => self.forward,
+            Direction::Back => self.backward,
+        }
+    }
+
+    /// Combines the limits of two roads merged into one, keeping the lower limit in each
+    /// direction. `other_reversed` means the other road points the opposite way.
+    pub(crate) fn merge(&mut self, other: &SpeedLimit, other_reversed: bool) {
+        let (other_fwd, other_back) = if other_reversed {
+            (other.backward, other.forward)
+        } else {
+            (other.forward, other.backward)
+        };
+        self.forward = lower_limit(self.forward, other_fwd);
+        self.backward = lower_limit(self.backward, other_back);
+        self.advisory = match (self.advisory, other.advisory) {
+            (Some(x), Some(y)) => Some(if x < y { x } else { y }),
+            (x, y) => x.or(y),
+        };
+        // Keep the most specific source
+        if other.source == SpeedLimitSource::Tagged
+            || (other.source == SpeedLimitSource::Zone && self.source == SpeedLimitSource::Default)
+        {
+            self.source = other.source;
+        }
+    }
+}
+
+// `None` means no limit, so the other one is lower
+fn lower_limit(x: Option<Speed>, y: Option<Speed>) -> Option<Speed> {
+    match (x, y) {
+        (Some(x), Some(y)) => Some(if x < y { x } else { y }),
+        (x, None) => x,
+        (None, y) => y,
+    }
+}
+
+/// Speed limits assumed for roads without any `maxspeed` tagged, by the type of road.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpeedLimitDefaults {
+    /// Keyed by `highway`. Links like `primary_link` use the limit of the road they link.
+    pub per_highway: BTreeMap<String, Speed>,
+    /// For every other type of road
+    pub other: Speed,
+}
+
+impl Default for SpeedLimitDefaults {
+    fn default() -> Self {
+        Self::generic()
+    }
+}
+
+impl SpeedLimitDefaults {
+    /// Rough guesses that don't assume any particular country.
+    pub fn generic() -> Self {
+        Self::km_per_hour(
+            &[
+                ("motorway", 100.0),
+                ("trunk", 80.0),
+                ("primary", 60.0),
+                ("secondary", 50.0),
+                ("tertiary", 50.0),
+                ("service", 20.0),
+                ("living_street", 20.0),
+            ],
+            30.0,
+        )
+    }
+
+    /// Typical limits in one country, identified by its ISO 3166-1 alpha-2 code, like "DE". Roads
+    /// can be urban or rural, so these're only coarse. `None` if there's no table for the country.
+    pub fn for_country(code: &str) -> Option<Self> {
+        match code {
+            // Germany has no general motorway limit; 130 km/h is the recommended speed
+            "DE" => Some(Self::km_per_hour(
+                &[
+                    ("motorway", 130.0),
+                    ("trunk", 100.0),
+                    ("primary", 70.0),
+                    ("secondary", 70.0),
+                    ("tertiary", 50.0),
+                    ("residential", 30.0),
+                    ("service", 20.0),
+                    ("living_street", WALKING_PACE),
+                ],
+                50.0,
+            )),
+            "FR" => Some(Self::km_per_hour(
+                &[
+                    ("motorway", 130.0),
+                    ("trunk", 110.0),
+                    ("primary", 80.0),
+                    ("secondary", 80.0),
+                    ("service", 20.0),
+                    ("living_street", 20.0),
+                ],
+                50.0,
+            )),
+            "GB" => Some(Self::miles_per_hour(
+                &[
+                    ("motorway", 70.0),
+                    ("trunk", 60.0),
+                    ("primary", 40.0),
+                    ("service", 10.0),
+                    ("living_street", 10.0),
+                ],
+                30.0,
+            )),
+            "US" => Some(Self::miles_per_hour(
+                &[
+                    ("motorway", 65.0),
+                    ("trunk", 55.0),
+                    ("primary", 45.0),
+                    ("secondary", 35.0),
+                    ("tertiary", 30.0),
+                    ("service", 15.0),
+                    ("living_street", 15.0),
+                ],
+                25.0,
+            )),
+            _ => None,
+        }
+    }
+
+    /// The limit assumed for a road with these tags.
+    pub fn for_road(&self, tags: &Tags) -> Speed {
+        tags.get(osm::HIGHWAY)
+            .and_then(|highway| self.per_highway.get(highway.trim_end_matches("_link")))
+            .copied()
+            .unwrap_or(self.other)
+    }
+
+    fn km_per_hour(per_highway: &[(&str, f64)], other: f64) -> Self {
+        Self {
+            per_highway: per_highway
+                .iter()
+                .map(|(highway, x)| (highway.to_string(), Speed::km_per_hour(*x)))
+                .collect(),
+            other: Speed::km_per_hour(other),
+        }
+    }
+
+    fn miles_per_hour(per_highway: &[(&str, f64)], other: f64) -> Self {
+        Self {
+            per_highway: per_highway
+                .iter()
+                .map(|(highway, x)| (highway.to_string(), Speed::miles_per_hour(*x)))
+                .collect(),
+            other: Speed::miles_per_hour(other),
+        }
+    }
+}
+
+impl LaneSpec {
+    /// Fills out `speed_limit` from `maxspeed:lanes`, `maxspeed:lanes:forward`, and
+    /// `maxspeed:lanes:backward`.
+    pub(crate) fn parse_lane_speed_limits(lanes_ltr: &mut [LaneSpec], tags: &Tags) {
+        for (idx, part) in LaneSpec::lanes_per_direction(lanes_ltr, tags, "maxspeed:lanes") {
+            lanes_ltr[idx].speed_limit = parse_maxspeed(part).and_then(|(limit, _)| limit);
+        }
+    }
+}
+
+/// Parses a `maxspeed` value: a number with optional units (`km/h` by default, `mph`, or
+/// `knots`), `none`, `walk`, or a zone like `DE:urban`. The outer `None` means the value isn't
+/// understood, like `signals`. The inner one means there's no limit.
+fn parse_maxspeed(value: &str) -> Option<(Option<Speed>, SpeedLimitSource)> {
+    let value = value.trim();
+    if value == "none" {
+        return Some((None, SpeedLimitSource::Tagged));
+    }
+    if value == "walk" {
+        return Some((
+            Some(Speed::km_per_hour(WALKING_PACE)),
+            SpeedLimitSource::Tagged,
+        ));
+    }
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    if let Ok(number) = number.parse::<f64>() {
+        if number <= 0.0 {
+            return None;
+        }
+        let speed = match unit.trim() {
+            "" | "km/h" | "kmh" | "kph" => Speed::km_per_hour(number),
+            "mph" => Speed::miles_per_hour(number),
+            "knots" => Speed::km_per_hour(number * 1.852),
+            _ => return None,
+        };
+        return Some((Some(speed), SpeedLimitSource::Tagged));
+    }
+    parse_zone(value).map(|limit| (limit, SpeedLimitSource::Zone))
+}
+
+// The general limit in a zone like `DE:urban` or `DE:zone30`. Same return value as
+// parse_maxspeed.
+fn parse_zone(value: &str) -> Option<Option<Speed>> {
+    let (country, zone) = value.trim().split_once(':')?;
+    let imperial = matches!(country, "GB" | "US");
+    let limit = |x: f64| {
+        Some(Some(if imperial {
+            Speed::miles_per_hour(x)
+        } else {
+            Speed::km_per_hour(x)
+        }))
+    };
+
+    // The limit is in the name of the zone, like `DE:zone30`, or `zone:maxspeed` is often just the
+    // limit, like `DE:30`
+    let number = zone
+        .strip_prefix("zone")
+        .map(|x| x.trim_start_matches(':'))
+        .unwrap_or(zone);
+    if let Ok(number) = number.parse::<f64>() {
+        return if number > 0.0 { limit(number) } else { None };
+    }
+    match (country, zone) {
+        (_, "living_street") => limit(WALKING_PACE),
+        ("AT", "urban") => limit(50.0),
+        ("AT", "rural") => limit(100.0),
+        ("AT", "motorway") => limit(130.0),
+        ("CH", "urban") => limit(50.0),
+        ("CH", "rural") => limit(80.0),
+        ("CH", "trunk") => limit(100.0),
+        ("CH", "motorway") => limit(120.0),
+        ("DE", "urban") => limit(50.0),
+        ("DE", "rural") => limit(100.0),
+        ("DE", "motorway") => Some(None),
+        ("DE", "bicycle_road") => limit(30.0),
+        ("FR", "urban") => limit(50.0),
+        ("FR", "rural") => limit(80.0),
+        ("FR", "motorway") => limit(130.0),
+        ("GB", "nsl_restricted") => limit(30.0),
+        ("GB", "nsl_single") => limit(60.0),
+        ("GB", "nsl_dual") => limit(70.0),
+        ("GB", "motorway") => limit(70.0),
+        ("IT", "urban") => limit(50.0),
+        ("IT", "rural") => limit(90.0),
+        ("IT", "motorway") => limit(130.0),
+        ("NL", "urban") => limit(50.0),
+        ("NL", "rural") => limit(80.0),
+        ("NL", "motorway") => limit(100.0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_maxspeed() {
+        let kmh = |x| Some((Some(Speed::km_per_hour(x)), SpeedLimitSource::Tagged));
+        assert_eq!(parse_maxspeed("50"), kmh(50.0));
+        assert_eq!(parse_maxspeed("50 km/h"), kmh(50.0));
+        assert_eq!(parse_maxspeed("92.6"), kmh(92.6));
+        assert_eq!(
+            parse_maxspeed("30 mph"),
+            Some((Some(Speed::miles_per_hour(30.0)), SpeedLimitSource::Tagged))
+        );
+        assert_eq!(
+            parse_maxspeed("none"),
+            Some((None, SpeedLimitSource::Tagged))
+        );
+        assert_eq!(
+            parse_maxspeed("DE:urban"),
+            Some((Some(Speed::km_per_hour(50.0)), SpeedLimitSource::Zone))
+        );
+        assert_eq!(
+            parse_maxspeed("GB:nsl_single"),
+            Some((Some(Speed::miles_per_hour(60.0)), SpeedLimitSource::Zone))
+        );
+        assert_eq!(
+            parse_maxspeed("DE:zone30"),
+            Some((Some(Speed::km_per_hour(30.0)), SpeedLimitSource::Zone))
+        );
+        assert_eq!(
+            parse_maxspeed("DE:30"),
+            Some((Some(Speed::km_per_hour(30.0)), SpeedLimitSource::Zone))
+        );
+        assert_eq!(
+            parse_maxspeed("GB:20"),
+            Some((Some(Speed::miles_per_hour(20.0)), SpeedLimitSource::Zone))
+        );
+        assert_eq!(
+            parse_maxspeed("DE:motorway"),
+            Some((None, SpeedLimitSource::Zone))
+        );
+        assert_eq!(parse_maxspeed("signals"), None);
+        assert_eq!(parse_maxspeed("XX:urban"), None);
+        assert_eq!(parse_maxspeed("0"), None);
+    }
+
+    #[test]
+    fn test_speed_limit_from_tags() {
+        let tags = |pairs: &[(&str, &str)]| {
+            let mut tags = Tags::empty();
+            for (k, v) in pairs {
+                tags.insert(*k, *v);
+            }
+            tags
+        };
+        let mut cfg = MapConfig::default_for_side(crate::DrivingSide::Right);
+
+        let limit = SpeedLimit::from_tags(
+            &tags(&[
+                ("highway", "primary"),
+                ("maxspeed", "50"),
+                ("maxspeed:backward", "30"),
+            ]),
+            &cfg,
+        );
+        assert_eq!(limit.get(Direction::Fwd), Some(Speed::km_per_hour(50.0)));
+        assert_eq!(limit.get(Direction::Back), Some(Speed::km_per_hour(30.0)));
+        assert_eq!(limit.source, SpeedLimitSource::Tagged);
+
+        let limit = SpeedLimit::from_tags(
+            &tags(&[("highway", "residential"), ("maxspeed:type", "DE:urban")]),
+            &cfg,
+        );
+        assert_eq!(limit.forward, Some(Speed::km_per_hour(50.0)));
+        assert_eq!(limit.source, SpeedLimitSource::Zone);
+
+        // Nothing tagged, so it depends on the country
+        let untagged = tags(&[("highway", "residential")]);
+        assert_eq!(
+            SpeedLimit::from_tags(&untagged, &cfg).forward,
+            Some(Speed::km_per_hour(30.0))
+        );
+        cfg.default_speed_limits = SpeedLimitDefaults::for_country("US").unwrap();
+        let limit = SpeedLimit::from_tags(&untagged, &cfg);
+        assert_eq!(limit.forward, Some(Speed::miles_per_hour(25.0)));
+        assert_eq!(limit.source, SpeedLimitSource::Default);
+    }
+}
@@ -0,0 +1,59 @@
+//! Helpers to build tiny networks for unit tests
+
+use abstutil::Tags;
+use geom::Pt2D;
+
+use crate::{
+    osm, ControlType, Intersection, IntersectionComplexity, OriginalRoad, Road, StreetNetwork,
+};
+
+/// Adds an uncontrolled intersection at (x, y)
+pub fn add_intersection(streets: &mut StreetNetwork, id: i64, x: f64, y: f64) -> osm::NodeID {
+    let i = osm::NodeID(id);
+    streets.intersections.insert(
+        i,
+        Intersection::new(
+            Pt2D::new(x, y),
+            IntersectionComplexity::Crossing,
+            ControlType::Uncontrolled,
+        ),
+    );
+    i
+}
+
+/// Adds a straight road between two existing intersections. It's `highway=residential` unless
+/// `tags` say otherwise.
+pub fn add_road(
+    streets: &mut StreetNetwork,
+    way: i64,
+    i1: i64,
+    i2: i64,
+    tags: &[(&str, &str)],
+) -> OriginalRoad {
+    add_road_via(streets, way, i1, i2, &[], tags)
+}
+
+/// Like `add_road`, but the center line bends through `via` points
+pub fn add_road_via(
+    streets: &mut StreetNetwork,
+    way: i64,
+    i1: i64,
+    i2: i64,
+    via: &[(f64, f64)],
+    tags: &[(&str, &str)],
+) -> OriginalRoad {
+    let id = OriginalRoad::new(way, (i1, i2));
+    let mut pts = vec![streets.intersections[&id.i1].point];
+    pts.extend(via.iter().map(|(x, y)| Pt2D::new(*x, *y)));
+    pts.push(streets.intersections[&id.i2].point);
+
+    let mut osm_tags = Tags::empty();
+    osm_tags.insert(osm::HIGHWAY, "residential");
+    osm_tags.insert(osm::OSM_WAY_ID, way.to_string());
+    for (k, v) in tags {
+        osm_tags.insert(*k, *v);
+    }
+    let road = Road::new(pts, osm_tags, &streets.config).unwrap();
+    streets.insert_road(id, road);
+    id
+}
@@ -158,28 +158,51 @@ pub fn collapse_intersection(streets: &mut StreetNetwork, i: NodeID) {
     }
 
     info!("Collapsing degenerate {}", i);
-    // We could be more careful merging percent_incline and osm_tags, but in practice, it doesn't
-    // matter for the short segments we're merging.
+    // We could be more careful merging osm_tags, but in practice, it doesn't matter for the short
+    // segments we're merging.
     let mut new_road = streets.remove_road(&r1);
     let mut road2 = streets.remove_road(&r2);
     streets.intersections.remove(&i).unwrap();
 
+    // Average the grades, weighted by length. If r2 points the other way, its grade flips.
+    let r2_reversed = r1.i2 == r2.i2 || r1.i1 == r2.i1;
+    let grade2 = if r2_reversed {
+        -road2.percent_incline
+    } else {
+        road2.percent_incline
+    };
+    let (len1, len2) = (new_road.length(), road2.length());
+    if len1 + len2 > Distance::ZERO {
+        new_road.percent_incline = (new_road.percent_incline * len1.inner_meters()
+            + grade2 * len2.inner_meters())
+            / (len1 + len2).inner_meters();
+    }
+    new_road.speed_limit.merge(&road2.speed_limit, r2_reversed);
+
     // There are 4 cases, easy to understand on paper. Preserve the original direction of r1
     let (new_i1, new_i2) = if r1.i2 == r2.i1 {
         new_road.osm_center_points.extend(road2.osm_center_points);
+        new_road.original_segments.extend(road2.original_segments);
         (r1.i1, r2.i2)
     } else if r1.i2 == r2.i2 {
         road2.osm_center_points.reverse();
         new_road.osm_center_points.extend(road2.osm_center_points);
+        road2.original_segments.reverse();
+        new_road.original_segments.extend(road2.original_segments);
         (r1.i1, r2.i1)
     } else if r1.i1 == r2.i1 {
         road2.osm_center_points.reverse();
         road2.osm_center_points.extend(new_road.osm_center_points);
         new_road.osm_center_points = road2.osm_center_points;
+        road2.original_segments.reverse();
+        road2.original_segments.extend(new_road.original_segments);
+        new_road.original_segments = road2.original_segments;
         (r2.i2, r1.i2)
     } else if r1.i1 == r2.i2 {
         road2.osm_center_points.extend(new_road.osm_center_points);
         new_road.osm_center_points = road2.osm_center_points;
+        road2.original_segments.extend(new_road.original_segments);
+        new_road.original_segments = road2.original_segments;
         (r2.i1, r1.i2)
     } else {
         unreachable!()
@@ -201,13 +224,13 @@ pub fn collapse_intersection(streets: &mut StreetNetwork, i: NodeID) {
     // We may need to fix up turn restrictions. r1 and r2 both become new_r1.
     let rewrite = |x: &OriginalRoad| *x == r1 || *x == r2;
     for road in streets.roads.values_mut() {
-        for (_, id) in &mut road.turn_restrictions {
+        for (_, id, _) in &mut road.turn_restrictions {
             if rewrite(id) {
                 *id = new_r1;
             }
         }
 
-        for (id1, id2) in &mut road.complicated_turn_restrictions {
+        for (id1, id2, _) in &mut road.complicated_turn_restrictions {
             if rewrite(id1) {
                 *id1 = new_r1;
             }
@@ -252,3 +275,85 @@ pub fn trim_deadends(streets: &mut StreetNetwork) {
     // It's possible we need to do this in a fixed-point until there are no changes, but meh.
     // Results look good so far.
 }
+
+#[cfg(test)]
+mod tests {
+    use geom::Speed;
+
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+    use crate::Direction;
+
+    #[test]
+    fn test_collapse_keeps_original_segments() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 120.0, 0.0);
+        add_intersection(&mut streets, 3, 200.0, 0.0);
+        add_intersection(&mut streets, 4, 260.0, 0.0);
+        let r1 = add_road(&mut streets, 10, 1, 2, &[]);
+        // This one points the other way
+        let r2 = add_road(&mut streets, 11, 3, 2, &[]);
+        let r3 = add_road(&mut streets, 12, 3, 4, &[]);
+
+        collapse_intersection(&mut streets, NodeID(2));
+        let merged = OriginalRoad::new(10, (1, 3));
+        assert_eq!(streets.roads[&merged].original_segments, vec![r1, r2]);
+        assert_eq!(streets.road_for_original_segment(r1), Some(merged));
+        assert_eq!(streets.road_for_original_segment(r2), Some(merged));
+        // The merged ID was never an OSM segment
+        assert_eq!(streets.road_for_original_segment(merged), None);
+
+        // Extend the merged road again
+        collapse_intersection(&mut streets, NodeID(3));
+        let merged = OriginalRoad::new(10, (1, 4));
+        assert_eq!(
+            streets.roads.keys().cloned().collect::<Vec<_>>(),
+            vec![merged]
+        );
+        assert_eq!(streets.roads[&merged].original_segments, vec![r1, r2, r3]);
+        let mapping = streets.original_segments_to_roads();
+        assert_eq!(mapping.len(), 3);
+        assert!(mapping.values().all(|r| *r == merged));
+    }
+
+    #[test]
+    fn test_collapse_merges_grades() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 300.0, 0.0);
+        add_intersection(&mut streets, 3, 400.0, 0.0);
+        let r1 = add_road(&mut streets, 10, 1, 2, &[]);
+        // This one points the other way, so going from 2 to 3 is 6% uphill
+        let r2 = add_road(&mut streets, 11, 3, 2, &[]);
+        streets.roads.get_mut(&r1).unwrap().percent_incline = 0.02;
+        streets.roads.get_mut(&r2).unwrap().percent_incline = -0.06;
+
+        collapse_intersection(&mut streets, NodeID(2));
+        let merged = &streets.roads[&OriginalRoad::new(10, (1, 3))];
+        // Climbing 6m over the first 300m and 6m over the last 100m
+        assert!((merged.percent_incline - 0.03).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_collapse_merges_speed_limits() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 300.0, 0.0);
+        add_intersection(&mut streets, 3, 400.0, 0.0);
+        add_road(&mut streets, 10, 1, 2, &[("maxspeed", "50")]);
+        // This one points the other way, so its backward limit applies going from 2 to 3
+        add_road(
+            &mut streets,
+            11,
+            3,
+            2,
+            &[("maxspeed", "40"), ("maxspeed:backward", "60")],
+        );
+
+        collapse_intersection(&mut streets, NodeID(2));
+        let limit = &streets.roads[&OriginalRoad::new(10, (1, 3))].speed_limit;
+        assert_eq!(limit.get(Direction::Fwd), Some(Speed::km_per_hour(50.0)));
+        assert_eq!(limit.get(Direction::Back), Some(Speed::km_per_hour(40.0)));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{ApproachControl, ControlType, StreetNetwork};
+
+/// Decides how every intersection without traffic signals is controlled. Stop and give way signs
+/// mapped in OSM win. Where nothing is mapped, traffic from smaller roads gives way to the biggest
+/// road at the intersection, and intersections between roads of the same rank are uncontrolled.
+pub fn infer_control(streets: &mut StreetNetwork) {
+    let mut yields = Vec::new();
+    let mut unsignalized = Vec::new();
+    for (i, intersection) in &streets.intersections {
+        if matches!(
+            intersection.control,
+            ControlType::TrafficSignal | ControlType::Border | ControlType::Construction
+        ) {
+            continue;
+        }
+        unsignalized.push(*i);
+        // Two roads meeting is just a change in road type, not a junction anyone yields at
+        if intersection.approach_controls.is_empty() && intersection.roads.len() > 2 {
+            for r in streets.minor_approaches(*i) {
+                yields.push((*i, r));
+            }
+        }
+    }
+
+    for (i, r) in yields {
+        streets.set_approach_control(i, r, ApproachControl::Yield);
+    }
+    for i in unsignalized {
+        let intersection = streets.intersections.get_mut(&i).unwrap();
+        intersection.control = match intersection.approach_controls.values().max() {
+            None => ControlType::Uncontrolled,
+            Some(ApproachControl::Yield) => ControlType::YieldSign,
+            Some(ApproachControl::Stop) => ControlType::StopSign,
+            Some(ApproachControl::TrafficSignal) => ControlType::TrafficSignal,
+        };
+    }
+}
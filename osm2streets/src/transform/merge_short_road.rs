@@ -41,12 +41,11 @@ impl StreetNetwork {
             }
         }
 
-        // TODO Fix up turn restrictions. Many cases:
-        // [ ] road we're deleting has simple restrictions
-        // [ ] road we're deleting has complicated restrictions
+        // Fix up turn restrictions. Transformation::apply rewrites IDs of roads that change
+        // afterwards, and drops restrictions that become meaningless. The cases needing special
+        // handling here:
         // [X] road we're deleting is the target of a simple BanTurns restriction
         // [ ] road we're deleting is the target of a simple OnlyAllowTurns restriction
-        // [ ] road we're deleting is the target of a complicated restriction
         // [X] road we're deleting is the 'via' of a complicated restriction
         // [ ] road we're deleting has turn lanes that wind up orphaning something
 
@@ -142,7 +141,7 @@ impl StreetNetwork {
         // If we're deleting the target of a simple restriction somewhere, update it.
         for (from_id, road) in &mut self.roads {
             let mut fix_trs = Vec::new();
-            for (rt, to) in road.turn_restrictions.drain(..) {
+            for (rt, to, conditions) in road.turn_restrictions.drain(..) {
                 if to == short && rt == RestrictionType::BanTurns {
                     // Remove this restriction, replace it with a new one to each of the successors
                     // of the deleted road. Depending if the intersection we kept is the one
@@ -155,15 +154,15 @@ impl StreetNetwork {
                         == i1
                     {
                         for x in &created {
-                            fix_trs.push((rt, *x));
+                            fix_trs.push((rt, *x, conditions.clone()));
                         }
                     } else {
                         for x in &connected_to_i1 {
-                            fix_trs.push((rt, *x));
+                            fix_trs.push((rt, *x, conditions.clone()));
                         }
                     }
                 } else {
-                    fix_trs.push((rt, to));
+                    fix_trs.push((rt, to, conditions));
                 }
             }
             road.turn_restrictions = fix_trs;
@@ -173,16 +172,17 @@ impl StreetNetwork {
         // simple restriction.
         for road in self.roads.values_mut() {
             let mut add = Vec::new();
-            road.complicated_turn_restrictions.retain(|(via, to)| {
-                if *via == short {
-                    // Depending which intersection we're deleting, the ID of 'to' might change
-                    let to_id = old_to_new.get(to).cloned().unwrap_or(*to);
-                    add.push((RestrictionType::BanTurns, to_id));
-                    false
-                } else {
-                    true
-                }
-            });
+            road.complicated_turn_restrictions
+                .retain(|(via, to, conditions)| {
+                    if *via == short {
+                        // Depending which intersection we're deleting, the ID of 'to' might change
+                        let to_id = old_to_new.get(to).cloned().unwrap_or(*to);
+                        add.push((RestrictionType::BanTurns, to_id, conditions.clone()));
+                        false
+                    } else {
+                        true
+                    }
+                });
             road.turn_restrictions.extend(add);
         }
 
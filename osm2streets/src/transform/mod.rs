@@ -1,24 +1,31 @@
 use abstutil::Timer;
 
+use crate::control::ApproachSnapshot;
+use crate::provenance::Snapshot;
+use crate::restrictions::RestrictionSnapshot;
+use crate::transit::StopSnapshot;
 use crate::StreetNetwork;
 
 pub mod classify_intersections;
 mod collapse_intersections;
 mod dual_carriageways;
 mod find_short_roads;
+mod infer_control;
 mod merge_short_road;
+mod parallel;
 mod remove_disconnected;
 mod sausage_links;
 mod separate_cycletracks;
+mod separate_sidewalks;
 mod shrink_roads;
-#[allow(unused)]
-mod snappy;
 
 /// An in-place transformation of a `StreetNetwork`.
 pub enum Transformation {
     ClassifyIntersections,
     TrimDeadendCycleways,
     SnapCycleways,
+    // Only does anything when `MapConfig::inferred_sidewalks` is false
+    SnapSidewalks,
     RemoveDisconnectedRoads,
     // TODO Move dog leg config here
     FindShortRoads { consolidate_all_intersections: bool },
@@ -27,6 +34,8 @@ pub enum Transformation {
     CollapseSausageLinks,
     ShrinkOverlappingRoads,
     MergeDualCarriageways,
+    // Without it, every intersection without traffic signals is `ControlType::Uncontrolled`
+    InferIntersectionControl,
 }
 
 impl Transformation {
@@ -36,10 +45,10 @@ impl Transformation {
     /// with that are here.
     pub fn abstreet() -> Vec<Self> {
         vec![
+            Transformation::SnapSidewalks,
             Transformation::ClassifyIntersections,
             Transformation::TrimDeadendCycleways,
-            // Not working yet
-            //Transformation::SnapCycleways,
+            Transformation::SnapCycleways,
             // More dead-ends can be created after snapping cycleways. But also, snapping can be
             // easier to do after trimming some dead-ends. So... just run it twice.
             Transformation::TrimDeadendCycleways,
@@ -54,6 +63,7 @@ impl Transformation {
             },
             Transformation::MergeShortRoads,
             Transformation::CollapseDegenerateIntersections,
+            Transformation::InferIntersectionControl,
             Transformation::ShrinkOverlappingRoads,
         ]
     }
@@ -62,14 +72,18 @@ impl Transformation {
     /// clipped areas.
     pub fn standard_for_clipped_areas() -> Vec<Self> {
         vec![
+            Transformation::SnapSidewalks,
             Transformation::ClassifyIntersections,
             Transformation::TrimDeadendCycleways,
+            Transformation::SnapCycleways,
+            Transformation::TrimDeadendCycleways,
             Transformation::CollapseSausageLinks,
             Transformation::FindShortRoads {
                 consolidate_all_intersections: false,
             },
             Transformation::MergeShortRoads,
             Transformation::CollapseDegenerateIntersections,
+            Transformation::InferIntersectionControl,
             Transformation::ShrinkOverlappingRoads,
         ]
     }
@@ -79,6 +93,7 @@ impl Transformation {
             Transformation::ClassifyIntersections => "classify intersections",
             Transformation::TrimDeadendCycleways => "trim dead-end cycleways",
             Transformation::SnapCycleways => "snap separate cycleways",
+            Transformation::SnapSidewalks => "snap separate sidewalks",
             Transformation::RemoveDisconnectedRoads => "remove disconnected roads",
             Transformation::FindShortRoads { .. } => "find short roads",
             Transformation::MergeShortRoads => "merge short roads",
@@ -86,11 +101,16 @@ impl Transformation {
             Transformation::CollapseSausageLinks => "collapse sausage links",
             Transformation::ShrinkOverlappingRoads => "shrink overlapping roads",
             Transformation::MergeDualCarriageways => "merge dual carriageways",
+            Transformation::InferIntersectionControl => "infer intersection control",
         }
     }
 
     fn apply(&self, streets: &mut StreetNetwork, timer: &mut Timer) {
         timer.start(self.name());
+        let before = streets.provenance.as_ref().map(|_| Snapshot::new(streets));
+        let restrictions = RestrictionSnapshot::new(streets);
+        let stops = StopSnapshot::new(streets);
+        let approaches = ApproachSnapshot::new(streets);
         match self {
             Transformation::ClassifyIntersections => {
                 classify_intersections::classify_intersections(streets);
@@ -101,6 +121,9 @@ impl Transformation {
             Transformation::SnapCycleways => {
                 separate_cycletracks::snap_cycleways(streets);
             }
+            Transformation::SnapSidewalks => {
+                separate_sidewalks::snap_sidewalks(streets);
+            }
             Transformation::RemoveDisconnectedRoads => {
                 remove_disconnected::remove_disconnected_roads(streets);
             }
@@ -124,6 +147,16 @@ impl Transformation {
             Transformation::MergeDualCarriageways => {
                 dual_carriageways::merge(streets);
             }
+            Transformation::InferIntersectionControl => {
+                infer_control::infer_control(streets);
+            }
+        }
+        restrictions.fix(self.name(), streets);
+        stops.fix(streets);
+        approaches.fix(streets);
+        if let Some(before) = before {
+            let record = before.diff(self.name(), streets);
+            streets.provenance.as_mut().unwrap().steps.push(record);
         }
         timer.stop(self.name());
     }
@@ -139,6 +172,8 @@ impl StreetNetwork {
         for transformation in transformations {
             transformation.apply(self, timer);
         }
+        // Roads may have moved, so find the road each building faces again
+        self.link_building_frontages();
         timer.stop("simplify StreetNetwork");
     }
 
@@ -158,6 +193,7 @@ impl StreetNetwork {
             // first
             self.start_debug_step(transformation.name());
         }
+        self.link_building_frontages();
         timer.stop("simplify StreetNetwork");
     }
 }
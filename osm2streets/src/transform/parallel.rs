@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use geom::{Distance, FindClosest, PolyLine};
+
+use crate::{OriginalRoad, StreetNetwork};
+
+// How far apart to check points along a way
+const STEP_SIZE: Distance = Distance::const_meters(5.0);
+// Where to look for roads around a way. This has to cover half of the widest roads too.
+const SEARCH_DIST: Distance = Distance::const_meters(30.0);
+// How many degrees difference to consider parallel ways
+const PARALLEL_THRESHOLD: f64 = 30.0;
+
+/// One point where a separately mapped way runs alongside a road
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub road: OriginalRoad,
+    /// Is the way on the right side of the road?
+    pub on_right: bool,
+    /// Does the way point the same direction as the road?
+    pub same_direction: bool,
+    pub dist_along: Distance,
+}
+
+/// Matches separately mapped ways, like sidewalks and cycletracks, to the driveable roads they run
+/// alongside.
+pub struct ParallelRoads {
+    center_lines: BTreeMap<OriginalRoad, PolyLine>,
+    closest: FindClosest<OriginalRoad>,
+}
+
+impl ParallelRoads {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        let mut center_lines = BTreeMap::new();
+        let mut closest = FindClosest::new(&streets.gps_bounds.to_bounds());
+        for (id, road) in &streets.roads {
+            if !road.is_driveable() {
+                continue;
+            }
+            let pl = road.untrimmed_road_geometry().0;
+            closest.add(*id, pl.points());
+            center_lines.insert(*id, pl);
+        }
+        Self {
+            center_lines,
+            closest,
+        }
+    }
+
+    /// Walks along `center` and finds the closest parallel road beside each step. The way may be
+    /// up to `max_gap` from the edge of the road. Returns nothing if less than `min_pct` of the
+    /// steps are beside a road.
+    pub fn match_way(
+        &self,
+        streets: &StreetNetwork,
+        center: &PolyLine,
+        layer: Option<&String>,
+        max_gap: Distance,
+        min_pct: f64,
+    ) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let mut steps = 0;
+        let mut dist = Distance::ZERO;
+        loop {
+            steps += 1;
+            let (pt, way_angle) = center.must_dist_along(dist);
+            for (r, _, _) in self.closest.all_close_pts(pt, SEARCH_DIST) {
+                let road = &streets.roads[&r];
+                // A way can't belong to a road at a different height
+                if road.osm_tags.get("layer") != layer {
+                    continue;
+                }
+                let pl = &self.center_lines[&r];
+                let projected = pl.project_pt(pt);
+                let (dist_along, road_angle) = match pl.dist_along_of_point(projected) {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+                // Past the end of the road, the way is probably wrapping around a corner
+                if dist_along == Distance::ZERO || dist_along == pl.length() {
+                    continue;
+                }
+                if !road_angle.approx_parallel(way_angle, PARALLEL_THRESHOLD)
+                    || projected.dist_to(pt) > road.total_width() / 2.0 + max_gap
+                {
+                    continue;
+                }
+
+                let mut diff =
+                    projected.angle_to(pt).normalized_degrees() - road_angle.normalized_degrees();
+                if diff > 180.0 {
+                    diff -= 360.0;
+                } else if diff <= -180.0 {
+                    diff += 360.0;
+                }
+                hits.push(Hit {
+                    road: r,
+                    // Angles increase clockwise, so a positive difference means the way is on the
+                    // right
+                    on_right: diff > 0.0,
+                    same_direction: road_angle.approx_eq(way_angle, PARALLEL_THRESHOLD),
+                    dist_along,
+                });
+                // Stop at the closest hit
+                break;
+            }
+
+            if dist == center.length() {
+                break;
+            }
+            dist = (dist + STEP_SIZE).min(center.length());
+        }
+
+        let pct = (hits.len() as f64) / (steps as f64);
+        if pct < min_pct {
+            return Vec::new();
+        }
+        hits
+    }
+
+    /// The sides of roads, as (road, is it the right side), that `hits` cover at least `min_pct`
+    /// of. Where a way is interrupted at an intersection, the road segments next to a covered side
+    /// might only be touched a little, so any of those with hits on the same side are included
+    /// too.
+    pub fn covered_sides(
+        &self,
+        streets: &StreetNetwork,
+        hits: &[Hit],
+        min_pct: f64,
+    ) -> BTreeSet<(OriginalRoad, bool)> {
+        let mut dists: BTreeMap<(OriginalRoad, bool), Vec<Distance>> = BTreeMap::new();
+        for hit in hits {
+            dists
+                .entry((hit.road, hit.on_right))
+                .or_default()
+                .push(hit.dist_along);
+        }
+
+        let mut covered = BTreeSet::new();
+        for (key, dists) in &dists {
+            let min = dists.iter().min().cloned().unwrap();
+            let max = dists.iter().max().cloned().unwrap();
+            if (max - min + STEP_SIZE) / self.center_lines[&key.0].length() >= min_pct {
+                covered.insert(*key);
+            }
+        }
+
+        let mut neighbors = Vec::new();
+        for (r, on_right) in dists.keys() {
+            if covered.contains(&(*r, *on_right)) {
+                continue;
+            }
+            let touches_covered = [r.i1, r.i2].into_iter().any(|i| {
+                streets
+                    .roads_per_intersection(i)
+                    .into_iter()
+                    .filter(|other| other != r)
+                    .any(|other| {
+                        // If both roads point into or out of the intersection, the sides flip
+                        let same_orientation = (r.i2 == i) == (other.i1 == i);
+                        covered.contains(&(other, *on_right == same_orientation))
+                    })
+            });
+            if touches_covered {
+                neighbors.push((*r, *on_right));
+            }
+        }
+        covered.extend(neighbors);
+        covered
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::BTreeSet;
 
 use crate::{
     osm, BufferType, Direction, DrivingSide, LaneSpec, LaneType, OriginalRoad, StreetNetwork,
+    WidthSource,
 };
 
 /// Find dual carriageways that split very briefly and then re-join, with no intermediate roads.
@@ -117,6 +118,9 @@ fn fix(streets: &mut StreetNetwork, id1: OriginalRoad, id2: OriginalRoad) {
                 lt: LaneType::Buffer(BufferType::Curb),
                 dir: Direction::Fwd,
                 width: LaneSpec::typical_lane_width(LaneType::Buffer(BufferType::Curb)),
+                allowed_turns: BTreeSet::new(),
+                width_source: WidthSource::Default,
+                speed_limit: None,
             },
         );
 
@@ -137,6 +141,9 @@ fn fix(streets: &mut StreetNetwork, id1: OriginalRoad, id2: OriginalRoad) {
             lt: LaneType::Buffer(BufferType::Curb),
             dir: Direction::Fwd,
             width: LaneSpec::typical_lane_width(LaneType::Buffer(BufferType::Curb)),
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
         });
 
         for mut lane in road2.lane_specs_ltr {
@@ -152,11 +159,44 @@ fn fix(streets: &mut StreetNetwork, id1: OriginalRoad, id2: OriginalRoad) {
     // here and only see the original road1.
 
     // IDs
-    // TODO The IDs in StreetNetwork are based on original OSM IDs, but they diverge as we make
-    // transformations like this. We could consider some combination of assigning new IDs all the
-    // time and associating one Road with multiple OSM IDs.
+    //
+    // road1 keeps its ID, but remember that road2's OSM segments are now part of it.
+    road1.original_segments.extend(road2.original_segments);
 }
 
 fn into_set<T: Ord>(list: Vec<T>) -> BTreeSet<T> {
     list.into_iter().collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road, add_road_via};
+
+    #[test]
+    fn test_sausage_link_keeps_original_segments() {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, 100.0, 0.0);
+        add_intersection(&mut streets, 3, -100.0, 0.0);
+        add_intersection(&mut streets, 4, 200.0, 0.0);
+        let fwd = add_road_via(&mut streets, 10, 1, 2, &[(50.0, 5.0)], &[("oneway", "yes")]);
+        let back = add_road_via(
+            &mut streets,
+            11,
+            2,
+            1,
+            &[(50.0, -5.0)],
+            &[("oneway", "yes")],
+        );
+        add_road(&mut streets, 20, 3, 1, &[]);
+        add_road(&mut streets, 21, 2, 4, &[]);
+
+        collapse_sausage_links(&mut streets);
+        assert!(!streets.roads.contains_key(&back));
+        assert_eq!(streets.roads[&fwd].original_segments, vec![fwd, back]);
+        assert_eq!(streets.road_for_original_segment(fwd), Some(fwd));
+        assert_eq!(streets.road_for_original_segment(back), Some(fwd));
+        assert_eq!(streets.original_segments_to_roads()[&back], fwd);
+    }
+}
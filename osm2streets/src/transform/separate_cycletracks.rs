@@ -1,216 +1,254 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use abstutil::Tags;
 use geom::{Distance, PolyLine};
 
-use crate::{osm, BufferType, Direction, LaneSpec, LaneType, OriginalRoad, StreetNetwork};
+use super::parallel::ParallelRoads;
+use crate::lanes::osm_separation_type;
+use crate::{
+    BufferType, ControlType, LaneSpec, LaneType, OriginalRoad, StreetNetwork, WidthSource,
+};
+
+// How far a cycletrack may be from the edge of the road it belongs to
+const MAX_GAP: Distance = Distance::const_meters(8.0);
+// The fraction of a cycletrack that has to run alongside roads for it to be snapped. There are
+// many paths through parks and along rivers that happen to pass by a road for a while, so be
+// strict.
+const MIN_PCT_SNAPPED: f64 = 0.8;
+// The fraction of one side of a road that snapped cycletracks have to cover to get the track
+const MIN_PCT_COVERED: f64 = 0.5;
 
 /// Find cycleway segments that exist as separate objects, parallel to a main road. Merge (or
 /// "snap") them into the main road, inserting a buffer lane to represent the physical division.
+///
+/// One cycleway may run alongside several road segments, and one road segment may have several
+/// cycleway segments beside it, so this matches them by geometry, not by how they're connected.
+/// Tracks interrupted where they cross a side road still cover the whole main road, and two-way
+/// tracks keep both directions on one side. The buffer comes from the cycleway's
+/// `separation:left` or `separation:right` tag, whichever faces the road. Cycleways are only
+/// removed if their lanes were added to some road; where a side already has a bike lane, other
+/// tracks stay separate.
 pub fn snap_cycleways(streets: &mut StreetNetwork) {
-    for cycleway in find_cycleways(streets) {
-        streets.maybe_start_debug_step(format!("snap cycleway {}", cycleway.debug_idx));
-        cycleway.debug(streets);
-        snap(streets, cycleway);
+    let cycleways = find_cycleways(streets);
+    if cycleways.is_empty() {
+        return;
+    }
+    let parallel = ParallelRoads::new(streets);
+
+    let mut all_hits = Vec::new();
+    // For each side of a road (true for the right), how many points along each cycleway snapped
+    // there face the same and the opposite direction as the road
+    let mut hits_per_side: BTreeMap<(OriginalRoad, bool), BTreeMap<usize, (usize, usize)>> =
+        BTreeMap::new();
+    let mut matched = Vec::new();
+    for (idx, cycleway) in cycleways.iter().enumerate() {
+        let hits = parallel.match_way(
+            streets,
+            &cycleway.center,
+            cycleway.layer.as_ref(),
+            MAX_GAP,
+            MIN_PCT_SNAPPED,
+        );
+        if hits.is_empty() {
+            continue;
+        }
+        for hit in &hits {
+            let entry = hits_per_side
+                .entry((hit.road, hit.on_right))
+                .or_default()
+                .entry(idx)
+                .or_insert((0, 0));
+            if hit.same_direction {
+                entry.0 += 1;
+            } else {
+                entry.1 += 1;
+            }
+        }
+        all_hits.extend(hits);
+        matched.push(idx);
+    }
+    if matched.is_empty() {
+        return;
     }
-}
 
-// We're only pattern matching on one type of separate cycleway right now. This represents a single
-// Road that's parallel to one or more main_roads.
-//
-// X--X
-// C  M
-// C  M
-// C  M
-// C  X
-// C  M
-// C  M
-// X--X
-//
-// C is the cycleway segment. X are intersections. M are main roads -- note there are two matching
-// up to this one cycleway. The '-'s are short connector roads between the two.
-struct Cycleway {
-    cycleway: OriginalRoad,
-    cycleway_center: PolyLine,
-    // Just to distinguish different cycleways when debugging
-    debug_idx: usize,
-    main_road_i1: osm::NodeID,
-    main_road_i2: osm::NodeID,
-    main_roads: Vec<OriginalRoad>,
-}
+    streets.maybe_start_debug_step("snap cycleways");
+    for idx in &matched {
+        streets.debug_road(cycleways[*idx].id, format!("cycleway {}", idx));
+    }
+    let mut spliced = BTreeSet::new();
+    for (r, on_right) in parallel.covered_sides(streets, &all_hits, MIN_PCT_COVERED) {
+        // Start with the cycleway running along most of this side
+        let mut candidates: Vec<(usize, usize, usize)> = hits_per_side[&(r, on_right)]
+            .iter()
+            .map(|(idx, (same, opposite))| (*idx, *same, *opposite))
+            .collect();
+        candidates.sort_by_key(|(idx, same, opposite)| (Reverse(same + opposite), *idx));
+
+        let mut inserted: Option<Vec<LaneSpec>> = None;
+        for (idx, same, opposite) in candidates {
+            let same_direction = same >= opposite;
+            let insert_lanes = lanes_for_road(&cycleways[idx], on_right, same_direction);
+            // Another piece of the same track, interrupted somewhere along this road
+            if inserted.as_ref() == Some(&insert_lanes) {
+                spliced.insert(idx);
+                continue;
+            }
+            streets.debug_road(
+                r,
+                format!(
+                    "main road along {idx}, on_right = {on_right}, same_direction = {same_direction}"
+                ),
+            );
+            let main_road = streets.roads.get_mut(&r).unwrap();
+            if splice_in(
+                &mut main_road.lane_specs_ltr,
+                on_right,
+                insert_lanes.clone(),
+            ) {
+                spliced.insert(idx);
+                inserted = Some(insert_lanes);
+            }
+        }
+    }
 
-impl Cycleway {
-    fn debug(&self, streets: &StreetNetwork) {
-        streets.debug_road(self.cycleway, format!("cycleway {}", self.debug_idx));
-        streets.debug_intersection(self.main_road_i1, format!("i1 of {}", self.debug_idx));
-        streets.debug_intersection(self.main_road_i2, format!("i2 of {}", self.debug_idx));
-        for x in &self.main_roads {
-            streets.debug_road(*x, format!("main road along {}", self.debug_idx));
+    // Remove the separate cycleways that're part of a road now. Where side roads crossed them, the
+    // intersections left behind are handled by CollapseDegenerateIntersections, and short
+    // connections to the main road by TrimDeadendCycleways.
+    let mut endpoints = BTreeSet::new();
+    for idx in spliced {
+        let id = cycleways[idx].id;
+        streets.remove_road(&id);
+        endpoints.insert(id.i1);
+        endpoints.insert(id.i2);
+    }
+    for i in endpoints {
+        if streets.intersections[&i].control != ControlType::Border
+            && streets.can_delete_intersection(i)
+        {
+            streets.delete_intersection(i);
         }
     }
 }
 
-fn find_cycleways(streets: &StreetNetwork) -> Vec<Cycleway> {
-    const SHORT_ROAD_THRESHOLD: Distance = Distance::const_meters(10.0);
+struct Cycleway {
+    id: OriginalRoad,
+    center: PolyLine,
+    layer: Option<String>,
+    lanes: Vec<LaneSpec>,
+    osm_tags: Tags,
+}
 
+fn find_cycleways(streets: &StreetNetwork) -> Vec<Cycleway> {
     let mut cycleways = Vec::new();
-    for (cycleway_id, cycleway_road) in &streets.roads {
-        if cycleway_road.is_cycleway() {
-            // Look at other roads connected to both endpoints. One of them should be "very short."
-            let mut main_road_endpoints = Vec::new();
-            for i in [cycleway_id.i1, cycleway_id.i2] {
-                let mut candidates = Vec::new();
-                for r in streets.roads_per_intersection(i) {
-                    let road = &streets.roads[&r];
-                    if road.length() < SHORT_ROAD_THRESHOLD {
-                        candidates.push(r);
-                    }
-                }
-                if candidates.len() == 1 {
-                    main_road_endpoints.push(candidates[0].other_side(i));
-                }
-            }
-
-            if main_road_endpoints.len() == 2 {
-                // Often the main road parallel to this cycleway segment is just one road, but it
-                // might be more.
-                let main_road_i1 = main_road_endpoints[0];
-                let main_road_i2 = main_road_endpoints[1];
-                // Find all main road segments "parallel to" this cycleway, by pathfinding between
-                // the main road intersections. We don't care about the order, but simple_path
-                // does. In case it's one-way for driving, try both.
-                if let Some(path) = streets
-                    .simple_path(main_road_i1, main_road_i2, &[LaneType::Driving])
-                    .or_else(|| {
-                        streets.simple_path(main_road_i2, main_road_i1, &[LaneType::Driving])
-                    })
-                {
-                    cycleways.push(Cycleway {
-                        cycleway: *cycleway_id,
-                        cycleway_center: cycleway_road.untrimmed_road_geometry().0,
-                        debug_idx: cycleways.len(),
-                        main_road_i1,
-                        main_road_i2,
-                        main_roads: path.into_iter().map(|(r, _)| r).collect(),
-                    });
-                }
-            }
+    for (id, road) in &streets.roads {
+        // Crossings are perpendicular to roads, so they'd never snap anyway
+        if !road.is_cycleway() || road.osm_tags.is("cycleway", "crossing") {
+            continue;
+        }
+        // The cycleway likely had shoulder lanes assigned to it by get_lane_specs_ltr, because we
+        // have many partially competing strategies for representing shared walking/cycling roads.
+        // Remove those.
+        let lanes: Vec<LaneSpec> = road
+            .lane_specs_ltr
+            .iter()
+            .filter(|lane| lane.lt != LaneType::Shoulder)
+            .cloned()
+            .collect();
+        if lanes.is_empty() {
+            continue;
         }
+        cycleways.push(Cycleway {
+            id: *id,
+            center: road.untrimmed_road_geometry().0,
+            layer: road.osm_tags.get("layer").cloned(),
+            lanes,
+            osm_tags: road.osm_tags.clone(),
+        });
     }
     cycleways
 }
 
-fn snap(streets: &mut StreetNetwork, input: Cycleway) {
-    // This analysis shouldn't modify other cycleways when it works on one
-    assert!(streets.roads.contains_key(&input.cycleway));
-
-    // Remove the cycleway, but remember the lanes it contained
-    let mut cycleway_lanes = streets
-        .roads
-        .remove(&input.cycleway)
-        .unwrap()
-        .lane_specs_ltr;
-
-    // The cycleway likely had shoulder lanes assigned to it by get_lane_specs_ltr, because we have
-    // many partially competing strategies for representing shared walking/cycling roads. Remove
-    // those.
-    if cycleway_lanes[0].lt == LaneType::Shoulder {
-        cycleway_lanes.remove(0);
-    }
-    if cycleway_lanes.last().as_ref().unwrap().lt == LaneType::Shoulder {
-        cycleway_lanes.pop();
+// The cycleway's lanes, oriented like the main road, and a buffer between them and the road
+fn lanes_for_road(cycleway: &Cycleway, on_right: bool, same_direction: bool) -> Vec<LaneSpec> {
+    let mut lanes = cycleway.lanes.clone();
+    if !same_direction {
+        lanes.reverse();
+        for lane in &mut lanes {
+            lane.dir = lane.dir.opposite();
+        }
     }
 
-    // The cycleway was tagged as a separate way due to some kind of physical separation. We'll
-    // represent that with a buffer lane.
-    let buffer = LaneSpec {
-        // TODO Use https://wiki.openstreetmap.org/wiki/Proposed_features/cycleway:separation if
-        // available
-        lt: LaneType::Buffer(BufferType::Planters),
-        dir: Direction::Fwd,
-        width: LaneSpec::typical_lane_width(LaneType::Buffer(BufferType::Planters)),
+    // Which side of the cycleway faces the road?
+    let facing_road = if on_right == same_direction {
+        "left"
+    } else {
+        "right"
+    };
+    let separation = cycleway
+        .osm_tags
+        .get(&format!("separation:{}", facing_road))
+        .or_else(|| cycleway.osm_tags.get("separation"));
+    let buffer_type = match separation {
+        Some(value) if value == "no" => None,
+        // The cycleway was mapped as a separate way due to some kind of physical separation, so
+        // assume there's something even if we don't know what
+        Some(value) => Some(osm_separation_type(value).unwrap_or(BufferType::Planters)),
+        None => Some(BufferType::Planters),
     };
 
-    // For every main road segment corresponding to the cycleway, we need to insert these
-    // cycleway_lanes somewhere.
-    //
-    // - Fixing the direction of the lanes
-    // - Appending them on the left or right side (and "inside" the inferred sidewalk on the road)
-    // - Inserting the buffer
-    for r in input.main_roads {
-        let main_road = &streets.roads[&r];
-        // Which side is closer to the cycleway?
-        let (left, right) = main_road.get_untrimmed_sides().unwrap();
-        // TODO georust has a way to check distance of linestrings. But for now, just check the
-        // middles
-        let snap_to_left = input.cycleway_center.middle().dist_to(left.middle())
-            < input.cycleway_center.middle().dist_to(right.middle());
-
-        // Does the cycleway point the same direction as this main road? We can use the left or
-        // right side, doesn't matter.
-        // TODO Check this logic very carefully; angles always lead to bugs. 90 is a very generous
-        // definition of parallel. But we have a binary decision to make, so maybe we should even
-        // use 180.
-        let oriented_same_way = input
-            .cycleway_center
-            .overall_angle()
-            .approx_eq(left.overall_angle(), 90.0);
-
-        // Where should we insert the cycleway lanes? If the main road already has a sidewalk,
-        // let's assume it should stay at the outermost part of the road. (That isn't always true,
-        // but it's an assumption we'll take for now.)
-        let insert_idx = if snap_to_left {
-            if main_road.lane_specs_ltr[0].lt.is_walkable() {
-                1
-            } else {
-                0
-            }
-        } else {
-            if main_road
-                .lane_specs_ltr
-                .last()
-                .as_ref()
-                .unwrap()
-                .lt
-                .is_walkable()
-            {
-                main_road.lane_specs_ltr.len() - 1
+    if let Some(buffer_type) = buffer_type {
+        let lt = LaneType::Buffer(buffer_type);
+        // TODO I'm not sure what direction the buffer lane should face. This is a very strong
+        // argument for Direction::Both.
+        let buffer = LaneSpec {
+            lt,
+            dir: if on_right {
+                lanes[0].dir
             } else {
-                main_road.lane_specs_ltr.len()
-            }
+                lanes.last().unwrap().dir
+            },
+            width: LaneSpec::typical_lane_width(lt),
+            allowed_turns: BTreeSet::new(),
+            width_source: WidthSource::Default,
+            speed_limit: None,
         };
-
-        streets.debug_road(r, format!("snap_to_left = {snap_to_left}, oriented_same_way = {oriented_same_way}, insert_idx = {insert_idx}"));
-
-        // This logic thankfully doesn't depend on driving side at all!
-        let mut insert_lanes = Vec::new();
-        for mut lane in cycleway_lanes.clone() {
-            if !oriented_same_way {
-                lane.dir = lane.dir.opposite();
-            }
-            insert_lanes.push(lane);
-        }
-        // TODO Do we ever need to reverse the order of the lanes?
-        let mut buffer_lane = buffer.clone();
-        if snap_to_left {
-            // TODO I'm not sure what direction the buffer lane should face. This is a very strong
-            // argument for Direction::Both.
-            buffer_lane.dir = insert_lanes.last().as_ref().unwrap().dir;
-            insert_lanes.push(buffer_lane);
+        if on_right {
+            lanes.insert(0, buffer);
         } else {
-            buffer_lane.dir = insert_lanes[0].dir;
-            insert_lanes.insert(0, buffer_lane);
+            lanes.push(buffer);
         }
-
-        let main_road = streets.roads.get_mut(&r).unwrap();
-        splice_in(&mut main_road.lane_specs_ltr, insert_idx, insert_lanes);
     }
-
-    // After this transformation, we should run CollapseDegenerateIntersections to handle the
-    // intersection where the side road originally crossed the cycleway, and TrimDeadendCycleways
-    // to clean up any small cycle connection roads.
+    lanes
 }
 
-// Insert all of `insert` at `idx` in `target`
-fn splice_in<T>(target: &mut Vec<T>, idx: usize, insert: Vec<T>) {
-    let tail = target.split_off(idx);
-    target.extend(insert);
-    target.extend(tail);
+// Insert all of `insert` on one side of the road. If the road already has a sidewalk, let's
+// assume it should stay at the outermost part of the road. (That isn't always true, but it's an
+// assumption we'll take for now.) Returns false if there's already a bike lane on that side.
+fn splice_in(lanes_ltr: &mut Vec<LaneSpec>, on_right: bool, insert: Vec<LaneSpec>) -> bool {
+    let idx = if on_right {
+        if lanes_ltr.last().unwrap().lt.is_walkable() {
+            lanes_ltr.len() - 1
+        } else {
+            lanes_ltr.len()
+        }
+    } else if lanes_ltr[0].lt.is_walkable() {
+        1
+    } else {
+        0
+    };
+    // Two cycleways snapped to the same side, or the road already had a bike lane there
+    let neighbor = if on_right {
+        idx.checked_sub(1).map(|i| &lanes_ltr[i])
+    } else {
+        lanes_ltr.get(idx)
+    };
+    if matches!(neighbor, Some(lane) if lane.lt == LaneType::Biking) {
+        return false;
+    }
+
+    let tail = lanes_ltr.split_off(idx);
+    lanes_ltr.extend(insert);
+    lanes_ltr.extend(tail);
+    true
 }
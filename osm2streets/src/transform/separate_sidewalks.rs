@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use geom::{Distance, PolyLine};
+
+use super::parallel::ParallelRoads;
+use crate::{
+    osm, ControlType, Direction, DrivingSide, LaneSpec, LaneType, OriginalRoad, StreetNetwork,
+};
+
+// How far a sidewalk may be from the edge of the road it belongs to. This allows for verges and
+// parking between them.
+const MAX_GAP: Distance = Distance::const_meters(10.0);
+// How far a crossing footway's dangling end may be moved to reach the road's intersection
+const MAX_RECONNECT_DIST: Distance = Distance::const_meters(20.0);
+// The fraction of a sidewalk that has to run alongside roads for it to be snapped. The pieces
+// wrapping around a corner into a side street don't match anything.
+const MIN_PCT_SNAPPED: f64 = 0.7;
+// The fraction of one side of a road that snapped sidewalks have to cover to give it a sidewalk
+const MIN_PCT_COVERED: f64 = 0.5;
+
+/// Find sidewalks mapped as separate `footway=sidewalk` ways, and turn them into `Sidewalk` lanes
+/// on the side of the road they run alongside. Sidewalks are only removed once a side of a road
+/// they run along gets the lane; the rest stay separate. Footways that ended at a removed
+/// sidewalk, usually crossings, are reconnected to the nearest intersection of that road.
+///
+/// Separate sidewalks only exist when `MapConfig::inferred_sidewalks` is false, so this does
+/// nothing otherwise.
+pub fn snap_sidewalks(streets: &mut StreetNetwork) {
+    let sidewalks = find_sidewalks(streets);
+    if sidewalks.is_empty() {
+        return;
+    }
+    let parallel = ParallelRoads::new(streets);
+
+    let mut all_hits = Vec::new();
+    // For each sidewalk, the sides of roads (true for the right) that it runs along
+    let mut sides_per_sidewalk: Vec<BTreeSet<(OriginalRoad, bool)>> = Vec::new();
+    // For each side of a road, how many points of each sidewalk are along it
+    let mut hits_per_side: BTreeMap<(OriginalRoad, bool), BTreeMap<usize, usize>> = BTreeMap::new();
+    for (idx, sidewalk) in sidewalks.iter().enumerate() {
+        let hits = parallel.match_way(
+            streets,
+            &sidewalk.center,
+            sidewalk.layer.as_ref(),
+            MAX_GAP,
+            MIN_PCT_SNAPPED,
+        );
+        let mut sides = BTreeSet::new();
+        for hit in &hits {
+            *hits_per_side
+                .entry((hit.road, hit.on_right))
+                .or_default()
+                .entry(idx)
+                .or_default() += 1;
+            sides.insert((hit.road, hit.on_right));
+        }
+        sides_per_sidewalk.push(sides);
+        all_hits.extend(hits);
+    }
+
+    let covered = parallel.covered_sides(streets, &all_hits, MIN_PCT_COVERED);
+    let driving_side = streets.config.driving_side;
+    for (r, on_right) in &covered {
+        // Use the sidewalk running along most of this side
+        let (idx, _) = hits_per_side[&(*r, *on_right)]
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .unwrap();
+        add_sidewalk(
+            &mut streets.roads.get_mut(r).unwrap().lane_specs_ltr,
+            *on_right,
+            sidewalks[*idx].lane.clone(),
+            driving_side,
+        );
+    }
+
+    // The roads next to the sidewalks ending at each intersection
+    let mut parents: BTreeMap<osm::NodeID, BTreeSet<OriginalRoad>> = BTreeMap::new();
+    for (sidewalk, sides) in sidewalks.iter().zip(sides_per_sidewalk) {
+        let snapped: Vec<OriginalRoad> = sides
+            .into_iter()
+            .filter(|side| covered.contains(side))
+            .map(|(r, _)| r)
+            .collect();
+        if snapped.is_empty() {
+            debug!(
+                "Sidewalk {} doesn't run along enough of a road, keeping it separate",
+                sidewalk.id
+            );
+            continue;
+        }
+        for i in [sidewalk.id.i1, sidewalk.id.i2] {
+            parents
+                .entry(i)
+                .or_default()
+                .extend(snapped.iter().cloned());
+        }
+        streets.remove_road(&sidewalk.id);
+    }
+
+    for (i, parents) in parents {
+        reconnect(streets, i, &parents);
+    }
+}
+
+struct Sidewalk {
+    id: OriginalRoad,
+    center: PolyLine,
+    layer: Option<String>,
+    lane: LaneSpec,
+}
+
+fn find_sidewalks(streets: &StreetNetwork) -> Vec<Sidewalk> {
+    let mut sidewalks = Vec::new();
+    for (id, road) in &streets.roads {
+        if road.osm_tags.is(osm::HIGHWAY, "footway") && road.osm_tags.is("footway", "sidewalk") {
+            let lane = match road
+                .lane_specs_ltr
+                .iter()
+                .find(|lane| lane.lt == LaneType::Sidewalk)
+            {
+                Some(lane) => lane.clone(),
+                None => continue,
+            };
+            sidewalks.push(Sidewalk {
+                id: *id,
+                center: road.untrimmed_road_geometry().0,
+                layer: road.osm_tags.get("layer").cloned(),
+                lane,
+            });
+        }
+    }
+    sidewalks
+}
+
+fn add_sidewalk(
+    lanes_ltr: &mut Vec<LaneSpec>,
+    on_right: bool,
+    mut lane: LaneSpec,
+    driving_side: DrivingSide,
+) {
+    lane.lt = LaneType::Sidewalk;
+    lane.dir = if on_right == (driving_side == DrivingSide::Right) {
+        Direction::Fwd
+    } else {
+        Direction::Back
+    };
+    lane.allowed_turns.clear();
+    lane.speed_limit = None;
+
+    let idx = if on_right { lanes_ltr.len() - 1 } else { 0 };
+    match lanes_ltr[idx].lt {
+        LaneType::Sidewalk => {}
+        // Shoulders are where people walk without a sidewalk
+        LaneType::Shoulder => {
+            lanes_ltr[idx] = lane;
+        }
+        _ => {
+            if on_right {
+                lanes_ltr.push(lane);
+            } else {
+                lanes_ltr.insert(0, lane);
+            }
+        }
+    }
+}
+
+// After removing sidewalks, the footways that crossed the road to reach them end at intersections
+// off to the side. Move those ends to the closest intersection of the road the sidewalk snapped to.
+fn reconnect(streets: &mut StreetNetwork, i: osm::NodeID, parents: &BTreeSet<OriginalRoad>) {
+    match streets.intersections.get(&i) {
+        Some(intersection) if intersection.control != ControlType::Border => {}
+        _ => return,
+    }
+    let roads = streets.roads_per_intersection(i);
+    if roads.is_empty() {
+        streets.delete_intersection(i);
+        return;
+    }
+    // If the sidewalk met a real road here, there's nothing to fix
+    if roads.iter().any(|r| !streets.roads[r].is_footway()) {
+        return;
+    }
+
+    let pt = streets.intersections[&i].point;
+    let target = match parents
+        .iter()
+        .flat_map(|r| [r.i1, r.i2])
+        .filter(|x| streets.intersections.contains_key(x))
+        .map(|x| (streets.intersections[&x].point.dist_to(pt), x))
+        .filter(|(dist, _)| *dist <= MAX_RECONNECT_DIST)
+        .min_by_key(|(dist, _)| *dist)
+    {
+        Some((_, target)) => target,
+        None => return,
+    };
+    let target_pt = streets.intersections[&target].point;
+
+    for r in roads {
+        let mut road = streets.remove_road(&r);
+        if r.i1 == r.i2 || r.other_side(i) == target {
+            // The footway only led from the sidewalk to the road, which the sidewalk is part of
+            // now
+            continue;
+        }
+        let mut id = r;
+        if id.i1 == i {
+            id.i1 = target;
+            road.osm_center_points[0] = target_pt;
+        } else {
+            id.i2 = target;
+            *road.osm_center_points.last_mut().unwrap() = target_pt;
+        }
+        road.osm_center_points.dedup();
+        if streets.roads.contains_key(&id) || PolyLine::new(road.osm_center_points.clone()).is_err()
+        {
+            debug!("Dropping footway {} after snapping sidewalks", r);
+            continue;
+        }
+        streets.insert_road(id, road);
+    }
+    streets.delete_intersection(i);
+}
@@ -0,0 +1,554 @@
+use std::collections::BTreeMap;
+
+use abstutil::Tags;
+use anyhow::Result;
+use geom::{Angle, Distance, FindClosest, PolyLine, Pt2D};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    osm, Direction, DrivingSide, LaneType, OriginalRoad, RoutingGraph, StreetNetwork, TravelMode,
+};
+
+/// Public transit stops and routes, usually imported from GTFS. Road IDs are kept up-to-date
+/// through transformations.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TransitNetwork {
+    /// Keyed by GTFS `stop_id`
+    pub stops: BTreeMap<String, TransitStop>,
+    pub routes: Vec<TransitRoute>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransitStop {
+    pub name: String,
+    pub position: Pt2D,
+    /// `None` if no road used by any route serving this stop is nearby
+    pub snapped: Option<SnappedStop>,
+}
+
+/// Where a stop is along a road
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnappedStop {
+    pub road: OriginalRoad,
+    /// The direction of travel served by this stop, based on which side of the road it's on
+    pub dir: Direction,
+    /// Distance along the road's untrimmed center line, from `i1`
+    pub dist_along: Distance,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransitRoute {
+    pub gtfs_id: String,
+    pub short_name: String,
+    pub long_name: String,
+    pub kind: TransitKind,
+    /// The roads followed by one representative trip, in order. Empty if the route has no shape or
+    /// doesn't use roads.
+    pub path: Vec<(OriginalRoad, Direction)>,
+    /// GTFS `stop_id`s served by the representative trip, in order
+    pub stops: Vec<String>,
+}
+
+/// What kind of vehicle a transit route uses, simplified from the GTFS `route_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TransitKind {
+    Bus,
+    Tram,
+    /// Subways, trains, ferries, and other things that don't use roads
+    Other,
+}
+
+impl TransitKind {
+    /// Interprets both basic and extended GTFS route types
+    pub fn from_gtfs_route_type(route_type: usize) -> TransitKind {
+        match route_type {
+            3 | 11 | 200..=299 | 700..=799 | 800..=899 => TransitKind::Bus,
+            0 | 900..=999 => TransitKind::Tram,
+            _ => TransitKind::Other,
+        }
+    }
+
+    fn can_use_lane(self, lt: LaneType) -> bool {
+        match self {
+            TransitKind::Bus => lt == LaneType::Driving || lt == LaneType::Bus,
+            TransitKind::Tram => lt == LaneType::LightRail,
+            TransitKind::Other => false,
+        }
+    }
+}
+
+/// A bus stop, platform, or tram stop mapped in OSM, attached to the road it serves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PublicTransportStop {
+    pub osm_node_id: osm::NodeID,
+    pub stop_type: StopType,
+    pub name: Option<String>,
+    /// Where the OSM node is. This may be on the road's center line or off to the side.
+    pub position: Pt2D,
+    /// Distance along the road's untrimmed center line, from `i1`. Kept up-to-date through
+    /// transformations.
+    pub dist_along: Distance,
+    /// The direction of travel served by this stop
+    pub dir: Direction,
+    pub placement: StopPlacement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopType {
+    /// `highway=bus_stop`
+    BusStop,
+    /// `public_transport=platform`
+    Platform,
+    /// `public_transport=stop_position`, where the vehicle stops on the road
+    StopPosition,
+    /// `railway=tram_stop`
+    TramStop,
+}
+
+impl StopType {
+    /// Classifies an OSM node, if it's some kind of stop
+    pub fn from_tags(tags: &Tags) -> Option<StopType> {
+        if tags.is("railway", "tram_stop") {
+            Some(StopType::TramStop)
+        } else if tags.is("public_transport", "stop_position") {
+            Some(StopType::StopPosition)
+        } else if tags.is(osm::HIGHWAY, "bus_stop") {
+            Some(StopType::BusStop)
+        } else if tags.is("public_transport", "platform") {
+            Some(StopType::Platform)
+        } else {
+            None
+        }
+    }
+
+    /// Which roads can this kind of stop be attached to?
+    pub fn transit_kind(self) -> TransitKind {
+        match self {
+            StopType::TramStop => TransitKind::Tram,
+            StopType::BusStop | StopType::Platform | StopType::StopPosition => TransitKind::Bus,
+        }
+    }
+}
+
+/// Where a vehicle waits at a stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StopPlacement {
+    /// In a general traffic lane, or on tracks
+    Roadside,
+    /// In a bus lane
+    BusLane,
+    /// In a bay pulled out of the road
+    Bay,
+}
+
+/// Snaps stops and matches shapes to the roads usable by one `TransitKind`
+pub struct TransitMatcher<'a> {
+    streets: &'a StreetNetwork,
+    kind: TransitKind,
+    center_lines: BTreeMap<OriginalRoad, PolyLine>,
+    closest: FindClosest<OriginalRoad>,
+    // Only used to fill gaps for buses
+    graph: Option<&'a RoutingGraph>,
+}
+
+impl<'a> TransitMatcher<'a> {
+    /// If `graph` is specified, gaps between matched bus roads are filled in by routing.
+    pub fn new(
+        streets: &'a StreetNetwork,
+        kind: TransitKind,
+        graph: Option<&'a RoutingGraph>,
+    ) -> Self {
+        let mut center_lines = BTreeMap::new();
+        let mut closest = FindClosest::new(&streets.gps_bounds.to_bounds());
+        for (id, road) in &streets.roads {
+            if !road.lane_specs_ltr.iter().any(|l| kind.can_use_lane(l.lt)) {
+                continue;
+            }
+            if let Ok(pl) = PolyLine::new(road.osm_center_points.clone()) {
+                closest.add(*id, pl.points());
+                center_lines.insert(*id, pl);
+            }
+        }
+        Self {
+            streets,
+            kind,
+            center_lines,
+            closest,
+            graph,
+        }
+    }
+
+    /// Finds the closest usable road to a stop, and which direction of travel it serves.
+    pub fn snap_stop(&self, pt: Pt2D) -> Option<SnappedStop> {
+        let max_dist = Distance::meters(30.0);
+        let (road, _) = self.closest.closest_pt(pt, max_dist)?;
+        let pl = &self.center_lines[&road];
+        let projected = pl.project_pt(pt);
+        let (dist_along, road_angle) = pl.dist_along_of_point(projected)?;
+
+        let mut diff =
+            projected.angle_to(pt).normalized_degrees() - road_angle.normalized_degrees();
+        if diff > 180.0 {
+            diff -= 360.0;
+        } else if diff <= -180.0 {
+            diff += 360.0;
+        }
+        // Angles increase clockwise, so a positive difference means the stop is on the right
+        let on_right = diff > 0.0;
+        let mut dir = if on_right == (self.streets.config.driving_side == DrivingSide::Right) {
+            Direction::Fwd
+        } else {
+            Direction::Back
+        };
+        // Stops on one-way roads serve the only direction
+        let (fwd, back) = self.usable_directions(road);
+        if !fwd {
+            dir = Direction::Back;
+        } else if !back {
+            dir = Direction::Fwd;
+        }
+
+        Some(SnappedStop {
+            road,
+            dir,
+            dist_along,
+        })
+    }
+
+    /// Matches a route's shape to the sequence of roads it follows.
+    pub fn match_shape(&self, shape: &PolyLine) -> Vec<(OriginalRoad, Direction)> {
+        let step_size = Distance::meters(10.0);
+        let max_dist = Distance::meters(20.0);
+        // How many degrees difference to consider heading the same way
+        let parallel_threshold = 30.0;
+
+        let mut matched: Vec<(OriginalRoad, Direction)> = Vec::new();
+        let mut dist = Distance::ZERO;
+        loop {
+            let (pt, shape_angle) = shape.must_dist_along(dist);
+            for (road, _, _) in self.closest.all_close_pts(pt, max_dist) {
+                let pl = &self.center_lines[&road];
+                let road_angle = match pl.dist_along_of_point(pl.project_pt(pt)) {
+                    Some((_, angle)) => angle,
+                    None => continue,
+                };
+                let (fwd, back) = self.usable_directions(road);
+                let dir = if fwd && road_angle.approx_eq(shape_angle, parallel_threshold) {
+                    Direction::Fwd
+                } else if back
+                    && road_angle
+                        .opposite()
+                        .approx_eq(shape_angle, parallel_threshold)
+                {
+                    Direction::Back
+                } else {
+                    continue;
+                };
+                // Stop at the closest hit
+                if matched.last() != Some(&(road, dir)) {
+                    matched.push((road, dir));
+                }
+                break;
+            }
+
+            if dist == shape.length() {
+                break;
+            }
+            dist = (dist + step_size).min(shape.length());
+        }
+
+        self.fill_gaps(matched)
+    }
+
+    fn usable_directions(&self, road: OriginalRoad) -> (bool, bool) {
+        let mut fwd = false;
+        let mut back = false;
+        for lane in &self.streets.roads[&road].lane_specs_ltr {
+            if self.kind.can_use_lane(lane.lt) {
+                if lane.dir == Direction::Fwd {
+                    fwd = true;
+                } else {
+                    back = true;
+                }
+            }
+        }
+        (fwd, back)
+    }
+
+    // Matching points independently can skip short roads or briefly jump onto the wrong one.
+    // Drop detours that come right back, and route between roads that don't connect.
+    fn fill_gaps(&self, matched: Vec<(OriginalRoad, Direction)>) -> Vec<(OriginalRoad, Direction)> {
+        let mut path: Vec<(OriginalRoad, Direction)> = Vec::new();
+        for step in matched {
+            if path.len() >= 2 && path[path.len() - 2] == step {
+                path.pop();
+                continue;
+            }
+            if let Some(last) = path.last().cloned() {
+                let from = end_of(last);
+                let to = start_of(step);
+                if from != to {
+                    let detour = self.graph.and_then(|graph| {
+                        if self.kind == TransitKind::Bus {
+                            graph.route(TravelMode::Bus, from, to)
+                        } else {
+                            None
+                        }
+                    });
+                    match detour {
+                        // Only trust short detours; anything longer means a bad match
+                        Some((steps, _)) if steps.len() <= 5 => {
+                            path.extend(steps);
+                        }
+                        _ => {
+                            warn!("Transit path has a gap between {} and {}", last.0, step.0);
+                        }
+                    }
+                }
+            }
+            path.push(step);
+        }
+        path
+    }
+}
+
+fn start_of((road, dir): (OriginalRoad, Direction)) -> osm::NodeID {
+    if dir == Direction::Fwd {
+        road.i1
+    } else {
+        road.i2
+    }
+}
+
+fn end_of((road, dir): (OriginalRoad, Direction)) -> osm::NodeID {
+    if dir == Direction::Fwd {
+        road.i2
+    } else {
+        road.i1
+    }
+}
+
+/// The `PublicTransportStop`s on every road and the roads used by `StreetNetwork::transit`,
+/// captured before a transformation
+pub(crate) struct StopSnapshot {
+    segments: BTreeMap<OriginalRoad, Vec<OriginalRoad>>,
+    // (road, the road's angle at the stop, stop)
+    stops: Vec<(OriginalRoad, Angle, PublicTransportStop)>,
+    // The road's angle at each snapped transit stop, keyed by stop ID
+    transit_stops: BTreeMap<String, Angle>,
+    // The middle of each road in a transit path, and the road's angle there
+    path_roads: BTreeMap<OriginalRoad, (Pt2D, Angle)>,
+}
+
+impl StopSnapshot {
+    pub fn new(streets: &StreetNetwork) -> Self {
+        let mut segments = BTreeMap::new();
+        let mut stops = Vec::new();
+        for (id, road) in &streets.roads {
+            segments.insert(*id, road.original_segments.clone());
+            if road.public_transport_stops.is_empty() {
+                continue;
+            }
+            if let Ok(pl) = PolyLine::new(road.osm_center_points.clone()) {
+                for stop in &road.public_transport_stops {
+                    if let Some((_, angle)) = pl.dist_along_of_point(pl.project_pt(stop.position)) {
+                        stops.push((*id, angle, stop.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut transit_stops = BTreeMap::new();
+        for (id, stop) in &streets.transit.stops {
+            if let Some(snapped) = stop.snapped {
+                if let Some(angle) = road_angle_near(streets, snapped.road, stop.position) {
+                    transit_stops.insert(id.clone(), angle);
+                }
+            }
+        }
+        let mut path_roads = BTreeMap::new();
+        for route in &streets.transit.routes {
+            for (r, _) in &route.path {
+                if path_roads.contains_key(r) {
+                    continue;
+                }
+                if let Some(pl) = streets
+                    .roads
+                    .get(r)
+                    .and_then(|road| PolyLine::new(road.osm_center_points.clone()).ok())
+                {
+                    path_roads.insert(*r, pl.must_dist_along(pl.length() / 2.0));
+                }
+            }
+        }
+
+        Self {
+            segments,
+            stops,
+            transit_stops,
+            path_roads,
+        }
+    }
+
+    /// Move every stop to the road that now contains its original road, and recalculate the
+    /// position along it. If the road was reversed, the stop's direction flips too. Transit route
+    /// paths are updated the same way.
+    pub fn fix(mut self, streets: &mut StreetNetwork) {
+        if self.stops.is_empty() && self.transit_stops.is_empty() && self.path_roads.is_empty() {
+            return;
+        }
+        for road in streets.roads.values_mut() {
+            road.public_transport_stops.clear();
+        }
+
+        let owners = streets.original_segments_to_roads();
+        for (id, old_angle, mut stop) in std::mem::take(&mut self.stops) {
+            match self.relocate(streets, &owners, id, stop.position) {
+                Some((r, dist_along, angle)) => {
+                    stop.dist_along = dist_along;
+                    if !angle.approx_eq(old_angle, 90.0) {
+                        stop.dir = stop.dir.opposite();
+                    }
+                    streets
+                        .roads
+                        .get_mut(&r)
+                        .unwrap()
+                        .public_transport_stops
+                        .push(stop);
+                }
+                None => {
+                    warn!(
+                        "Dropping {:?} stop {}, because {} is gone",
+                        stop.stop_type, stop.osm_node_id, id
+                    );
+                }
+            }
+        }
+
+        let mut transit = std::mem::take(&mut streets.transit);
+        for (id, stop) in &mut transit.stops {
+            let (mut snapped, old_angle) = match (stop.snapped, self.transit_stops.get(id)) {
+                (Some(snapped), Some(angle)) => (snapped, *angle),
+                _ => continue,
+            };
+            stop.snapped = match self.relocate(streets, &owners, snapped.road, stop.position) {
+                Some((r, dist_along, angle)) => {
+                    snapped.road = r;
+                    snapped.dist_along = dist_along;
+                    if !angle.approx_eq(old_angle, 90.0) {
+                        snapped.dir = snapped.dir.opposite();
+                    }
+                    Some(snapped)
+                }
+                None => {
+                    warn!(
+                        "Transit stop {} isn't snapped anymore, because {} is gone",
+                        id, snapped.road
+                    );
+                    None
+                }
+            };
+        }
+        for route in &mut transit.routes {
+            let mut path: Vec<(OriginalRoad, Direction)> = Vec::new();
+            for (old, dir) in route.path.drain(..) {
+                let (pt, old_angle) = match self.path_roads.get(&old) {
+                    Some(pair) => *pair,
+                    None => continue,
+                };
+                let step = match self.relocate(streets, &owners, old, pt) {
+                    Some((r, _, angle)) => {
+                        if angle.approx_eq(old_angle, 90.0) {
+                            (r, dir)
+                        } else {
+                            (r, dir.opposite())
+                        }
+                    }
+                    None => {
+                        warn!(
+                            "Transit route {} has a gap, because {} is gone",
+                            route.gtfs_id, old
+                        );
+                        continue;
+                    }
+                };
+                // Roads merged together only appear once
+                if path.last() != Some(&step) {
+                    path.push(step);
+                }
+            }
+            route.path = path;
+        }
+        streets.transit = transit;
+    }
+
+    // Find the road that now contains the original road `id`, and the distance along and angle of
+    // that road closest to `pt`. If the road was split, use the closest piece.
+    fn relocate(
+        &self,
+        streets: &StreetNetwork,
+        owners: &BTreeMap<OriginalRoad, OriginalRoad>,
+        id: OriginalRoad,
+        pt: Pt2D,
+    ) -> Option<(OriginalRoad, Distance, Angle)> {
+        let candidates: Vec<OriginalRoad> = if streets.roads.contains_key(&id) {
+            vec![id]
+        } else {
+            self.segments
+                .get(&id)
+                .into_iter()
+                .flatten()
+                .filter_map(|segment| owners.get(segment).cloned())
+                .collect()
+        };
+        candidates
+            .into_iter()
+            .filter_map(|r| {
+                let pl = PolyLine::new(streets.roads[&r].osm_center_points.clone()).ok()?;
+                let projected = pl.project_pt(pt);
+                let (dist, angle) = pl.dist_along_of_point(projected)?;
+                Some((projected.dist_to(pt), r, dist, angle))
+            })
+            .min_by_key(|(dist_to_pt, _, _, _)| *dist_to_pt)
+            .map(|(_, r, dist, angle)| (r, dist, angle))
+    }
+}
+
+// The angle of a road at the point closest to `pt`
+fn road_angle_near(streets: &StreetNetwork, road: OriginalRoad, pt: Pt2D) -> Option<Angle> {
+    let pl = PolyLine::new(streets.roads.get(&road)?.osm_center_points.clone()).ok()?;
+    pl.dist_along_of_point(pl.project_pt(pt))
+        .map(|(_, angle)| angle)
+}
+
+impl StreetNetwork {
+    /// The fraction of a route's length, in [0, 1], that has a bus lane in its direction of
+    /// travel. Fails if a road in the path doesn't exist.
+    pub fn bus_lane_coverage(&self, route: &TransitRoute) -> Result<f64> {
+        let mut total = Distance::ZERO;
+        let mut covered = Distance::ZERO;
+        for (id, dir) in &route.path {
+            let road = match self.roads.get(id) {
+                Some(road) => road,
+                None => bail!(
+                    "Transit route {} uses {}, which doesn't exist",
+                    route.gtfs_id,
+                    id
+                ),
+            };
+            let length = road.length();
+            total += length;
+            if road
+                .lane_specs_ltr
+                .iter()
+                .any(|l| l.lt == LaneType::Bus && l.dir == *dir)
+            {
+                covered += length;
+            }
+        }
+        if total == Distance::ZERO {
+            return Ok(0.0);
+        }
+        Ok(covered / total)
+    }
+}
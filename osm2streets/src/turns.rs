@@ -0,0 +1,603 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::{Angle, Distance, PolyLine, Pt2D};
+
+use crate::{
+    initial, intersection_polygon, osm, ControlType, Direction, DrivingSide, LaneType,
+    OriginalRoad, RestrictionType, StreetNetwork, TurnDirection,
+};
+
+/// A lane, identified by its road and index into `lane_specs_ltr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct LaneID {
+    pub road: OriginalRoad,
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TurnType {
+    Straight,
+    Left,
+    Right,
+    UTurn,
+    /// Crossing the end of a road from the sidewalk on one side to the other
+    Crosswalk,
+}
+
+/// A movement through an intersection from the end of one lane to the start of another.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Turn {
+    pub from: LaneID,
+    pub to: LaneID,
+    pub turn_type: TurnType,
+    /// From the end of `from` to the start of `to`
+    pub geometry: PolyLine,
+    /// From unconditional `complicated_turn_restrictions`: this turn is banned when the previous
+    /// turn entered `from.road` from one of these roads.
+    pub banned_when_coming_from: Vec<OriginalRoad>,
+}
+
+impl StreetNetwork {
+    /// Lists all allowed lane-to-lane movements through one intersection. Vehicles only move
+    /// between lanes of compatible types: general traffic and bus lanes, bike lanes and shared-use
+    /// paths, or light rail tracks. Pedestrians only get crosswalks. Turn restrictions that only
+    /// apply to some vehicles or at some times don't remove turns. Lanes with `allowed_turns` only
+    /// lead where their arrows point.
+    pub fn get_turns(&self, i: osm::NodeID) -> Result<Vec<Turn>> {
+        let intersection = match self.intersections.get(&i) {
+            Some(intersection) => intersection,
+            None => bail!("{} doesn't exist", i),
+        };
+        if intersection.control == ControlType::Border {
+            return Ok(Vec::new());
+        }
+
+        let mut input_roads = Vec::new();
+        for r in &intersection.roads {
+            if !self.roads.contains_key(r) {
+                bail!("{} lists {}, which doesn't exist", i, r);
+            }
+            input_roads.push(initial::Road::new(self, *r).to_input_road());
+        }
+        let results = intersection_polygon(i, input_roads, &intersection.trim_roads_for_merging)?;
+
+        // Find where every lane touches this intersection
+        let mut incoming: Vec<LaneEnd> = Vec::new();
+        let mut outgoing: Vec<LaneEnd> = Vec::new();
+        for r in &intersection.roads {
+            let road = &self.roads[r];
+            let trimmed_center = &results.trimmed_center_pts[r].0;
+            for (index, (lane, pl)) in road
+                .lane_specs_ltr
+                .iter()
+                .zip(road.get_lane_center_lines(trimmed_center))
+                .enumerate()
+            {
+                let pts = pl.points();
+                // Oriented in the direction of the road
+                let (pt, angle) = if r.i2 == i {
+                    (
+                        pl.last_pt(),
+                        pts[pts.len() - 2].angle_to(pts[pts.len() - 1]),
+                    )
+                } else {
+                    (pl.first_pt(), pts[0].angle_to(pts[1]))
+                };
+                let end = LaneEnd {
+                    id: LaneID { road: *r, index },
+                    lt: lane.lt,
+                    dir: lane.dir,
+                    allowed_turns: lane.allowed_turns.clone(),
+                    pt,
+                    angle: if lane.dir == Direction::Fwd {
+                        angle
+                    } else {
+                        angle.opposite()
+                    },
+                };
+                // Roads pointing at the intersection bring lanes going forwards in
+                if (r.i2 == i) == (lane.dir == Direction::Fwd) {
+                    incoming.push(end);
+                } else {
+                    outgoing.push(end);
+                }
+            }
+        }
+
+        let mut turns = Vec::new();
+        for from_road in &intersection.roads {
+            for to_road in &intersection.roads {
+                if from_road == to_road && intersection.roads.len() > 1 {
+                    // Only allow U-turns onto the same road at dead-ends
+                    continue;
+                }
+                if !self.is_turn_allowed(*from_road, *to_road, i) {
+                    continue;
+                }
+
+                for group in [
+                    &[LaneType::Driving, LaneType::Bus][..],
+                    &[LaneType::Biking, LaneType::SharedUse][..],
+                    &[LaneType::LightRail][..],
+                ] {
+                    let from_lanes = lanes_left_to_right(&incoming, *from_road, group);
+                    let to_lanes = lanes_left_to_right(&outgoing, *to_road, group);
+                    if from_lanes.is_empty() || to_lanes.is_empty() {
+                        continue;
+                    }
+                    let turn_type = if from_road == to_road {
+                        TurnType::UTurn
+                    } else {
+                        classify(from_lanes[0].angle, to_lanes[0].angle)
+                    };
+                    let from_lanes: Vec<&LaneEnd> = from_lanes
+                        .into_iter()
+                        .filter(|end| allows(&end.allowed_turns, turn_type))
+                        .collect();
+                    if from_lanes.is_empty() {
+                        continue;
+                    }
+                    for (from, to) in
+                        pick_lanes(&from_lanes, &to_lanes, turn_type, self.config.driving_side)
+                    {
+                        if let Some(geometry) = turn_geometry(from, to) {
+                            turns.push(Turn {
+                                from: from.id,
+                                to: to.id,
+                                turn_type,
+                                geometry,
+                                banned_when_coming_from: Vec::new(),
+                            });
+                        } else {
+                            warn!(
+                                "Can't make geometry for a turn from {:?} to {:?}",
+                                from.id, to.id
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // Via-way restrictions ban a turn at this intersection, depending where the vehicle came
+        // from
+        for (from, road) in &self.roads {
+            for (via, to, conditions) in &road.complicated_turn_restrictions {
+                if !conditions.is_unconditional() {
+                    continue;
+                }
+                for turn in &mut turns {
+                    if turn.from.road == *via && turn.to.road == *to {
+                        turn.banned_when_coming_from.push(*from);
+                    }
+                }
+            }
+        }
+
+        // Crosswalks
+        for r in &intersection.roads {
+            let road = &self.roads[r];
+            let has_crosswalk = if r.i1 == i {
+                road.crosswalk_backward
+            } else {
+                road.crosswalk_forward
+            };
+            if !has_crosswalk {
+                continue;
+            }
+            let walkable: Vec<&LaneEnd> = incoming
+                .iter()
+                .chain(outgoing.iter())
+                .filter(|end| end.id.road == *r && end.lt.is_walkable())
+                .collect();
+            let left = walkable.iter().min_by_key(|end| end.id.index);
+            let right = walkable.iter().max_by_key(|end| end.id.index);
+            if let (Some(left), Some(right)) = (left, right) {
+                if left.id == right.id {
+                    continue;
+                }
+                if let Ok(geometry) = PolyLine::new(vec![left.pt, right.pt]) {
+                    turns.push(Turn {
+                        from: left.id,
+                        to: right.id,
+                        turn_type: TurnType::Crosswalk,
+                        geometry,
+                        banned_when_coming_from: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        Ok(turns)
+    }
+
+    /// Calculates `get_turns` for every intersection. Intersections with broken geometry are
+    /// skipped.
+    pub fn all_turns(&self) -> BTreeMap<osm::NodeID, Vec<Turn>> {
+        let mut result = BTreeMap::new();
+        for i in self.intersections.keys() {
+            match self.get_turns(*i) {
+                Ok(turns) => {
+                    result.insert(*i, turns);
+                }
+                Err(err) => {
+                    error!("Can't make turns for {}: {}", i, err);
+                }
+            }
+        }
+        result
+    }
+
+    fn is_turn_allowed(&self, from: OriginalRoad, to: OriginalRoad, i: osm::NodeID) -> bool {
+        let mut only_allowed = Vec::new();
+        for (rt, target, conditions) in &self.roads[&from].turn_restrictions {
+            // A restriction only applies where the two roads meet. Turns restricted only for some
+            // vehicles or times still exist.
+            if (target.i1 != i && target.i2 != i) || !conditions.is_unconditional() {
+                continue;
+            }
+            match rt {
+                RestrictionType::BanTurns => {
+                    if *target == to {
+                        return false;
+                    }
+                }
+                RestrictionType::OnlyAllowTurns => {
+                    only_allowed.push(*target);
+                }
+            }
+        }
+        only_allowed.is_empty() || only_allowed.contains(&to)
+    }
+}
+
+struct LaneEnd {
+    id: LaneID,
+    lt: LaneType,
+    dir: Direction,
+    allowed_turns: BTreeSet<TurnDirection>,
+    // Where the lane touches the intersection
+    pt: Pt2D,
+    // The direction of travel at `pt`
+    angle: Angle,
+}
+
+// Lanes of a road belonging to one group of lane types, ordered from left to right from the point
+// of view of somebody travelling in that lane.
+fn lanes_left_to_right<'a>(
+    ends: &'a [LaneEnd],
+    road: OriginalRoad,
+    group: &[LaneType],
+) -> Vec<&'a LaneEnd> {
+    let mut lanes: Vec<&LaneEnd> = ends
+        .iter()
+        .filter(|end| end.id.road == road && group.contains(&end.lt))
+        .collect();
+    lanes.sort_by_key(|end| end.id.index);
+    // lane_specs_ltr is from the point of view of somebody facing forwards along the road. All of
+    // the lanes of one road coming into or out of an intersection point the same way.
+    if lanes.first().map(|end| end.dir) == Some(Direction::Back) {
+        lanes.reverse();
+    }
+    lanes
+}
+
+fn classify(from: Angle, to: Angle) -> TurnType {
+    let mut diff = to.normalized_degrees() - from.normalized_degrees();
+    if diff > 180.0 {
+        diff -= 360.0;
+    } else if diff <= -180.0 {
+        diff += 360.0;
+    }
+
+    if diff.abs() < 30.0 {
+        TurnType::Straight
+    } else if diff.abs() > 135.0 {
+        TurnType::UTurn
+    } else if diff > 0.0 {
+        // Angles increase clockwise
+        TurnType::Right
+    } else {
+        TurnType::Left
+    }
+}
+
+// Does a lane marked with these arrows permit a movement? Unmarked lanes permit anything. Slight
+// turns could be classified either way.
+fn allows(allowed_turns: &BTreeSet<TurnDirection>, turn_type: TurnType) -> bool {
+    allowed_turns.is_empty()
+        || allowed_turns.iter().any(|turn| match turn_type {
+            TurnType::Straight => matches!(
+                turn,
+                TurnDirection::Through
+                    | TurnDirection::SlightLeft
+                    | TurnDirection::SlightRight
+                    | TurnDirection::MergeToLeft
+                    | TurnDirection::MergeToRight
+            ),
+            TurnType::Left => matches!(
+                turn,
+                TurnDirection::SharpLeft | TurnDirection::Left | TurnDirection::SlightLeft
+            ),
+            TurnType::Right => matches!(
+                turn,
+                TurnDirection::SharpRight | TurnDirection::Right | TurnDirection::SlightRight
+            ),
+            TurnType::UTurn => *turn == TurnDirection::Reverse,
+            TurnType::Crosswalk => true,
+        })
+}
+
+// Which incoming lanes connect to which outgoing lanes. Both lists are ordered left to right.
+fn pick_lanes<'a>(
+    from: &[&'a LaneEnd],
+    to: &[&'a LaneEnd],
+    turn_type: TurnType,
+    driving_side: DrivingSide,
+) -> Vec<(&'a LaneEnd, &'a LaneEnd)> {
+    let leftmost = |lanes: &[&'a LaneEnd]| lanes[0];
+    let rightmost = |lanes: &[&'a LaneEnd]| lanes[lanes.len() - 1];
+
+    match turn_type {
+        TurnType::Straight => {
+            // Match lanes up one-to-one, fanning out or merging if the counts differ
+            let n = from.len().max(to.len());
+            let mut pairs = Vec::new();
+            for idx in 0..n {
+                pairs.push((from[idx * from.len() / n], to[idx * to.len() / n]));
+            }
+            pairs
+        }
+        TurnType::Left | TurnType::Right if from.len() == 1 => {
+            to.iter().map(|dst| (from[0], *dst)).collect()
+        }
+        TurnType::Left => vec![(leftmost(from), leftmost(to))],
+        TurnType::Right => vec![(rightmost(from), rightmost(to))],
+        // U-turns happen from the lane closest to the middle of the road
+        TurnType::UTurn => match driving_side {
+            DrivingSide::Right => vec![(leftmost(from), leftmost(to))],
+            DrivingSide::Left => vec![(rightmost(from), rightmost(to))],
+        },
+        TurnType::Crosswalk => unreachable!(),
+    }
+}
+
+// A cubic Bezier curve following the direction of both lanes
+fn turn_geometry(from: &LaneEnd, to: &LaneEnd) -> Option<PolyLine> {
+    let dist = from.pt.dist_to(to.pt);
+    if dist < Distance::meters(0.1) {
+        return None;
+    }
+    let control_dist = (dist / 3.0).max(Distance::meters(1.0));
+    let p0 = from.pt;
+    let p1 = from.pt.project_away(control_dist, from.angle);
+    let p2 = to.pt.project_away(control_dist, to.angle.opposite());
+    let p3 = to.pt;
+
+    let steps = 10;
+    let mut pts = Vec::new();
+    for step in 0..=steps {
+        let t = (step as f64) / (steps as f64);
+        let a = (1.0 - t).powi(3);
+        let b = 3.0 * (1.0 - t).powi(2) * t;
+        let c = 3.0 * (1.0 - t) * t.powi(2);
+        let d = t.powi(3);
+        pts.push(Pt2D::new(
+            a * p0.x() + b * p1.x() + c * p2.x() + d * p3.x(),
+            a * p0.y() + b * p1.y() + c * p2.y() + d * p3.y(),
+        ));
+    }
+    PolyLine::new(pts)
+        .or_else(|_| PolyLine::new(vec![p0, p3]))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{add_intersection, add_road};
+    use crate::RestrictionConditions;
+
+    #[test]
+    fn test_classify() {
+        let east = Angle::degrees(0.0);
+        assert_eq!(classify(east, Angle::degrees(10.0)), TurnType::Straight);
+        assert_eq!(classify(east, Angle::degrees(350.0)), TurnType::Straight);
+        // Angles increase clockwise, so south is to the right
+        assert_eq!(classify(east, Angle::degrees(90.0)), TurnType::Right);
+        assert_eq!(classify(east, Angle::degrees(270.0)), TurnType::Left);
+        assert_eq!(classify(east, Angle::degrees(180.0)), TurnType::UTurn);
+        assert_eq!(
+            classify(Angle::degrees(350.0), Angle::degrees(80.0)),
+            TurnType::Right
+        );
+    }
+
+    #[test]
+    fn test_allows() {
+        let arrows = |list: &[TurnDirection]| list.iter().cloned().collect::<BTreeSet<_>>();
+
+        assert!(allows(&BTreeSet::new(), TurnType::Left));
+        assert!(allows(&BTreeSet::new(), TurnType::UTurn));
+
+        let through_right = arrows(&[TurnDirection::Through, TurnDirection::Right]);
+        assert!(allows(&through_right, TurnType::Straight));
+        assert!(allows(&through_right, TurnType::Right));
+        assert!(!allows(&through_right, TurnType::Left));
+        assert!(!allows(&through_right, TurnType::UTurn));
+
+        // Slight turns could be classified either way
+        let slight_left = arrows(&[TurnDirection::SlightLeft]);
+        assert!(allows(&slight_left, TurnType::Straight));
+        assert!(allows(&slight_left, TurnType::Left));
+        assert!(!allows(&slight_left, TurnType::Right));
+
+        assert!(allows(&arrows(&[TurnDirection::Reverse]), TurnType::UTurn));
+        assert!(allows(
+            &arrows(&[TurnDirection::MergeToLeft]),
+            TurnType::Straight
+        ));
+    }
+
+    #[test]
+    fn test_pick_lanes() {
+        let lanes = |way: i64, count: usize| -> Vec<LaneEnd> {
+            (0..count)
+                .map(|index| LaneEnd {
+                    id: LaneID {
+                        road: OriginalRoad::new(way, (1, 2)),
+                        index,
+                    },
+                    lt: LaneType::Driving,
+                    dir: Direction::Fwd,
+                    allowed_turns: BTreeSet::new(),
+                    pt: Pt2D::new(index as f64, 0.0),
+                    angle: Angle::degrees(0.0),
+                })
+                .collect()
+        };
+        let pick =
+            |from: &[LaneEnd], to: &[LaneEnd], turn_type: TurnType, driving_side: DrivingSide| {
+                let from: Vec<&LaneEnd> = from.iter().collect();
+                let to: Vec<&LaneEnd> = to.iter().collect();
+                pick_lanes(&from, &to, turn_type, driving_side)
+                    .into_iter()
+                    .map(|(from, to)| (from.id.index, to.id.index))
+                    .collect::<Vec<_>>()
+            };
+        let two = lanes(10, 2);
+        let three = lanes(11, 3);
+        let one = lanes(12, 1);
+
+        // Straight through fans out when the number of lanes grows
+        assert_eq!(
+            pick(&two, &three, TurnType::Straight, DrivingSide::Right),
+            vec![(0, 0), (0, 1), (1, 2)]
+        );
+        // And merges when it shrinks
+        assert_eq!(
+            pick(&three, &two, TurnType::Straight, DrivingSide::Right),
+            vec![(0, 0), (1, 0), (2, 1)]
+        );
+        // Turns stay on the side they're turning towards
+        assert_eq!(
+            pick(&two, &three, TurnType::Left, DrivingSide::Right),
+            vec![(0, 0)]
+        );
+        assert_eq!(
+            pick(&two, &three, TurnType::Right, DrivingSide::Right),
+            vec![(1, 2)]
+        );
+        // Unless there's only one lane to turn from
+        assert_eq!(
+            pick(&one, &two, TurnType::Right, DrivingSide::Right),
+            vec![(0, 0), (0, 1)]
+        );
+        // U-turns happen from the lane closest to the middle of the road
+        assert_eq!(
+            pick(&two, &two, TurnType::UTurn, DrivingSide::Right),
+            vec![(0, 0)]
+        );
+        assert_eq!(
+            pick(&two, &two, TurnType::UTurn, DrivingSide::Left),
+            vec![(1, 1)]
+        );
+    }
+
+    // A four-way intersection at the origin. Returns the roads to the west, north, east, and south.
+    fn four_way() -> (StreetNetwork, [OriginalRoad; 4]) {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, -100.0, 0.0);
+        add_intersection(&mut streets, 3, 0.0, -100.0);
+        add_intersection(&mut streets, 4, 100.0, 0.0);
+        add_intersection(&mut streets, 5, 0.0, 100.0);
+        let west = add_road(&mut streets, 10, 2, 1, &[]);
+        let north = add_road(&mut streets, 11, 1, 3, &[]);
+        let east = add_road(&mut streets, 12, 1, 4, &[]);
+        let south = add_road(&mut streets, 13, 1, 5, &[]);
+        (streets, [west, north, east, south])
+    }
+
+    // The types of vehicle turns between two roads
+    fn turn_types(turns: &[Turn], from: OriginalRoad, to: OriginalRoad) -> BTreeSet<TurnType> {
+        turns
+            .iter()
+            .filter(|turn| turn.from.road == from && turn.to.road == to)
+            .map(|turn| turn.turn_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_four_way_turns() {
+        let (streets, [west, north, east, south]) = four_way();
+        let turns = streets.get_turns(osm::NodeID(1)).unwrap();
+
+        // Travelling east from the west road
+        assert_eq!(
+            turn_types(&turns, west, east),
+            BTreeSet::from([TurnType::Straight])
+        );
+        assert_eq!(
+            turn_types(&turns, west, north),
+            BTreeSet::from([TurnType::Left])
+        );
+        assert_eq!(
+            turn_types(&turns, west, south),
+            BTreeSet::from([TurnType::Right])
+        );
+        // No U-turns except at dead-ends. Every road has sidewalks on both sides, so the only
+        // movement from a road back to itself is the crosswalk over it.
+        for r in [west, north, east, south] {
+            assert_eq!(
+                turn_types(&turns, r, r),
+                BTreeSet::from([TurnType::Crosswalk])
+            );
+        }
+
+        assert!(streets.get_turns(osm::NodeID(99)).is_err());
+    }
+
+    #[test]
+    fn test_turn_restrictions() {
+        let (mut streets, [west, north, east, south]) = four_way();
+        let road = streets.roads.get_mut(&west).unwrap();
+        road.turn_restrictions.push((
+            RestrictionType::BanTurns,
+            north,
+            RestrictionConditions::default(),
+        ));
+        // Restrictions that don't apply to everybody all the time don't remove turns
+        road.turn_restrictions.push((
+            RestrictionType::BanTurns,
+            south,
+            RestrictionConditions {
+                except: vec![crate::VehicleType::Bicycle],
+                ..Default::default()
+            },
+        ));
+        let turns = streets.get_turns(osm::NodeID(1)).unwrap();
+        assert!(turn_types(&turns, west, north).is_empty());
+        assert!(!turn_types(&turns, west, south).is_empty());
+        assert!(!turn_types(&turns, west, east).is_empty());
+        // Other roads aren't affected
+        assert!(!turn_types(&turns, south, north).is_empty());
+
+        let road = streets.roads.get_mut(&west).unwrap();
+        road.turn_restrictions = vec![(
+            RestrictionType::OnlyAllowTurns,
+            east,
+            RestrictionConditions::default(),
+        )];
+        let turns = streets.get_turns(osm::NodeID(1)).unwrap();
+        assert!(turn_types(&turns, west, north).is_empty());
+        assert!(turn_types(&turns, west, south).is_empty());
+        assert_eq!(
+            turn_types(&turns, west, east),
+            BTreeSet::from([TurnType::Straight])
+        );
+    }
+}
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags};
 use geom::Distance;
 
-use crate::{osm, OriginalRoad};
+use crate::{osm, LaneType, OriginalRoad, SpeedLimitDefaults};
 
 /// None corresponds to the native name
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -69,6 +69,29 @@ pub struct MapConfig {
     /// If true, use experimental osm2lanes for figuring out lanes per road. If false, use the
     /// classic algorithm.
     pub osm2lanes: bool,
+    /// If true, lane widths come from `width`, `width:lanes`, and similar tags where they're
+    /// present. If false, only sidewalk widths are taken from OSM.
+    #[serde(default)]
+    pub lane_widths_from_tags: bool,
+    /// Speed limits for roads without `maxspeed` tagged
+    #[serde(default)]
+    pub default_speed_limits: SpeedLimitDefaults,
+    /// The country the map is in, as an ISO 3166-1 alpha-2 code like "GB", if known
+    #[serde(default)]
+    pub country_code: Option<String>,
+    /// Replaces the typical width of these lane types. Only lanes that would've gotten the usual
+    /// default are changed, so narrow alleys stay narrow.
+    #[serde(
+        default,
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub default_lane_widths: BTreeMap<LaneType, Distance>,
+    /// When inferring sidewalks, assume roads with these `highway` types don't have any
+    #[serde(default)]
+    pub highways_without_sidewalks: Vec<String>,
+    #[serde(default)]
+    pub marking_style: MarkingStyle,
 
     /// Enable experimental dog-leg intersection merging
     pub find_dog_legs_experiment: bool,
@@ -85,6 +108,12 @@ impl MapConfig {
             street_parking_spot_length: Distance::meters(8.0),
             turn_on_red: true,
             osm2lanes: false,
+            lane_widths_from_tags: false,
+            default_speed_limits: SpeedLimitDefaults::generic(),
+            country_code: None,
+            default_lane_widths: BTreeMap::new(),
+            highways_without_sidewalks: Vec::new(),
+            marking_style: MarkingStyle::YellowCenterLines,
             find_dog_legs_experiment: false,
             merge_osm_ways: Vec::new(),
         }
@@ -97,6 +126,25 @@ pub enum DrivingSide {
     Left,
 }
 
+/// How lane markings are painted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkingStyle {
+    /// Yellow lines separate opposing traffic, like in North America
+    #[default]
+    YellowCenterLines,
+    /// Every line is white, like in most of Europe
+    WhiteCenterLines,
+}
+
+impl MarkingStyle {
+    pub fn center_line_color(self) -> &'static str {
+        match self {
+            MarkingStyle::YellowCenterLines => "yellow",
+            MarkingStyle::WhiteCenterLines => "white",
+        }
+    }
+}
+
 /// How a lane of travel is interrupted.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum InterruptionType {
@@ -151,9 +199,9 @@ pub enum IntersectionComplexity {
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum ControlType {
-    Uncontrolled, // Pretty sure this is a term that implies right of way rules somewhere.
-    //TODO YieldSign,
-    StopSign,      // Signed is a good standard of safety
+    Uncontrolled,  // Pretty sure this is a term that implies right of way rules somewhere.
+    YieldSign,     // Some approaches give way, but nobody has to stop
+    StopSign,      // Signed is a good standard of safety. Some or all approaches stop.
     TrafficSignal, // Signalled is better.
     Border,        //TODO move to using IntersectionComplexity::MapEdge
     Construction,  // Are these treated as "closed"?
@@ -0,0 +1,432 @@
+use std::fmt;
+
+use geom::PolyLine;
+
+use crate::{osm, OriginalRoad, StreetNetwork};
+
+/// Something wrong with a `StreetNetwork`. Usually this indicates a bug in a transformation or
+/// in the import.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// A road points to an intersection that doesn't exist
+    MissingIntersection {
+        road: OriginalRoad,
+        intersection: osm::NodeID,
+    },
+    /// A road ends at an intersection, but the intersection doesn't list it
+    RoadNotListed {
+        road: OriginalRoad,
+        intersection: osm::NodeID,
+    },
+    /// An intersection lists a road that doesn't exist or doesn't end there
+    UnknownRoad {
+        intersection: osm::NodeID,
+        road: OriginalRoad,
+    },
+    /// An intersection's roads aren't in clockwise order
+    UnsortedRoads { intersection: osm::NodeID },
+    /// An intersection's roads can't be sorted clockwise, so they're left in their old order
+    CantSortRoads {
+        intersection: osm::NodeID,
+        error: String,
+    },
+    /// A road starts and ends at the same intersection
+    Loop { road: OriginalRoad },
+    /// A road's center line can't form a valid `PolyLine`
+    DegenerateGeometry { road: OriginalRoad, error: String },
+    /// An intersection without any roads
+    DanglingIntersection { intersection: osm::NodeID },
+    /// A turn restriction refers to a road that doesn't exist or doesn't connect
+    DanglingTurnRestriction {
+        from: OriginalRoad,
+        to: OriginalRoad,
+    },
+    /// An intersection has an approach control for a road that doesn't exist or doesn't end there
+    DanglingApproachControl {
+        intersection: osm::NodeID,
+        road: OriginalRoad,
+    },
+    /// The polygon for an intersection couldn't be calculated
+    IntersectionGeometry {
+        intersection: osm::NodeID,
+        error: String,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::MissingIntersection { road, intersection } => {
+                write!(f, "{} points to missing {}", road, intersection)
+            }
+            Violation::RoadNotListed { road, intersection } => {
+                write!(f, "{} doesn't list {}", intersection, road)
+            }
+            Violation::UnknownRoad { intersection, road } => {
+                write!(
+                    f,
+                    "{} lists {}, which doesn't end there",
+                    intersection, road
+                )
+            }
+            Violation::UnsortedRoads { intersection } => {
+                write!(f, "The roads of {} aren't sorted clockwise", intersection)
+            }
+            Violation::CantSortRoads {
+                intersection,
+                error,
+            } => write!(f, "Can't sort the roads of {}: {}", intersection, error),
+            Violation::Loop { road } => write!(f, "{} is a loop", road),
+            Violation::DegenerateGeometry { road, error } => {
+                write!(f, "{} has broken geometry: {}", road, error)
+            }
+            Violation::DanglingIntersection { intersection } => {
+                write!(f, "{} has no roads", intersection)
+            }
+            Violation::DanglingTurnRestriction { from, to } => {
+                write!(f, "Turn restriction from {} to {} is dangling", from, to)
+            }
+            Violation::DanglingApproachControl { intersection, road } => {
+                write!(f, "{} has an approach control for {}", intersection, road)
+            }
+            Violation::IntersectionGeometry {
+                intersection,
+                error,
+            } => write!(f, "Can't make geometry for {}: {}", intersection, error),
+        }
+    }
+}
+
+impl StreetNetwork {
+    /// Check every invariant of the network, returning all problems found. An empty list means
+    /// the network is valid.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        for (id, road) in &self.roads {
+            for i in [id.i1, id.i2] {
+                match self.intersections.get(&i) {
+                    Some(intersection) => {
+                        if !intersection.roads.contains(id) {
+                            violations.push(Violation::RoadNotListed {
+                                road: *id,
+                                intersection: i,
+                            });
+                        }
+                    }
+                    None => {
+                        violations.push(Violation::MissingIntersection {
+                            road: *id,
+                            intersection: i,
+                        });
+                    }
+                }
+            }
+            if id.i1 == id.i2 {
+                violations.push(Violation::Loop { road: *id });
+            }
+            if let Err(err) = PolyLine::new(road.osm_center_points.clone()) {
+                violations.push(Violation::DegenerateGeometry {
+                    road: *id,
+                    error: err.to_string(),
+                });
+            }
+
+            for (_, to, _) in &road.turn_restrictions {
+                if !self.roads.contains_key(to) || !id.has_common_endpoint(*to) {
+                    violations.push(Violation::DanglingTurnRestriction { from: *id, to: *to });
+                }
+            }
+            for (via, to, _) in &road.complicated_turn_restrictions {
+                if !self.roads.contains_key(via)
+                    || !self.roads.contains_key(to)
+                    || !id.has_common_endpoint(*via)
+                    || !via.has_common_endpoint(*to)
+                {
+                    violations.push(Violation::DanglingTurnRestriction { from: *id, to: *to });
+                }
+            }
+        }
+
+        for (id, intersection) in &self.intersections {
+            if intersection.roads.is_empty() {
+                violations.push(Violation::DanglingIntersection { intersection: *id });
+                continue;
+            }
+            for r in intersection.approach_controls.keys() {
+                if !self.roads.contains_key(r) || (r.i1 != *id && r.i2 != *id) {
+                    violations.push(Violation::DanglingApproachControl {
+                        intersection: *id,
+                        road: *r,
+                    });
+                }
+            }
+            let mut all_known = true;
+            for r in &intersection.roads {
+                if !self.roads.contains_key(r) || (r.i1 != *id && r.i2 != *id) {
+                    violations.push(Violation::UnknownRoad {
+                        intersection: *id,
+                        road: *r,
+                    });
+                    all_known = false;
+                }
+            }
+            // Only check the order when the roads are sensible; otherwise sorting fails anyway
+            if all_known {
+                match self.calculate_sorted_roads(*id) {
+                    Ok(sorted) => {
+                        if !same_cyclic_order(&intersection.roads, &sorted) {
+                            violations.push(Violation::UnsortedRoads { intersection: *id });
+                        }
+                    }
+                    Err(err) => {
+                        violations.push(Violation::CantSortRoads {
+                            intersection: *id,
+                            error: err.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+// Clockwise order doesn't have a fixed starting point
+fn same_cyclic_order(actual: &[OriginalRoad], expected: &[OriginalRoad]) -> bool {
+    if actual.len() != expected.len() {
+        return false;
+    }
+    if actual.is_empty() {
+        return true;
+    }
+    (0..expected.len()).any(|offset| {
+        actual
+            .iter()
+            .enumerate()
+            .all(|(idx, r)| *r == expected[(idx + offset) % expected.len()])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use abstutil::Timer;
+    use geom::Pt2D;
+
+    use super::*;
+    use crate::initial::InitialMap;
+    use crate::test_utils::{add_intersection, add_road};
+    use crate::{
+        ApproachControl, ControlType, Intersection, IntersectionComplexity, RestrictionConditions,
+        RestrictionType,
+    };
+
+    // Three roads meeting at intersection 1
+    fn network() -> (StreetNetwork, [OriginalRoad; 3]) {
+        let mut streets = StreetNetwork::blank();
+        add_intersection(&mut streets, 1, 0.0, 0.0);
+        add_intersection(&mut streets, 2, -100.0, 0.0);
+        add_intersection(&mut streets, 3, 100.0, 0.0);
+        add_intersection(&mut streets, 4, 0.0, 100.0);
+        let a = add_road(&mut streets, 10, 2, 1, &[]);
+        let b = add_road(&mut streets, 11, 1, 3, &[]);
+        let c = add_road(&mut streets, 12, 1, 4, &[]);
+        assert!(streets.validate().is_empty());
+        (streets, [a, b, c])
+    }
+
+    #[test]
+    fn test_missing_intersection() {
+        let (mut streets, [_, b, _]) = network();
+        streets.intersections.remove(&osm::NodeID(3));
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::MissingIntersection {
+                road: b,
+                intersection: osm::NodeID(3),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_road_not_listed() {
+        let (mut streets, [a, _, _]) = network();
+        streets
+            .intersections
+            .get_mut(&osm::NodeID(1))
+            .unwrap()
+            .roads
+            .retain(|r| *r != a);
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::RoadNotListed {
+                road: a,
+                intersection: osm::NodeID(1),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unknown_road() {
+        let (mut streets, _) = network();
+        let bogus = OriginalRoad::new(99, (1, 5));
+        streets
+            .intersections
+            .get_mut(&osm::NodeID(1))
+            .unwrap()
+            .roads
+            .push(bogus);
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::UnknownRoad {
+                intersection: osm::NodeID(1),
+                road: bogus,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unsorted_roads() {
+        let (mut streets, _) = network();
+        // With three roads, the reverse order isn't the same cycle
+        streets
+            .intersections
+            .get_mut(&osm::NodeID(1))
+            .unwrap()
+            .roads
+            .reverse();
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::UnsortedRoads {
+                intersection: osm::NodeID(1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_broken_geometry() {
+        let (mut streets, [a, _, _]) = network();
+        // Both points are the same
+        let pt = streets.intersections[&osm::NodeID(2)].point;
+        streets.roads.get_mut(&a).unwrap().osm_center_points = vec![pt, pt];
+
+        let violations = streets.validate();
+        assert_eq!(violations.len(), 3);
+        assert!(matches!(
+            violations[0],
+            Violation::DegenerateGeometry { road, .. } if road == a
+        ));
+        // The roads at either end can't be sorted anymore
+        for (violation, i) in violations[1..].iter().zip([1, 2]) {
+            assert!(matches!(
+                violation,
+                Violation::CantSortRoads { intersection, .. } if *intersection == osm::NodeID(i)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        let (mut streets, [a, _, _]) = network();
+        let mut road = streets.roads[&a].clone();
+        road.osm_center_points = vec![
+            Pt2D::new(-100.0, 0.0),
+            Pt2D::new(-150.0, 50.0),
+            Pt2D::new(-150.0, -50.0),
+            Pt2D::new(-100.0, 0.0),
+        ];
+        let id = OriginalRoad::new(20, (2, 2));
+        streets.roads.insert(id, road);
+        // Record the loop once, without resorting the roads
+        streets
+            .intersections
+            .get_mut(&osm::NodeID(2))
+            .unwrap()
+            .roads
+            .push(id);
+        assert!(streets.validate().contains(&Violation::Loop { road: id }));
+    }
+
+    #[test]
+    fn test_dangling_intersection() {
+        let mut streets = StreetNetwork::blank();
+        assert!(streets.validate().is_empty());
+
+        streets.intersections.insert(
+            osm::NodeID(1),
+            Intersection::new(
+                Pt2D::new(0.0, 0.0),
+                IntersectionComplexity::Crossing,
+                ControlType::StopSign,
+            ),
+        );
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::DanglingIntersection {
+                intersection: osm::NodeID(1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dangling_turn_restriction() {
+        let (mut streets, [a, b, _]) = network();
+        add_intersection(&mut streets, 5, 200.0, 0.0);
+        let far = add_road(&mut streets, 13, 3, 5, &[]);
+        let road = streets.roads.get_mut(&a).unwrap();
+        // a doesn't touch far
+        road.turn_restrictions.push((
+            RestrictionType::BanTurns,
+            far,
+            RestrictionConditions::default(),
+        ));
+        // But going via b, it does
+        road.complicated_turn_restrictions
+            .push((b, far, RestrictionConditions::default()));
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::DanglingTurnRestriction { from: a, to: far }]
+        );
+
+        // Remove the via road
+        streets.roads.get_mut(&a).unwrap().turn_restrictions.clear();
+        streets.remove_road(&b);
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::DanglingTurnRestriction { from: a, to: far }]
+        );
+    }
+
+    #[test]
+    fn test_dangling_approach_control() {
+        let (mut streets, [_, b, _]) = network();
+        // b doesn't end at 2
+        streets
+            .intersections
+            .get_mut(&osm::NodeID(2))
+            .unwrap()
+            .approach_controls
+            .insert(b, ApproachControl::Stop);
+        assert_eq!(
+            streets.validate(),
+            vec![Violation::DanglingApproachControl {
+                intersection: osm::NodeID(2),
+                road: b,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_intersection_geometry() {
+        let (mut streets, _) = network();
+        // An intersection without roads has no geometry. InitialMap reports this, not validate.
+        add_intersection(&mut streets, 5, 500.0, 500.0);
+        let map = InitialMap::new(&streets, &mut Timer::throwaway());
+        assert_eq!(map.errors.len(), 1);
+        assert!(matches!(
+            map.errors[0],
+            Violation::IntersectionGeometry { intersection, .. } if intersection == osm::NodeID(5)
+        ));
+    }
+}
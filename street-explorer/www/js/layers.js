@@ -49,7 +49,7 @@ export const makePlainGeoJsonLayer = (text) => {
 };
 
 export const makeLanePolygonLayer = (text) => {
-  // These could change per locale
+  // Center lines say what color they are, since that depends on the locale
   const colors = {
     Driving: "black",
     Parking: "#333333",
@@ -125,13 +125,18 @@ export const makeLaneMarkingsLayer = (text) => {
     "lane arrow": "white",
     "buffer edge": "white",
     "buffer stripe": "white",
+    "stop line": "white",
+    "yield line": "white",
+    "crosswalk stripe": "white",
+    "crosswalk line": "white",
+    "turn arrow": "white",
   };
 
   return new L.geoJSON(JSON.parse(text), {
     style: function (feature) {
       return {
         fill: true,
-        fillColor: colors[feature.properties.type],
+        fillColor: feature.properties.color || colors[feature.properties.type],
         fillOpacity: 0.9,
         stroke: false,
       };
@@ -139,6 +144,54 @@ export const makeLaneMarkingsLayer = (text) => {
   });
 };
 
+export const makePublicTransportStopsLayer = (text) => {
+  return new L.geoJSON(JSON.parse(text), {
+    pointToLayer: function (feature, latlng) {
+      return L.circleMarker(latlng, {
+        radius: 5,
+        color: "#BE4A4C",
+        fillOpacity: 0.8,
+      });
+    },
+    onEachFeature: function (feature, layer) {
+      const osmUrl = feature.properties.osm_link;
+      delete feature.properties.osm_link;
+      const jsonDump = JSON.stringify(feature.properties, null, "<br/>");
+      const popup =
+        jsonDump + `<br/><a href="${osmUrl}" target="_blank">Open in OSM</a>`;
+      layer.bindPopup(popup);
+    },
+  });
+};
+
+export const makeAreasLayer = (text) => {
+  const colors = {
+    Building: "#8C8C8C",
+    ParkingLot: "#D3C7A9",
+    Landuse: "#A8D5A2",
+  };
+
+  return new L.geoJSON(JSON.parse(text), {
+    style: function (feature) {
+      return {
+        fill: true,
+        fillColor: colors[feature.properties.type],
+        fillOpacity: 0.5,
+        stroke: false,
+      };
+    },
+    onEachFeature: function (feature, layer) {
+      const osmUrl = feature.properties.osm_link;
+      delete feature.properties.osm_link;
+      let popup = JSON.stringify(feature.properties, null, "<br/>");
+      if (osmUrl) {
+        popup += `<br/><a href="${osmUrl}" target="_blank">Open in OSM</a>`;
+      }
+      layer.bindPopup(popup);
+    },
+  });
+};
+
 export const makeOsmLayer = (text) => {
   return new L.OSM.DataLayer(
     new DOMParser().parseFromString(text, "application/xml"),
@@ -7,12 +7,14 @@ import {
 } from "./files.js";
 import { loadTests } from "./tests.js";
 import {
+  makeAreasLayer,
   makeDebugLayer,
   makeDotLayer,
   makeLaneMarkingsLayer,
   makeLanePolygonLayer,
   makeOsmLayer,
   makePlainGeoJsonLayer,
+  makePublicTransportStopsLayer,
 } from "./layers.js";
 import {
   LayerGroup,
@@ -138,8 +140,7 @@ class TestCase {
 
       importButton.innerText = "Importing OSM data...";
 
-      const drivingSide =
-        app.getImportSettings().drivingSideForNewImports || "Right";
+      const drivingSide = app.getImportSettings().drivingSide || "Right";
 
       importOSM("Imported area", app, osmInput, drivingSide, true);
       const bounds = app.layers
@@ -191,19 +192,24 @@ class TestCase {
 function importOSM(groupName, app, osmXML, drivingSide, addOSMLayer) {
   try {
     const importSettings = app.getImportSettings();
+    // When detecting the region fails, fall back to driving on the right
     const network = new JsStreetNetwork(osmXML, {
-      driving_side: drivingSide,
+      driving_side: drivingSide === "Detect" ? "Right" : drivingSide,
+      detect_region: drivingSide === "Detect",
       debug_each_step: !!importSettings.debugEachStep,
       dual_carriageway_experiment: !!importSettings.dualCarriagewayExperiment,
-      cycletrack_snapping_experiment:
-        !!importSettings.cycletrackSnappingExperiment,
       inferred_sidewalks: importSettings.sidewalks === "infer",
       osm2lanes: !!importSettings.osm2lanes,
+      lane_widths_from_tags: !!importSettings.laneWidthsFromTags,
+      areas: !!importSettings.areas,
     });
     var group = new LayerGroup(groupName, app.map);
     if (addOSMLayer) {
       group.addLayer("OSM", makeOsmLayer(osmXML), { enabled: false });
     }
+    group.addLazyLayer("Areas", () =>
+      makeAreasLayer(network.toAreasGeojson())
+    );
     group.addLayer("Geometry", makePlainGeoJsonLayer(network.toGeojsonPlain()));
     group.addLayer(
       "Lane polygons",
@@ -213,6 +219,9 @@ function importOSM(groupName, app, osmXML, drivingSide, addOSMLayer) {
       "Lane markings",
       makeLaneMarkingsLayer(network.toLaneMarkingsGeojson())
     );
+    group.addLazyLayer("Public transport stops", () =>
+      makePublicTransportStopsLayer(network.toPublicTransportStopsGeojson())
+    );
     group.addLazyLayer("Debug road ordering", () =>
       makeDebugLayer(network.debugClockwiseOrderingGeojson())
     );
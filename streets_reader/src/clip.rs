@@ -1,11 +1,19 @@
 use abstutil::Timer;
 use anyhow::Result;
 use geom::PolyLine;
-use osm2streets::{osm, ControlType, IntersectionComplexity, StreetNetwork};
-
-// TODO This needs to update turn restrictions too
+use osm2streets::{
+    osm, ApproachSnapshot, ControlType, IntersectionComplexity, RestrictionSnapshot, StreetNetwork,
+    Violation,
+};
+
+/// Remove everything outside of the `boundary_polygon`, trimming roads that cross it and creating
+/// border intersections. Turn restrictions are remapped to renamed roads or dropped, barrier and
+/// crossing nodes outside the boundary are removed, and areas are clipped to the boundary.
+/// Approach controls follow their roads.
 pub fn clip_map(streets: &mut StreetNetwork, timer: &mut Timer) -> Result<()> {
     timer.start("clipping map to boundary");
+    let restrictions = RestrictionSnapshot::new(streets);
+    let approaches = ApproachSnapshot::new(streets);
 
     // So we can use retain without borrowing issues
     let boundary_polygon = streets.boundary_polygon.clone();
@@ -117,10 +125,71 @@ pub fn clip_map(streets: &mut StreetNetwork, timer: &mut Timer) -> Result<()> {
         }
     }
 
+    // Barriers and crossings on the part of a road that got trimmed away are gone too
+    for road in streets.roads.values_mut() {
+        road.barrier_nodes
+            .retain(|pt| boundary_polygon.contains_pt(*pt));
+        road.crossing_nodes
+            .retain(|(_, pt, _)| boundary_polygon.contains_pt(*pt));
+    }
+
     if streets.roads.is_empty() {
         bail!("There are no roads inside the clipping polygon");
     }
 
+    let mut clipped_areas = Vec::new();
+    for area in streets.areas.drain(..) {
+        // Most areas are entirely inside, so skip the expensive intersection
+        if area
+            .polygon
+            .get_outer_ring()
+            .points()
+            .iter()
+            .all(|pt| boundary_polygon.contains_pt(*pt))
+        {
+            clipped_areas.push(area);
+            continue;
+        }
+        match boundary_polygon.intersection(&area.polygon) {
+            Ok(polygons) => {
+                for polygon in polygons {
+                    let mut piece = area.clone();
+                    piece.polygon = polygon;
+                    clipped_areas.push(piece);
+                }
+            }
+            Err(err) => {
+                warn!("Dropping area {:?}: can't clip it: {}", area.osm_id, err);
+            }
+        }
+    }
+    streets.areas = clipped_areas;
+
+    restrictions.fix("clipping", streets);
+    approaches.fix(streets);
+    check_clipped(streets);
+
     timer.stop("clipping map to boundary");
     Ok(())
 }
+
+/// Warn about anything left after clipping that refers to something that was removed. These are
+/// bugs in clipping, but the rest of the network is still usable, so they don't stop the import.
+/// Problems that could already be in the input, like loops or odd geometry, aren't checked here.
+fn check_clipped(streets: &StreetNetwork) {
+    for violation in streets.validate() {
+        match violation {
+            Violation::Loop { .. }
+            | Violation::DegenerateGeometry { .. }
+            | Violation::UnsortedRoads { .. }
+            | Violation::CantSortRoads { .. }
+            | Violation::IntersectionGeometry { .. } => {}
+            _ => warn!("After clipping, the network is broken: {}", violation),
+        }
+    }
+    for (id, i) in &streets.intersections {
+        if i.control == ControlType::Border && i.roads.len() != 1 {
+            warn!("After clipping, border {} has {} roads", id, i.roads.len());
+        }
+    }
+}
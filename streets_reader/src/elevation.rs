@@ -0,0 +1,423 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use abstutil::{Tags, Timer};
+use anyhow::Result;
+use geom::{Distance, LonLat, PolyLine};
+use osm2streets::osm::NodeID;
+use osm2streets::StreetNetwork;
+
+/// How far apart to sample the DEM along each road
+const SAMPLE_SPACING: Distance = Distance::const_meters(5.0);
+
+/// Fill out `Intersection::elevation` and `Road::percent_incline` from a digital elevation model.
+/// `path` is a GeoTIFF (`.tif` or `.tiff`) in WGS84, a single SRTM `.hgt` tile, or a directory of
+/// `.hgt` tiles.
+///
+/// Bridges, tunnels, and anything on a non-zero `layer` don't follow the terrain. Intersections
+/// only touching those roads get an elevation interpolated from the nearest grounded ones, and
+/// those roads get a constant grade between their endpoints.
+pub fn add_elevation(streets: &mut StreetNetwork, path: &str, timer: &mut Timer) -> Result<()> {
+    timer.start("load DEM");
+    let dem = Dem::load(path)?;
+    timer.stop("load DEM");
+
+    let grounded: BTreeSet<NodeID> = streets
+        .intersections
+        .iter()
+        .filter(|(_, i)| {
+            i.roads
+                .iter()
+                .any(|r| !is_elevated(&streets.roads[r].osm_tags))
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    let mut elevation: BTreeMap<NodeID, Distance> = BTreeMap::new();
+    let mut missing = 0;
+    timer.start_iter("sample intersection elevation", streets.intersections.len());
+    for (id, i) in &streets.intersections {
+        timer.next();
+        match dem.sample(i.point.to_gps(&streets.gps_bounds)) {
+            Some(height) => {
+                elevation.insert(*id, height);
+            }
+            None => {
+                missing += 1;
+                elevation.insert(*id, Distance::ZERO);
+            }
+        }
+    }
+    if missing > 0 {
+        warn!("The DEM doesn't cover {} intersections", missing);
+    }
+
+    interpolate_elevated(streets, &grounded, &mut elevation);
+    for (id, i) in &mut streets.intersections {
+        i.elevation = elevation[id];
+    }
+
+    timer.start_iter("calculate road grades", streets.roads.len());
+    let gps_bounds = streets.gps_bounds.clone();
+    for (id, road) in &mut streets.roads {
+        timer.next();
+        let length = road.length();
+        if length == Distance::ZERO {
+            continue;
+        }
+        let endpoints_grade =
+            (elevation[&id.i2] - elevation[&id.i1]).inner_meters() / length.inner_meters();
+        road.percent_incline = if is_elevated(&road.osm_tags) {
+            endpoints_grade
+        } else {
+            PolyLine::new(road.osm_center_points.clone())
+                .ok()
+                .and_then(|pl| {
+                    let mut samples = Vec::new();
+                    let mut dist = Distance::ZERO;
+                    while dist <= pl.length() {
+                        let pt = pl.must_dist_along(dist).0;
+                        if let Some(height) = dem.sample(pt.to_gps(&gps_bounds)) {
+                            samples.push((dist.inner_meters(), height.inner_meters()));
+                        }
+                        dist += SAMPLE_SPACING;
+                    }
+                    least_squares_slope(&samples)
+                })
+                .unwrap_or(endpoints_grade)
+        };
+    }
+
+    Ok(())
+}
+
+/// Does this road not follow the terrain?
+fn is_elevated(tags: &Tags) -> bool {
+    (tags.contains_key("bridge") && !tags.is("bridge", "no"))
+        || (tags.contains_key("tunnel") && !tags.is("tunnel", "no"))
+        || tags
+            .get("layer")
+            .and_then(|layer| layer.parse::<isize>().ok())
+            .map(|layer| layer != 0)
+            .unwrap_or(false)
+}
+
+/// Replace the sampled elevation of every intersection that isn't grounded. Along a chain of
+/// bridge or tunnel segments, each intersection winds up as the length-weighted average of its
+/// neighbors, which linearly interpolates between the grounded ends.
+fn interpolate_elevated(
+    streets: &StreetNetwork,
+    grounded: &BTreeSet<NodeID>,
+    elevation: &mut BTreeMap<NodeID, Distance>,
+) {
+    let floating: Vec<NodeID> = streets
+        .intersections
+        .iter()
+        .filter(|(id, i)| !grounded.contains(id) && !i.roads.is_empty())
+        .map(|(id, _)| *id)
+        .collect();
+    if floating.is_empty() {
+        return;
+    }
+
+    // Floating intersections with no path to the ground just keep the terrain height
+    let mut reachable = BTreeSet::new();
+    let mut queue: Vec<NodeID> = grounded.iter().cloned().collect();
+    while let Some(i) = queue.pop() {
+        for r in &streets.intersections[&i].roads {
+            let other = r.other_side(i);
+            if !grounded.contains(&other) && reachable.insert(other) {
+                queue.push(other);
+            }
+        }
+    }
+
+    // Gauss-Seidel iterations converge quickly for the short chains found in practice
+    for _ in 0..100 {
+        let mut max_change: f64 = 0.0;
+        for i in &floating {
+            if !reachable.contains(i) {
+                continue;
+            }
+            let mut weighted_sum = 0.0;
+            let mut total_weight = 0.0;
+            for r in &streets.intersections[i].roads {
+                let weight = 1.0 / streets.roads[r].length().inner_meters().max(1.0);
+                weighted_sum += weight * elevation[&r.other_side(*i)].inner_meters();
+                total_weight += weight;
+            }
+            let updated = Distance::meters(weighted_sum / total_weight);
+            max_change = max_change.max((updated - elevation[i]).inner_meters().abs());
+            elevation.insert(*i, updated);
+        }
+        if max_change < 0.01 {
+            break;
+        }
+    }
+}
+
+/// The slope of the best-fit line through (distance, height) samples
+fn least_squares_slope(samples: &[(f64, f64)]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for (x, y) in samples {
+        numerator += (x - mean_x) * (y - mean_y);
+        denominator += (x - mean_x).powi(2);
+    }
+    if denominator == 0.0 {
+        return None;
+    }
+    Some(numerator / denominator)
+}
+
+enum Dem {
+    Hgt(Vec<HgtTile>),
+    GeoTiff(Raster),
+}
+
+impl Dem {
+    fn load(path: &str) -> Result<Dem> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            return Ok(Dem::GeoTiff(Raster::read_geotiff(path)?));
+        }
+        if lower.ends_with(".hgt") {
+            return Ok(Dem::Hgt(vec![HgtTile::read(Path::new(path))?]));
+        }
+        if Path::new(path).is_dir() {
+            let mut tiles = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path
+                    .extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("hgt"))
+                    .unwrap_or(false)
+                {
+                    tiles.push(HgtTile::read(&entry_path)?);
+                }
+            }
+            if tiles.is_empty() {
+                bail!("No .hgt tiles in {}", path);
+            }
+            return Ok(Dem::Hgt(tiles));
+        }
+        bail!(
+            "Don't know how to read elevation from {}; expected .hgt, .tif, or a directory",
+            path
+        );
+    }
+
+    fn sample(&self, pt: LonLat) -> Option<Distance> {
+        match self {
+            Dem::Hgt(tiles) => tiles.iter().find_map(|tile| tile.raster.sample(pt)),
+            Dem::GeoTiff(raster) => raster.sample(pt),
+        }
+        .map(Distance::meters)
+    }
+}
+
+/// A grid of heights in meters. Rows go north to south, columns west to east, and the values are
+/// at pixel centers.
+struct Raster {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+    // The longitude and latitude of the center of the top-left pixel
+    west: f64,
+    north: f64,
+    // Degrees per pixel
+    lon_step: f64,
+    lat_step: f64,
+    nodata: Option<f64>,
+}
+
+impl Raster {
+    fn read_geotiff(path: &str) -> Result<Raster> {
+        use tiff::decoder::{Decoder, DecodingResult};
+        use tiff::tags::Tag;
+
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        let (width, height) = decoder.dimensions()?;
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+        let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            bail!("{} has a malformed georeference", path);
+        }
+        let nodata = match decoder.find_tag(Tag::GdalNodata)? {
+            Some(value) => value.into_string()?.trim_matches('\0').trim().parse().ok(),
+            None => None,
+        };
+        let values: Vec<f64> = match decoder.read_image()? {
+            DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+            DecodingResult::F64(v) => v,
+            _ => bail!("{} has an unsupported sample format", path),
+        };
+        let (width, height) = (width as usize, height as usize);
+        if values.len() != width * height {
+            bail!("{} should have exactly one band", path);
+        }
+
+        // The tiepoint maps raster (i, j) to the model (x, y). GeoTIFFs default to treating this
+        // as the corner of the pixel.
+        let (lon_step, lat_step) = (scale[0], scale[1]);
+        Ok(Raster {
+            width,
+            height,
+            values,
+            west: tiepoint[3] - tiepoint[0] * lon_step + lon_step / 2.0,
+            north: tiepoint[4] + tiepoint[1] * lat_step - lat_step / 2.0,
+            lon_step,
+            lat_step,
+            nodata,
+        })
+    }
+
+    /// Bilinear interpolation between the 4 nearest pixels, ignoring any without data
+    fn sample(&self, pt: LonLat) -> Option<f64> {
+        let col = (pt.x() - self.west) / self.lon_step;
+        let row = (self.north - pt.y()) / self.lat_step;
+        if col < 0.0 || row < 0.0 || col > (self.width - 1) as f64 || row > (self.height - 1) as f64
+        {
+            return None;
+        }
+        let (col0, row0) = (col.floor() as usize, row.floor() as usize);
+        let (col1, row1) = (
+            (col0 + 1).min(self.width - 1),
+            (row0 + 1).min(self.height - 1),
+        );
+        let (dx, dy) = (col - col0 as f64, row - row0 as f64);
+
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (c, r, weight) in [
+            (col0, row0, (1.0 - dx) * (1.0 - dy)),
+            (col1, row0, dx * (1.0 - dy)),
+            (col0, row1, (1.0 - dx) * dy),
+            (col1, row1, dx * dy),
+        ] {
+            let value = self.values[r * self.width + c];
+            if weight > 0.0 && value.is_finite() && Some(value) != self.nodata {
+                sum += weight * value;
+                total_weight += weight;
+            }
+        }
+        if total_weight == 0.0 {
+            return None;
+        }
+        Some(sum / total_weight)
+    }
+}
+
+/// An SRTM tile covering one degree. The filename, like `N47W122.hgt`, names the southwest
+/// corner. The contents are big-endian 16-bit heights, with -32768 meaning no data.
+struct HgtTile {
+    raster: Raster,
+}
+
+impl HgtTile {
+    fn read(path: &Path) -> Result<HgtTile> {
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_uppercase())
+            .unwrap_or_default();
+        let (south, west) = match parse_hgt_name(&name) {
+            Some(corner) => corner,
+            None => bail!("Can't figure out the location of {}", path.display()),
+        };
+
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        // 1201x1201 for 3 arc-second tiles, 3601x3601 for 1 arc-second
+        let size = ((bytes.len() / 2) as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != bytes.len() {
+            bail!("{} isn't a square grid of heights", path.display());
+        }
+        let values = bytes
+            .chunks_exact(2)
+            .map(|pair| f64::from(i16::from_be_bytes([pair[0], pair[1]])))
+            .collect();
+        let step = 1.0 / (size - 1) as f64;
+        Ok(HgtTile {
+            raster: Raster {
+                width: size,
+                height: size,
+                values,
+                west,
+                north: south + 1.0,
+                lon_step: step,
+                lat_step: step,
+                nodata: Some(-32768.0),
+            },
+        })
+    }
+}
+
+/// Parse the southwest corner out of something like `N47W122`
+fn parse_hgt_name(name: &str) -> Option<(f64, f64)> {
+    if name.len() != 7 || !name.is_ascii() {
+        return None;
+    }
+    let lat: f64 = name[1..3].parse().ok()?;
+    let lon: f64 = name[4..7].parse().ok()?;
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => return None,
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => return None,
+    };
+    Some((lat, lon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hgt_sampling() {
+        assert_eq!(parse_hgt_name("N47W122"), Some((47.0, -122.0)));
+        assert_eq!(parse_hgt_name("S01E010"), Some((-1.0, 10.0)));
+        assert_eq!(parse_hgt_name("X47W122"), None);
+
+        // A tiny 3x3 tile, with a void in the southeast corner
+        let raster = Raster {
+            width: 3,
+            height: 3,
+            values: vec![
+                100.0, 110.0, 120.0, 200.0, 210.0, 220.0, 300.0, 310.0, -32768.0,
+            ],
+            west: -122.0,
+            north: 48.0,
+            lon_step: 0.5,
+            lat_step: 0.5,
+            nodata: Some(-32768.0),
+        };
+        assert_eq!(raster.sample(LonLat::new(-122.0, 48.0)), Some(100.0));
+        assert_eq!(raster.sample(LonLat::new(-121.5, 47.5)), Some(210.0));
+        assert_eq!(raster.sample(LonLat::new(-121.75, 47.75)), Some(155.0));
+        assert_eq!(raster.sample(LonLat::new(-123.0, 47.5)), None);
+
+        assert_eq!(
+            least_squares_slope(&[(0.0, 10.0), (5.0, 10.5), (10.0, 11.0)]),
+            Some(0.1)
+        );
+    }
+}
@@ -1,11 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use osm::{NodeID, OsmID, RelationID, WayID};
 
 use abstutil::Tags;
-use geom::{HashablePt2D, Pt2D};
-use osm2streets::{osm, CrossingType, Direction, RestrictionType};
+use geom::{HashablePt2D, Pt2D, Ring};
+use osm2streets::{
+    osm, parse_turn_restrictions, ApproachControl, Area, AreaType, CrossingType, Direction,
+    RestrictionConditions, RestrictionType, StopType,
+};
 
+use crate::osm_reader::{get_multipolygon_members, glue_multipolygon, multipoly_geometry};
 use crate::osm_reader::{Node, Relation, Way};
 use crate::Options;
 
@@ -14,16 +18,26 @@ pub struct OsmExtract {
     pub roads: Vec<(WayID, Vec<Pt2D>, Tags)>,
     /// Traffic signals to the direction they apply
     pub traffic_signals: HashMap<HashablePt2D, Direction>,
+    /// Stop and give way signs, the direction they apply to if it's tagged, and whether every
+    /// approach to the intersection stops (`stop=all`)
+    pub traffic_signs: HashMap<HashablePt2D, (ApproachControl, Option<Direction>, bool)>,
     pub osm_node_ids: HashMap<HashablePt2D, NodeID>,
-    /// (ID, restriction type, from way ID, via node ID, to way ID)
-    pub simple_turn_restrictions: Vec<(RestrictionType, WayID, NodeID, WayID)>,
-    /// (relation ID, from way ID, via way ID, to way ID)
-    pub complicated_turn_restrictions: Vec<(RelationID, WayID, WayID, WayID)>,
+    /// (restriction type, conditions, from way ID, via node ID, to way ID)
+    pub simple_turn_restrictions:
+        Vec<(RestrictionType, RestrictionConditions, WayID, NodeID, WayID)>,
+    /// (relation ID, conditions, from way ID, via way ID, to way ID)
+    pub complicated_turn_restrictions:
+        Vec<(RelationID, RestrictionConditions, WayID, WayID, WayID)>,
     /// Crossings located at these points, which should be on a Road's center line
-    pub crossing_nodes: HashSet<(HashablePt2D, CrossingType)>,
+    pub crossing_nodes: HashSet<(NodeID, HashablePt2D, CrossingType)>,
     /// Some kind of barrier nodes at these points. Only the ones on a Road center line are
     /// relevant.
     pub barrier_nodes: HashSet<HashablePt2D>,
+    /// Bus stops, platforms, and tram stops. They may be on a road's center line or beside it.
+    pub stop_nodes: Vec<(NodeID, Pt2D, StopType, Tags)>,
+    /// Buildings, parking lots, and landuse from closed ways and multipolygons. Only filled out
+    /// if `Options::areas` is set.
+    pub areas: Vec<Area>,
 }
 
 impl OsmExtract {
@@ -31,11 +45,14 @@ impl OsmExtract {
         Self {
             roads: Vec::new(),
             traffic_signals: HashMap::new(),
+            traffic_signs: HashMap::new(),
             osm_node_ids: HashMap::new(),
             simple_turn_restrictions: Vec::new(),
             complicated_turn_restrictions: Vec::new(),
             crossing_nodes: HashSet::new(),
             barrier_nodes: HashSet::new(),
+            stop_nodes: Vec::new(),
+            areas: Vec::new(),
         }
     }
 
@@ -50,37 +67,93 @@ impl OsmExtract {
             };
             self.traffic_signals.insert(node.pt.to_hashable(), dir);
         }
+        let sign = if node.tags.is(osm::HIGHWAY, "stop") {
+            Some(ApproachControl::Stop)
+        } else if node.tags.is(osm::HIGHWAY, "give_way") {
+            Some(ApproachControl::Yield)
+        } else {
+            None
+        };
+        if let Some(sign) = sign {
+            let dir = if node.tags.is("direction", "forward") {
+                Some(Direction::Fwd)
+            } else if node.tags.is("direction", "backward") {
+                Some(Direction::Back)
+            } else {
+                None
+            };
+            // stop=minor is the usual meaning when nothing's tagged
+            let all_way = sign == ApproachControl::Stop && node.tags.is("stop", "all");
+            self.traffic_signs
+                .insert(node.pt.to_hashable(), (sign, dir, all_way));
+        }
         if node.tags.is(osm::HIGHWAY, "crossing") {
-            // TODO Look for crossing:signals:* too.
-            // https://wiki.openstreetmap.org/wiki/Tag:crossing=traffic%20signals?uselang=en
-            let kind = if node.tags.is("crossing", "traffic_signals") {
+            // https://wiki.openstreetmap.org/wiki/Key:crossing
+            let kind = if node.tags.is_any("crossing", vec!["unmarked", "no"])
+                || node.tags.is("crossing:markings", "no")
+            {
+                CrossingType::Unmarked
+            } else if node.tags.is("crossing", "traffic_signals")
+                || node.tags.is("crossing:signals", "yes")
+                // British crossings with signals
+                || node
+                    .tags
+                    .is_any("crossing_ref", vec!["pelican", "puffin", "toucan", "pegasus"])
+            {
                 CrossingType::Signalized
             } else {
                 CrossingType::Unsignalized
             };
-            self.crossing_nodes.insert((node.pt.to_hashable(), kind));
+            self.crossing_nodes
+                .insert((id, node.pt.to_hashable(), kind));
         }
         // TODO Any kind of barrier?
         if node.tags.is("barrier", "bollard") {
             self.barrier_nodes.insert(node.pt.to_hashable());
         }
+        if let Some(stop_type) = StopType::from_tags(&node.tags) {
+            self.stop_nodes
+                .push((id, node.pt, stop_type, node.tags.clone()));
+        }
+    }
+
+    /// Decides if `handle_node` would use a node that isn't part of any road, just from its tags.
+    pub fn wants_node(tags: &Tags) -> bool {
+        StopType::from_tags(tags).is_some()
     }
 
     // Returns true if the way was added as a road
     pub fn handle_way(&mut self, id: WayID, way: &Way, opts: &Options) -> bool {
-        let tags = &way.tags;
+        if OsmExtract::wants_area(&way.tags, opts) && way.nodes[0] == *way.nodes.last().unwrap() {
+            if let Ok(ring) = Ring::new(way.pts.clone()) {
+                self.areas.push(Area {
+                    area_type: AreaType::from_tags(&way.tags).unwrap(),
+                    polygon: ring.into_polygon(),
+                    osm_id: Some(OsmID::Way(id)),
+                    osm_tags: way.tags.clone(),
+                    frontage: None,
+                });
+            }
+        }
+
+        if !OsmExtract::wants_way(&way.tags, opts) {
+            return false;
+        }
+        self.roads.push((id, way.pts.clone(), way.tags.clone()));
+        true
+    }
 
+    /// Decides if `handle_way` would use a way, just from its tags.
+    pub fn wants_way(tags: &Tags, opts: &Options) -> bool {
         if tags.is("area", "yes") {
             return false;
         }
 
         // First deal with railways.
         if tags.is("railway", "light_rail") {
-            self.roads.push((id, way.pts.clone(), tags.clone()));
             return true;
         }
         if tags.is("railway", "rail") && opts.include_railroads {
-            self.roads.push((id, way.pts.clone(), tags.clone()));
             return true;
         }
 
@@ -152,10 +225,44 @@ impl OsmExtract {
             return false;
         }
 
-        self.roads.push((id, way.pts.clone(), tags.clone()));
         true
     }
 
+    /// Decides if a closed way or multipolygon relation should become an `Area`, just from its
+    /// tags.
+    pub fn wants_area(tags: &Tags, opts: &Options) -> bool {
+        opts.areas && AreaType::from_tags(tags).is_some()
+    }
+
+    /// Turns a multipolygon relation into areas. `ways` must contain its members. If the outer
+    /// members don't form closed rings, they're glued together, following `boundary` if possible.
+    pub fn handle_area_relation(
+        &mut self,
+        id: RelationID,
+        rel: &Relation,
+        ways: &BTreeMap<WayID, Way>,
+        boundary: Option<&Ring>,
+        opts: &Options,
+    ) {
+        if !rel.tags.is("type", "multipolygon") || !OsmExtract::wants_area(&rel.tags, opts) {
+            return;
+        }
+        let polygons = match multipoly_geometry(id, rel, ways) {
+            Ok(polygons) if !polygons.is_empty() => polygons,
+            // Usually the outer ring is split into several ways
+            _ => glue_multipolygon(id, get_multipolygon_members(id, rel, ways), boundary),
+        };
+        for polygon in polygons {
+            self.areas.push(Area {
+                area_type: AreaType::from_tags(&rel.tags).unwrap(),
+                polygon,
+                osm_id: Some(OsmID::Relation(id)),
+                osm_tags: rel.tags.clone(),
+                frontage: None,
+            });
+        }
+    }
+
     // Returns true if the relation was used (turn restrictions only)
     pub fn handle_relation(&mut self, id: RelationID, rel: &Relation) -> bool {
         if !rel.tags.is("type", "restriction") {
@@ -187,22 +294,20 @@ impl OsmExtract {
                 }
             }
         }
-        if let Some(restriction) = rel.tags.get("restriction") {
-            if let Some(rt) = RestrictionType::new(restriction) {
-                if let (Some(from), Some(via), Some(to)) = (from_way_id, via_node_id, to_way_id) {
-                    self.simple_turn_restrictions.push((rt, from, via, to));
-                } else if let (Some(from), Some(via), Some(to)) =
-                    (from_way_id, via_way_id, to_way_id)
-                {
-                    if rt == RestrictionType::BanTurns {
-                        self.complicated_turn_restrictions.push((id, from, via, to));
-                    } else {
-                        warn!(
-                            "Weird complicated turn restriction \"{}\" from {} to {} via {}: \
-                             {}",
-                            restriction, from, to, via, id
-                        );
-                    }
+        // One relation may hold restrictions for different vehicles or times
+        for (rt, conditions) in parse_turn_restrictions(&rel.tags) {
+            if let (Some(from), Some(via), Some(to)) = (from_way_id, via_node_id, to_way_id) {
+                self.simple_turn_restrictions
+                    .push((rt, conditions, from, via, to));
+            } else if let (Some(from), Some(via), Some(to)) = (from_way_id, via_way_id, to_way_id) {
+                if rt == RestrictionType::BanTurns {
+                    self.complicated_turn_restrictions
+                        .push((id, conditions, from, via, to));
+                } else {
+                    warn!(
+                        "Weird complicated turn restriction {:?} from {} to {} via {}: {}",
+                        rt, from, to, via, id
+                    );
                 }
             }
         }
@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+
+use abstutil::Timer;
+use anyhow::Result;
+use geom::{LonLat, PolyLine, Pt2D};
+use osm2streets::{
+    RoutingGraph, StreetNetwork, TransitKind, TransitMatcher, TransitRoute, TransitStop,
+};
+
+/// Read a static GTFS feed from a local `.zip` file. Stops inside the boundary are snapped to
+/// the closest road used by the routes serving them, and each route's shape is matched to the
+/// roads it follows. The result is stored in `streets.transit`.
+///
+/// Only one representative trip per route (the first listed with a shape) is used for the path
+/// and stop sequence.
+pub fn import_gtfs(streets: &mut StreetNetwork, path: &str, timer: &mut Timer) -> Result<()> {
+    timer.start("read GTFS");
+    let feed = Feed::read(path)?;
+    timer.stop("read GTFS");
+
+    let bus_graph = RoutingGraph::new(streets);
+    let bus_matcher = TransitMatcher::new(streets, TransitKind::Bus, Some(&bus_graph));
+    let tram_matcher = TransitMatcher::new(streets, TransitKind::Tram, None);
+    let matcher = |kind: TransitKind| match kind {
+        TransitKind::Bus => Some(&bus_matcher),
+        TransitKind::Tram => Some(&tram_matcher),
+        TransitKind::Other => None,
+    };
+
+    let mut stops = BTreeMap::new();
+    timer.start_iter("snap transit stops", feed.stops.len());
+    for (id, (name, lonlat)) in &feed.stops {
+        timer.next();
+        if !streets.gps_bounds.contains(*lonlat) {
+            continue;
+        }
+        let position = lonlat.to_pt(&streets.gps_bounds);
+        if !streets.boundary_polygon.contains_pt(position) {
+            continue;
+        }
+        let snapped = feed
+            .stop_kinds
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|kind| matcher(*kind))
+            .find_map(|m| m.snap_stop(position));
+        stops.insert(
+            id.clone(),
+            TransitStop {
+                name: name.clone(),
+                position,
+                snapped,
+            },
+        );
+    }
+
+    let mut routes = Vec::new();
+    timer.start_iter("match transit routes", feed.routes.len());
+    for (id, route) in &feed.routes {
+        timer.next();
+        let kind = TransitKind::from_gtfs_route_type(route.route_type);
+        let mut path = Vec::new();
+        let mut route_stops = Vec::new();
+        if let Some(trip) = feed.representative_trips.get(id) {
+            route_stops = trip
+                .stops
+                .iter()
+                .filter(|stop| stops.contains_key(*stop))
+                .cloned()
+                .collect();
+            if let (Some(m), Some(shape)) = (
+                matcher(kind),
+                trip.shape_id.as_ref().and_then(|s| feed.shapes.get(s)),
+            ) {
+                for pl in shape_inside_boundary(streets, shape) {
+                    path.extend(m.match_shape(&pl));
+                }
+            }
+        }
+        routes.push(TransitRoute {
+            gtfs_id: id.clone(),
+            short_name: route.short_name.clone(),
+            long_name: route.long_name.clone(),
+            kind,
+            path,
+            stops: route_stops,
+        });
+    }
+    // Routes that never enter the map aren't interesting
+    routes.retain(|r| !r.path.is_empty() || !r.stops.is_empty());
+
+    info!(
+        "Imported {} transit stops and {} routes",
+        stops.len(),
+        routes.len()
+    );
+    streets.transit.stops = stops;
+    streets.transit.routes = routes;
+    Ok(())
+}
+
+// Shapes often extend beyond the map, and may leave and come back. Return each piece inside.
+fn shape_inside_boundary(streets: &StreetNetwork, shape: &[LonLat]) -> Vec<PolyLine> {
+    let mut pieces = Vec::new();
+    let mut current: Vec<Pt2D> = Vec::new();
+    for lonlat in shape {
+        let pt = lonlat.to_pt(&streets.gps_bounds);
+        if streets.gps_bounds.contains(*lonlat) && streets.boundary_polygon.contains_pt(pt) {
+            current.push(pt);
+        } else if !current.is_empty() {
+            pieces.extend(PolyLine::deduping_new(std::mem::take(&mut current)).ok());
+        }
+    }
+    if !current.is_empty() {
+        pieces.extend(PolyLine::deduping_new(current).ok());
+    }
+    pieces
+}
+
+struct Route {
+    short_name: String,
+    long_name: String,
+    route_type: usize,
+}
+
+struct Trip {
+    shape_id: Option<String>,
+    // Ordered by stop_sequence
+    stops: Vec<String>,
+}
+
+/// The parts of a GTFS feed that're used
+struct Feed {
+    // (name, position)
+    stops: BTreeMap<String, (String, LonLat)>,
+    routes: BTreeMap<String, Route>,
+    // Keyed by route ID
+    representative_trips: BTreeMap<String, Trip>,
+    // What kind of routes serve each stop
+    stop_kinds: HashMap<String, BTreeSet<TransitKind>>,
+    // Ordered by shape_pt_sequence
+    shapes: HashMap<String, Vec<LonLat>>,
+}
+
+impl Feed {
+    fn read(path: &str) -> Result<Feed> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+
+        let mut stops = BTreeMap::new();
+        read_table(&mut archive, "stops.txt", |row| {
+            // Skip stations, entrances, and other things that aren't where vehicles stop
+            if !matches!(row.get("location_type"), None | Some("") | Some("0")) {
+                return Ok(());
+            }
+            let lon: f64 = row.require("stop_lon")?.parse()?;
+            let lat: f64 = row.require("stop_lat")?.parse()?;
+            stops.insert(
+                row.require("stop_id")?.to_string(),
+                (
+                    row.get("stop_name").unwrap_or("").to_string(),
+                    LonLat::new(lon, lat),
+                ),
+            );
+            Ok(())
+        })?;
+
+        let mut routes = BTreeMap::new();
+        read_table(&mut archive, "routes.txt", |row| {
+            routes.insert(
+                row.require("route_id")?.to_string(),
+                Route {
+                    short_name: row.get("route_short_name").unwrap_or("").to_string(),
+                    long_name: row.get("route_long_name").unwrap_or("").to_string(),
+                    route_type: row.require("route_type")?.parse()?,
+                },
+            );
+            Ok(())
+        })?;
+
+        let mut trip_to_route: HashMap<String, String> = HashMap::new();
+        let mut representative_trip_ids: HashMap<String, String> = HashMap::new();
+        let mut representative_trips = BTreeMap::new();
+        read_table(&mut archive, "trips.txt", |row| {
+            let trip_id = row.require("trip_id")?.to_string();
+            let route_id = row.require("route_id")?.to_string();
+            let shape_id = row.get("shape_id").filter(|s| !s.is_empty());
+            let has_shape = representative_trips
+                .get(&route_id)
+                .map(|trip: &Trip| trip.shape_id.is_some())
+                .unwrap_or(false);
+            // Prefer the first trip with a shape
+            if !has_shape && (shape_id.is_some() || !representative_trips.contains_key(&route_id)) {
+                representative_trip_ids.retain(|_, r| r != &route_id);
+                representative_trip_ids.insert(trip_id.clone(), route_id.clone());
+                representative_trips.insert(
+                    route_id.clone(),
+                    Trip {
+                        shape_id: shape_id.map(|s| s.to_string()),
+                        stops: Vec::new(),
+                    },
+                );
+            }
+            trip_to_route.insert(trip_id, route_id);
+            Ok(())
+        })?;
+
+        let mut stop_kinds: HashMap<String, BTreeSet<TransitKind>> = HashMap::new();
+        let mut sequences: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+        // This is usually the largest table by far
+        read_table(&mut archive, "stop_times.txt", |row| {
+            let trip_id = row.require("trip_id")?;
+            let stop_id = row.require("stop_id")?;
+            if let Some(route) = trip_to_route.get(trip_id).and_then(|r| routes.get(r)) {
+                stop_kinds
+                    .entry(stop_id.to_string())
+                    .or_insert_with(BTreeSet::new)
+                    .insert(TransitKind::from_gtfs_route_type(route.route_type));
+            }
+            if let Some(route_id) = representative_trip_ids.get(trip_id) {
+                sequences
+                    .entry(route_id.clone())
+                    .or_insert_with(Vec::new)
+                    .push((row.require("stop_sequence")?.parse()?, stop_id.to_string()));
+            }
+            Ok(())
+        })?;
+        for (route_id, mut sequence) in sequences {
+            sequence.sort();
+            representative_trips.get_mut(&route_id).unwrap().stops =
+                sequence.into_iter().map(|(_, stop)| stop).collect();
+        }
+
+        let used_shapes: BTreeSet<String> = representative_trips
+            .values()
+            .filter_map(|trip| trip.shape_id.clone())
+            .collect();
+        let mut shape_points: HashMap<String, Vec<(usize, LonLat)>> = HashMap::new();
+        // shapes.txt is optional
+        if archive.by_name("shapes.txt").is_ok() {
+            read_table(&mut archive, "shapes.txt", |row| {
+                let shape_id = row.require("shape_id")?;
+                if !used_shapes.contains(shape_id) {
+                    return Ok(());
+                }
+                let lon: f64 = row.require("shape_pt_lon")?.parse()?;
+                let lat: f64 = row.require("shape_pt_lat")?.parse()?;
+                shape_points
+                    .entry(shape_id.to_string())
+                    .or_insert_with(Vec::new)
+                    .push((
+                        row.require("shape_pt_sequence")?.parse()?,
+                        LonLat::new(lon, lat),
+                    ));
+                Ok(())
+            })?;
+        }
+        let shapes = shape_points
+            .into_iter()
+            .map(|(id, mut pts)| {
+                pts.sort_by_key(|(seq, _)| *seq);
+                (id, pts.into_iter().map(|(_, pt)| pt).collect())
+            })
+            .collect();
+
+        Ok(Feed {
+            stops,
+            routes,
+            representative_trips,
+            stop_kinds,
+            shapes,
+        })
+    }
+}
+
+/// One row of a GTFS table, with values looked up by column name
+struct Row<'a> {
+    headers: &'a HashMap<String, usize>,
+    record: &'a csv::StringRecord,
+}
+
+impl<'a> Row<'a> {
+    fn get(&self, column: &str) -> Option<&'a str> {
+        self.headers
+            .get(column)
+            .and_then(|idx| self.record.get(*idx))
+            .map(|value| value.trim())
+    }
+
+    fn require(&self, column: &str) -> Result<&'a str> {
+        match self.get(column) {
+            Some(value) => Ok(value),
+            None => bail!("GTFS row is missing {}", column),
+        }
+    }
+}
+
+// Calls `handle` on each row of a table, reading the file as it goes. Some tables, like
+// stop_times.txt, are too large to hold in memory.
+fn read_table<F: FnMut(Row) -> Result<()>>(
+    archive: &mut zip::ZipArchive<File>,
+    name: &str,
+    mut handle: F,
+) -> Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(archive.by_name(name)?);
+    let headers = reader
+        .headers()?
+        .iter()
+        .enumerate()
+        .map(|(idx, header)| {
+            (
+                header.trim_start_matches('\u{feff}').trim().to_string(),
+                idx,
+            )
+        })
+        .collect();
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        handle(Row {
+            headers: &headers,
+            record: &record,
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use osm2streets::{Direction, DrivingSide, OriginalRoad, Transformation};
+
+    use super::*;
+
+    // One straight east-west road, split in the middle
+    const OSM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="47.6" minlon="-122.34" maxlat="47.61" maxlon="-122.33"/>
+  <node id="1" lat="47.605" lon="-122.338"/>
+  <node id="2" lat="47.605" lon="-122.335"/>
+  <node id="3" lat="47.605" lon="-122.332"/>
+  <way id="100">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="primary"/>
+  </way>
+  <way id="101">
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="primary"/>
+  </way>
+</osm>"#;
+
+    // Write a tiny feed: one eastbound bus route with a stop on each side of the road, a stop
+    // outside the map, and a ferry that never comes near
+    fn write_feed(path: &str) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(File::create(path)?);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, contents) in [
+            (
+                "stops.txt",
+                "stop_id,stop_name,stop_lat,stop_lon\n\
+                 north,North side,47.60505,-122.3365\n\
+                 south,South side,47.60495,-122.3335\n\
+                 far,Far away,48.0,-122.0\n",
+            ),
+            (
+                "routes.txt",
+                "route_id,route_short_name,route_long_name,route_type\n\
+                 r1,1,Eastbound,3\n\
+                 r2,2,Ferry,4\n",
+            ),
+            (
+                "trips.txt",
+                "route_id,service_id,trip_id,shape_id\n\
+                 r1,daily,t1,\n\
+                 r1,daily,t2,s1\n",
+            ),
+            (
+                "stop_times.txt",
+                "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+                 t2,08:05:00,08:05:00,south,2\n\
+                 t2,08:00:00,08:00:00,north,1\n\
+                 t2,08:30:00,08:30:00,far,3\n",
+            ),
+            // The shape leaves the map partway along the second road
+            (
+                "shapes.txt",
+                "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence\n\
+                 s1,47.605,-122.3335,2\n\
+                 s1,47.605,-122.3385,1\n\
+                 s1,47.605,-122.325,3\n",
+            ),
+        ] {
+            zip.start_file(name, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_gtfs() -> Result<()> {
+        let path = std::env::temp_dir().join("streets_reader_test_import_gtfs.zip");
+        let path = path.to_str().unwrap();
+        write_feed(path)?;
+
+        let mut timer = Timer::throwaway();
+        let mut opts = crate::Options::default_for_side(DrivingSide::Right);
+        opts.gtfs_url = Some(path.to_string());
+        let mut streets = crate::osm_to_street_network(OSM, None, opts, &mut timer)?;
+        std::fs::remove_file(path)?;
+
+        let west = OriginalRoad::new(100, (1, 2));
+        let east = OriginalRoad::new(101, (2, 3));
+        let transit = &streets.transit;
+        assert_eq!(
+            transit.stops.keys().collect::<Vec<_>>(),
+            vec!["north", "south"]
+        );
+        // Traffic drives on the right, so the stop north of the eastbound road serves westbound
+        // traffic
+        let north = transit.stops["north"].snapped.unwrap();
+        assert_eq!((north.road, north.dir), (west, Direction::Back));
+        assert!(north.dist_along.inner_meters() > 100.0 && north.dist_along.inner_meters() < 125.0);
+        let south = transit.stops["south"].snapped.unwrap();
+        assert_eq!((south.road, south.dir), (east, Direction::Fwd));
+
+        // The ferry is dropped, and the stops are ordered by stop_sequence
+        assert_eq!(transit.routes.len(), 1);
+        let route = &transit.routes[0];
+        assert_eq!(route.gtfs_id, "r1");
+        assert_eq!(route.stops, vec!["north", "south"]);
+        assert_eq!(
+            route.path,
+            vec![(west, Direction::Fwd), (east, Direction::Fwd)]
+        );
+        assert_eq!(streets.bus_lane_coverage(route)?, 0.0);
+
+        // Merging the two roads keeps everything pointing at the merged road
+        streets.apply_transformations(
+            vec![Transformation::CollapseDegenerateIntersections],
+            &mut timer,
+        );
+        let merged = streets.road_for_original_segment(west).unwrap();
+        assert_eq!(streets.road_for_original_segment(east), Some(merged));
+        let transit = &streets.transit;
+        let north = transit.stops["north"].snapped.unwrap();
+        assert_eq!((north.road, north.dir), (merged, Direction::Back));
+        let south = transit.stops["south"].snapped.unwrap();
+        assert_eq!((south.road, south.dir), (merged, Direction::Fwd));
+        assert!(south.dist_along.inner_meters() > 300.0);
+        assert_eq!(transit.routes[0].path, vec![(merged, Direction::Fwd)]);
+        assert_eq!(streets.bus_lane_coverage(&transit.routes[0])?, 0.0);
+
+        // A path that refers to a removed road is a bug
+        streets.remove_road(&merged);
+        assert!(streets
+            .bus_lane_coverage(&streets.transit.routes[0])
+            .is_err());
+        Ok(())
+    }
+}
@@ -3,20 +3,31 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use abstutil::Timer;
+use abstutil::{Tags, Timer};
 use anyhow::Result;
-use geom::{GPSBounds, HashablePt2D, LonLat, PolyLine, Ring};
+use geom::{GPSBounds, HashablePt2D, LonLat, PolyLine, Polygon, Pt2D, Ring};
 
-use osm2streets::{CrossingType, MapConfig, OriginalRoad, StreetNetwork};
+use osm2streets::osm::{NodeID, OsmID, RelationID, WayID};
+use osm2streets::{
+    ApproachSnapshot, Area, AreaType, CrossingType, Direction, DrivingSide, LaneType, MapConfig,
+    OriginalRoad, PublicTransportStop, RestrictionSnapshot, StopPlacement, StopType, StreetNetwork,
+    TransitKind, TransitMatcher,
+};
 
 pub use self::extract::OsmExtract;
+use self::osm_reader::{Document, Element, Node, OsmInput, Relation, Way};
 
 // TODO Clean up the public API of all of this
 pub mod clip;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod elevation;
 pub mod extract;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gtfs;
 pub mod osm_reader;
+pub mod parking;
 pub mod split_ways;
 
 /// Configures the creation of a `RawMap` from OSM and other input data.
@@ -30,13 +41,29 @@ pub struct Options {
     pub private_offstreet_parking: PrivateOffstreetParking,
     /// OSM railway=rail will be included as light rail if so. Cosmetic only.
     pub include_railroads: bool,
-    /// If provided, read polygons from this GeoJSON file and add them to the RawMap as buildings.
+    /// Import buildings, parking lots, and landuse from OSM into `StreetNetwork::areas`, clipped to
+    /// the boundary. Each building is linked to the road it faces.
+    pub areas: bool,
+    /// If provided, read polygons from this GeoJSON file and add them to `StreetNetwork::areas` as
+    /// buildings. This works even if `areas` isn't set.
     pub extra_buildings: Option<String>,
     /// Only include crosswalks that match a `highway=crossing` OSM node.
     pub filter_crosswalks: bool,
-    /// Configure public transit using this URL to a static GTFS feed in .zip format.
+    /// Configure public transit using this static GTFS feed in .zip format. Only paths to local
+    /// files are supported so far, and not on the web.
     pub gtfs_url: Option<String>,
-    pub elevation: bool,
+    /// Fill out elevation and road grades from this digital elevation model: a GeoTIFF in WGS84,
+    /// an SRTM `.hgt` tile, or a directory of `.hgt` tiles. Not supported on the web.
+    pub elevation: Option<String>,
+    /// Read the input in two passes, first keeping only the ways that'll become roads, then only
+    /// the nodes those ways reference. This uses much less memory than building a full `Document`
+    /// for large inputs, and produces the same result.
+    pub streaming: bool,
+    /// Find the country in the middle of the map, and use its driving side, lane widths, speed
+    /// limits, and other conventions in `map_config`. If the country isn't recognized,
+    /// `map_config` is used as is. Sidewalk inference during extraction still follows
+    /// `map_config`.
+    pub detect_region: bool,
 }
 
 impl Options {
@@ -47,10 +74,13 @@ impl Options {
             public_offstreet_parking: PublicOffstreetParking::None,
             private_offstreet_parking: PrivateOffstreetParking::FixedPerBldg(1),
             include_railroads: true,
+            areas: false,
             extra_buildings: None,
             filter_crosswalks: false,
             gtfs_url: None,
-            elevation: false,
+            elevation: None,
+            streaming: false,
+            detect_region: false,
         }
     }
 }
@@ -60,14 +90,17 @@ impl Options {
 pub enum OnstreetParking {
     /// If not tagged, there won't be parking.
     JustOSM,
-    /// If OSM data is missing, then try to match data from
-    /// <http://data-seattlecitygis.opendata.arcgis.com/datasets/blockface>. This is Seattle specific.
+    /// If OSM data is missing, then conflate curb segments from this local GeoJSON file onto each
+    /// side of the road. See `parking::use_parking_hints` for the format. Seattle's
+    /// <http://data-seattlecitygis.opendata.arcgis.com/datasets/blockface> works directly.
     Blockface(String),
     /// If OSM data is missing, then infer parking lanes on some percentage of
     /// "highway=residential" roads.
     SomeAdditionalWhereNoData {
         /// [0, 100]
         pct: usize,
+        /// The same seed always picks the same roads
+        seed: u64,
     },
 }
 
@@ -99,6 +132,38 @@ pub fn osm_to_street_network(
     opts: Options,
     timer: &mut Timer,
 ) -> Result<StreetNetwork> {
+    let mut streets = new_street_network(&clip_pts, &opts)?;
+    let extract = if opts.streaming {
+        let input = OsmInput::Xml(osm_xml_input);
+        extract_osm_streaming(&mut streets, input, clip_pts, &opts, timer)?
+    } else {
+        let doc = crate::osm_reader::read(osm_xml_input, &streets.gps_bounds, timer)?;
+        extract_osm(&mut streets, doc, clip_pts, &opts, timer)
+    };
+    finish_street_network(streets, extract, opts, timer)
+}
+
+/// Like `osm_to_street_network`, but reads an `.osm.pbf` file from `pbf_path`. If `clip_pts`
+/// isn't specified, the boundary is calculated from all nodes in the file.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pbf_to_street_network(
+    pbf_path: &str,
+    clip_pts: Option<Vec<LonLat>>,
+    opts: Options,
+    timer: &mut Timer,
+) -> Result<StreetNetwork> {
+    let mut streets = new_street_network(&clip_pts, &opts)?;
+    let extract = if opts.streaming {
+        let input = OsmInput::Pbf(pbf_path);
+        extract_osm_streaming(&mut streets, input, clip_pts, &opts, timer)?
+    } else {
+        let doc = crate::osm_reader::read_pbf(pbf_path, &streets.gps_bounds, timer)?;
+        extract_osm(&mut streets, doc, clip_pts, &opts, timer)
+    };
+    finish_street_network(streets, extract, opts, timer)
+}
+
+fn new_street_network(clip_pts: &Option<Vec<LonLat>>, opts: &Options) -> Result<StreetNetwork> {
     let mut streets = StreetNetwork::blank();
     // Do this early. Calculating Roads uses DrivingSide, for example!
     streets.config = opts.map_config.clone();
@@ -108,15 +173,37 @@ pub fn osm_to_street_network(
         streets.boundary_polygon = Ring::new(gps_bounds.convert(pts))?.into_polygon();
         streets.gps_bounds = gps_bounds;
     }
+    Ok(streets)
+}
 
-    let extract = extract_osm(&mut streets, osm_xml_input, clip_pts, &opts, timer)?;
-    let split_output = split_ways::split_up_roads(&mut streets, extract, timer);
-    clip::clip_map(&mut streets, timer)?;
+fn finish_street_network(
+    mut streets: StreetNetwork,
+    mut extract: OsmExtract,
+    opts: Options,
+    timer: &mut Timer,
+) -> Result<StreetNetwork> {
+    // The boundary is only known after extraction, and nothing before this depends on the region
+    if opts.detect_region {
+        match osm2streets::RegionProfile::for_gps_bounds(&streets.gps_bounds) {
+            Some(profile) => {
+                info!("Using the region profile for {}", profile.country_code);
+                profile.apply(&mut streets.config);
+            }
+            None => {
+                warn!("Couldn't detect the region, using the given MapConfig");
+            }
+        }
+    }
 
-    // Need to do a first pass of removing cul-de-sacs here, or we wind up with loop PolyLines when
-    // doing the parking hint matching.
-    streets.retain_roads(|r, _| r.i1 != r.i2);
+    streets.areas = std::mem::take(&mut extract.areas);
+    if let Some(ref path) = opts.extra_buildings {
+        add_extra_buildings(&mut streets, path)?;
+    }
 
+    let split_output = split_ways::split_up_roads(&mut streets, extract, timer);
+
+    // Attach point features before clipping. Clipping renames roads crossing the boundary, so
+    // pt_to_road would be stale afterwards, and it removes the features that wind up outside.
     use_barrier_nodes(
         &mut streets,
         split_output.barrier_nodes,
@@ -137,18 +224,49 @@ pub fn osm_to_street_network(
         );
     }
 
+    clip::clip_map(&mut streets, timer)?;
+
+    // Need to do a first pass of removing cul-de-sacs here, or we wind up with loop PolyLines when
+    // doing the parking hint matching.
+    let restrictions = RestrictionSnapshot::new(&streets);
+    let approaches = ApproachSnapshot::new(&streets);
+    streets.retain_roads(|r, _| r.i1 != r.i2);
+    restrictions.fix("removing loop roads", &mut streets);
+    approaches.fix(&mut streets);
+
+    match opts.onstreet_parking {
+        OnstreetParking::JustOSM => {}
+        OnstreetParking::Blockface(ref path) => {
+            parking::use_parking_hints(&mut streets, path, timer)?;
+        }
+        OnstreetParking::SomeAdditionalWhereNoData { pct, seed } => {
+            parking::add_inferred_parking(&mut streets, pct, seed);
+        }
+    }
+
+    streets.link_building_frontages();
+
+    use_stop_nodes(&mut streets, split_output.stop_nodes);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(ref path) = opts.elevation {
+        elevation::add_elevation(&mut streets, path, timer)?;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(ref path) = opts.gtfs_url {
+        gtfs::import_gtfs(&mut streets, path, timer)?;
+    }
+
     Ok(streets)
 }
 
 fn extract_osm(
     streets: &mut StreetNetwork,
-    osm_xml_input: &str,
+    doc: Document,
     clip_pts: Option<Vec<LonLat>>,
     opts: &Options,
     timer: &mut Timer,
-) -> Result<OsmExtract> {
-    let doc = crate::osm_reader::read(osm_xml_input, &streets.gps_bounds, timer)?;
-
+) -> OsmExtract {
     if clip_pts.is_none() {
         // Use the boundary from .osm.
         streets.gps_bounds = doc.gps_bounds.clone();
@@ -164,20 +282,254 @@ fn extract_osm(
     }
 
     timer.start_iter("processing OSM ways", doc.ways.len());
-    for (id, way) in doc.ways {
+    for (id, way) in &doc.ways {
         timer.next();
-        out.handle_way(id, &way, opts);
+        out.handle_way(*id, way, opts);
     }
 
+    let boundary: &Ring = &streets.boundary_polygon.get_outer_ring();
     timer.start_iter("processing OSM relations", doc.relations.len());
     for (id, rel) in doc.relations {
         timer.next();
+        out.handle_area_relation(id, &rel, &doc.ways, Some(boundary), opts);
+        out.handle_relation(id, &rel);
+    }
+
+    out
+}
+
+// Produces exactly the same result as `extract_osm`, without ever holding more than the ways that
+// become roads or areas, turn restriction and area relations, their member ways, and the nodes
+// they reference.
+fn extract_osm_streaming(
+    streets: &mut StreetNetwork,
+    input: OsmInput,
+    clip_pts: Option<Vec<LonLat>>,
+    opts: &Options,
+    timer: &mut Timer,
+) -> Result<OsmExtract> {
+    let mut file_bounds = None;
+    let mut ways: BTreeMap<WayID, (Vec<NodeID>, Tags)> = BTreeMap::new();
+    let mut relations: BTreeMap<RelationID, Relation> = BTreeMap::new();
+    // The full reader filters out relation members that don't exist, so remember every ID. (Unlike
+    // the full reader, a way is assumed to exist even if none of its nodes do.)
+    let mut all_ways: HashSet<WayID> = HashSet::new();
+    let mut all_relations: HashSet<RelationID> = HashSet::new();
+    let mut duplicate = None;
+
+    timer.start("read OSM ways and relations");
+    input.for_each(false, true, |element| match element {
+        Element::Bounds(b) => {
+            if file_bounds.is_none() {
+                file_bounds = Some(b);
+            }
+        }
+        Element::Node { .. } => {}
+        Element::Way { id, nodes, tags } => {
+            if nodes.is_empty() {
+                return;
+            }
+            if !all_ways.insert(id) {
+                duplicate = Some(OsmID::Way(id));
+            }
+            if OsmExtract::wants_way(&tags, opts) || OsmExtract::wants_area(&tags, opts) {
+                ways.insert(id, (nodes, tags));
+            }
+        }
+        Element::Relation {
+            id,
+            tags,
+            mut members,
+        } => {
+            members.retain(|(_, member)| match member {
+                OsmID::Node(_) => true,
+                OsmID::Way(w) => all_ways.contains(w),
+                // Only relations that came earlier count
+                OsmID::Relation(r) => all_relations.contains(r),
+            });
+            if !all_relations.insert(id) {
+                duplicate = Some(OsmID::Relation(id));
+            }
+            // Only turn restrictions and areas are used from relations
+            if tags.is("type", "restriction")
+                || (tags.is("type", "multipolygon") && OsmExtract::wants_area(&tags, opts))
+            {
+                relations.insert(id, Relation { tags, members });
+            }
+        }
+    })?;
+    timer.stop("read OSM ways and relations");
+    if let Some(id) = duplicate {
+        bail!("Duplicate {}, your input is corrupt", id);
+    }
+    drop(all_ways);
+    drop(all_relations);
+
+    // Relations come after ways, so their untagged member ways need another pass
+    let mut member_ways: HashSet<WayID> = HashSet::new();
+    for rel in relations.values() {
+        if rel.tags.is("type", "multipolygon") {
+            for (_, member) in &rel.members {
+                if let OsmID::Way(w) = member {
+                    if !ways.contains_key(w) {
+                        member_ways.insert(*w);
+                    }
+                }
+            }
+        }
+    }
+    if !member_ways.is_empty() {
+        timer.start("read OSM multipolygon members");
+        input.for_each(false, true, |element| {
+            if let Element::Way { id, nodes, tags } = element {
+                if member_ways.contains(&id) {
+                    ways.insert(id, (nodes, tags));
+                }
+            }
+        })?;
+        timer.stop("read OSM multipolygon members");
+    }
+
+    let mut needed_nodes: HashSet<NodeID> = HashSet::new();
+    for (nodes, _) in ways.values() {
+        needed_nodes.extend(nodes.iter().cloned());
+    }
+    for rel in relations.values() {
+        for (_, member) in &rel.members {
+            if let OsmID::Node(n) = member {
+                needed_nodes.insert(*n);
+            }
+        }
+    }
+
+    let mut gps_bounds = if clip_pts.is_some() {
+        streets.gps_bounds.clone()
+    } else {
+        file_bounds.unwrap_or_else(GPSBounds::new)
+    };
+    let scrape_bounds = gps_bounds == GPSBounds::new();
+    if scrape_bounds {
+        warn!(
+            "No clipping polygon provided and the input is missing bounds, so figuring out the \
+             bounds manually."
+        );
+    }
+
+    let mut nodes: BTreeMap<NodeID, (LonLat, Tags)> = BTreeMap::new();
+    timer.start("read OSM nodes");
+    input.for_each(true, false, |element| {
+        if let Element::Node { id, pt, tags } = element {
+            if scrape_bounds {
+                gps_bounds.update(pt);
+            }
+            if (needed_nodes.contains(&id) || OsmExtract::wants_node(&tags))
+                && nodes.insert(id, (pt, tags)).is_some()
+            {
+                duplicate = Some(OsmID::Node(id));
+            }
+        }
+    })?;
+    timer.stop("read OSM nodes");
+    if let Some(id) = duplicate {
+        bail!("Duplicate {}, your input is corrupt", id);
+    }
+    drop(needed_nodes);
+
+    if clip_pts.is_none() {
+        // Use the boundary from the input.
+        streets.gps_bounds = gps_bounds.clone();
+        streets.boundary_polygon = streets.gps_bounds.to_bounds().get_rectangle();
+    }
+
+    let mut out = OsmExtract::new();
+
+    // Nodes not referenced by any road are skipped entirely, except for stops. They wouldn't affect
+    // the result, unless a different node happens to be at exactly the same position as a road's
+    // node.
+    let mut pts: HashMap<NodeID, Pt2D> = HashMap::new();
+    timer.start_iter("processing OSM nodes", nodes.len());
+    for (id, (pt, tags)) in nodes {
+        timer.next();
+        let node = Node {
+            pt: pt.to_pt(&gps_bounds),
+            tags,
+        };
+        out.handle_node(id, &node);
+        pts.insert(id, node.pt);
+    }
+
+    let mut resolved_ways: BTreeMap<WayID, Way> = BTreeMap::new();
+    for (id, (refs, tags)) in ways {
+        let mut nodes = Vec::new();
+        let mut way_pts = Vec::new();
+        for n in refs {
+            // Just skip missing nodes
+            if let Some(pt) = pts.get(&n) {
+                nodes.push(n);
+                way_pts.push(*pt);
+            }
+        }
+        if !nodes.is_empty() {
+            resolved_ways.insert(
+                id,
+                Way {
+                    nodes,
+                    pts: way_pts,
+                    tags,
+                },
+            );
+        }
+    }
+
+    timer.start_iter("processing OSM ways", resolved_ways.len());
+    for (id, way) in &resolved_ways {
+        timer.next();
+        out.handle_way(*id, way, opts);
+    }
+
+    let boundary: &Ring = &streets.boundary_polygon.get_outer_ring();
+    timer.start_iter("processing OSM relations", relations.len());
+    for (id, mut rel) in relations {
+        timer.next();
+        rel.members.retain(|(_, member)| match member {
+            OsmID::Node(n) => pts.contains_key(n),
+            _ => true,
+        });
+        out.handle_area_relation(id, &rel, &resolved_ways, Some(boundary), opts);
         out.handle_relation(id, &rel);
     }
 
     Ok(out)
 }
 
+fn add_extra_buildings(streets: &mut StreetNetwork, path: &str) -> Result<()> {
+    // Anything outside the boundary gets clipped later
+    let require_in_bounds = false;
+    let polygons = Polygon::from_geojson_bytes(
+        &std::fs::read(path)?,
+        &streets.gps_bounds,
+        require_in_bounds,
+    )?;
+    info!("Read {} extra buildings from {}", polygons.len(), path);
+    for (polygon, props) in polygons {
+        let mut osm_tags = Tags::empty();
+        for (k, v) in props {
+            osm_tags.insert(k, v);
+        }
+        if !osm_tags.contains_key("building") {
+            osm_tags.insert("building", "yes");
+        }
+        streets.areas.push(Area {
+            area_type: AreaType::Building,
+            polygon,
+            osm_id: None,
+            osm_tags,
+            frontage: None,
+        });
+    }
+    Ok(())
+}
+
 pub fn use_barrier_nodes(
     streets: &mut StreetNetwork,
     barrier_nodes: HashSet<HashablePt2D>,
@@ -218,22 +570,99 @@ pub fn use_barrier_nodes(
     }
 }
 
+pub fn use_stop_nodes(
+    streets: &mut StreetNetwork,
+    stop_nodes: Vec<(NodeID, Pt2D, StopType, Tags)>,
+) {
+    let bus_matcher = TransitMatcher::new(streets, TransitKind::Bus, None);
+    let tram_matcher = TransitMatcher::new(streets, TransitKind::Tram, None);
+
+    let mut stops = Vec::new();
+    for (id, pt, stop_type, tags) in stop_nodes {
+        if !streets.boundary_polygon.contains_pt(pt) {
+            continue;
+        }
+        let matcher = match stop_type.transit_kind() {
+            TransitKind::Tram => &tram_matcher,
+            _ => &bus_matcher,
+        };
+        // Platforms and bus stops are often mapped beside the road
+        let snapped = match matcher.snap_stop(pt) {
+            Some(snapped) => snapped,
+            None => continue,
+        };
+        let road = &streets.roads[&snapped.road];
+
+        // The side of the road can't be determined for nodes on the center line, so trust the
+        // tags when they're present
+        let dir = if tags.is("direction", "forward") {
+            Direction::Fwd
+        } else if tags.is("direction", "backward") {
+            Direction::Back
+        } else {
+            snapped.dir
+        };
+
+        let side = if (dir == Direction::Fwd) == (streets.config.driving_side == DrivingSide::Right)
+        {
+            "right"
+        } else {
+            "left"
+        };
+        let placement = if tags.is_any("bus_bay", vec!["yes", "both", side])
+            || road.osm_tags.is_any("bus_bay", vec!["both", side])
+        {
+            StopPlacement::Bay
+        } else if road
+            .lane_specs_ltr
+            .iter()
+            .any(|l| l.lt == LaneType::Bus && l.dir == dir)
+        {
+            StopPlacement::BusLane
+        } else {
+            StopPlacement::Roadside
+        };
+
+        stops.push((
+            snapped.road,
+            PublicTransportStop {
+                osm_node_id: id,
+                stop_type,
+                name: tags.get("name").cloned(),
+                position: pt,
+                dist_along: snapped.dist_along,
+                dir,
+                placement,
+            },
+        ));
+    }
+
+    for (r, stop) in stops {
+        streets
+            .roads
+            .get_mut(&r)
+            .unwrap()
+            .public_transport_stops
+            .push(stop);
+    }
+}
+
 pub fn use_crossing_nodes(
     streets: &mut StreetNetwork,
-    crossing_nodes: &HashSet<(HashablePt2D, CrossingType)>,
+    crossing_nodes: &HashSet<(NodeID, HashablePt2D, CrossingType)>,
     pt_to_road: &HashMap<HashablePt2D, OriginalRoad>,
 ) {
-    for (pt, kind) in crossing_nodes {
+    for (id, pt, kind) in crossing_nodes {
         // Some crossings are on footpaths or roads that we don't retain
         if let Some(road) = pt_to_road.get(pt).and_then(|r| streets.roads.get_mut(r)) {
-            road.crossing_nodes.push((pt.to_pt2d(), *kind));
+            road.crossing_nodes.push((*id, pt.to_pt2d(), *kind));
         }
     }
 }
 
 pub fn filter_crosswalks(
     streets: &mut StreetNetwork,
-    crosswalks: HashSet<(HashablePt2D, CrossingType)>,
+    crosswalks: HashSet<(NodeID, HashablePt2D, CrossingType)>,
     pt_to_road: HashMap<HashablePt2D, OriginalRoad>,
     timer: &mut Timer,
 ) {
@@ -246,7 +675,7 @@ pub fn filter_crosswalks(
 
     // Match each crosswalk node to a road
     timer.start_iter("filter crosswalks", crosswalks.len());
-    for (pt, _) in crosswalks {
+    for (_, pt, _) in crosswalks {
         timer.next();
         // Some crossing nodes are outside the map boundary or otherwise not on a road that we
         // retained
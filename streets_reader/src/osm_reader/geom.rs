@@ -1,22 +1,26 @@
 //! Utilities for extracting concrete geometry from OSM objects.
 
+use std::collections::BTreeMap;
+
 use anyhow::Result;
 
 use geom::{PolyLine, Polygon, Pt2D, Ring};
 use osm2streets::osm::{OsmID, RelationID, WayID};
 
-use super::{Document, Relation};
+use super::{Relation, Way};
 
 pub fn get_multipolygon_members(
     id: RelationID,
     rel: &Relation,
-    doc: &Document,
+    ways: &BTreeMap<WayID, Way>,
 ) -> Vec<(WayID, Vec<Pt2D>)> {
     let mut pts_per_way = Vec::new();
     for (role, member) in &rel.members {
         if let OsmID::Way(w) = member {
             if role == "outer" {
-                pts_per_way.push((*w, doc.ways[w].pts.clone()));
+                if let Some(way) = ways.get(w) {
+                    pts_per_way.push((*w, way.pts.clone()));
+                }
             } else {
                 println!("{} has unhandled member role {}, ignoring it", id, role);
             }
@@ -94,7 +98,10 @@ pub fn glue_multipolygon(
             } else {
                 // Give up and just connect the ends directly.
                 result.push(result[0]);
-                polygons.push(Ring::must_new(result).into_polygon());
+                match Ring::new(result) {
+                    Ok(ring) => polygons.push(ring.into_polygon()),
+                    Err(err) => error!("Can't close multipolygon {}: {}", rel_id, err),
+                }
             }
         }
         Err(err) => {
@@ -128,17 +135,20 @@ fn glue_to_boundary(result_pl: PolyLine, boundary: &Ring) -> Option<Polygon> {
 }
 
 /// May return multiple polygons, when there are multiple `outer` members. Silently filters out
-/// invalid polygons.
+/// invalid polygons and members missing from `ways`.
 pub fn multipoly_geometry(
     rel_id: RelationID,
     rel: &Relation,
-    doc: &Document,
+    ways: &BTreeMap<WayID, Way>,
 ) -> Result<Vec<Polygon>> {
     let mut outer: Vec<Vec<Pt2D>> = Vec::new();
     let mut inner: Vec<Vec<Pt2D>> = Vec::new();
     for (role, member) in &rel.members {
         if let OsmID::Way(w) = member {
-            let mut deduped = doc.ways[w].pts.clone();
+            let mut deduped = match ways.get(w) {
+                Some(way) => way.pts.clone(),
+                None => continue,
+            };
             deduped.dedup();
             if deduped.len() < 3 {
                 continue;
@@ -1,5 +1,11 @@
 mod geom;
+#[cfg(not(target_arch = "wasm32"))]
+mod pbf;
 mod reader;
+mod stream;
 
 pub use self::geom::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::pbf::*;
 pub use self::reader::*;
+pub use self::stream::*;
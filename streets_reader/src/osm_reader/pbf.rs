@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use abstutil::{prettyprint_usize, Tags, Timer};
+use geom::{GPSBounds, LonLat};
+use osm2streets::osm::{NodeID, OsmID, RelationID, WayID};
+
+use super::{Document, Element, Node, OsmInput, Relation, Way};
+
+/// Read an `.osm.pbf` file into the same `Document` that `read` produces from `.osm.xml`. The file
+/// is decoded block-by-block, so the raw input never has to be held in memory.
+///
+/// If `input_gps_bounds` is empty, the bounds are calculated from every node in the file. (The
+/// optional bounding box in the PBF header isn't used.)
+pub fn read_pbf(path: &str, input_gps_bounds: &GPSBounds, timer: &mut Timer) -> Result<Document> {
+    // Nodes can't be converted to Pt2D until the bounds are known, so first gather everything in
+    // WGS84.
+    let mut nodes: BTreeMap<NodeID, (LonLat, Tags)> = BTreeMap::new();
+    let mut ways: BTreeMap<WayID, (Vec<NodeID>, Tags)> = BTreeMap::new();
+    // Relations stay in the order of the file, so members can be filtered like the XML reader does
+    let mut relations: Vec<(RelationID, Vec<(String, OsmID)>, Tags)> = Vec::new();
+    let mut duplicate = None;
+
+    timer.start("parse osm.pbf");
+    OsmInput::Pbf(path).for_each(true, true, |element| match element {
+        Element::Bounds(_) => {}
+        Element::Node { id, pt, tags } => {
+            if nodes.insert(id, (pt, tags)).is_some() {
+                duplicate = Some(OsmID::Node(id));
+            }
+        }
+        Element::Way {
+            id,
+            nodes: refs,
+            tags,
+        } => {
+            if ways.insert(id, (refs, tags)).is_some() {
+                duplicate = Some(OsmID::Way(id));
+            }
+        }
+        Element::Relation { id, tags, members } => {
+            relations.push((id, members, tags));
+        }
+    })?;
+    timer.stop("parse osm.pbf");
+    if let Some(id) = duplicate {
+        bail!("Duplicate {}, your .osm.pbf is corrupt", id);
+    }
+
+    let mut doc = Document {
+        gps_bounds: input_gps_bounds.clone(),
+        nodes: BTreeMap::new(),
+        ways: BTreeMap::new(),
+        relations: BTreeMap::new(),
+    };
+    if doc.gps_bounds == GPSBounds::new() {
+        for (pt, _) in nodes.values() {
+            doc.gps_bounds.update(*pt);
+        }
+    }
+
+    timer.start("scrape objects");
+    for (id, (pt, tags)) in nodes {
+        let pt = pt.to_pt(&doc.gps_bounds);
+        doc.nodes.insert(id, Node { pt, tags });
+    }
+
+    for (id, (refs, tags)) in ways {
+        let mut nodes = Vec::new();
+        let mut pts = Vec::new();
+        for n in refs {
+            // Just skip missing nodes
+            if let Some(node) = doc.nodes.get(&n) {
+                nodes.push(n);
+                pts.push(node.pt);
+            }
+        }
+        if !nodes.is_empty() {
+            doc.ways.insert(id, Way { nodes, pts, tags });
+        }
+    }
+
+    // Like the XML reader, filter out references to missing objects. Relations can only refer to
+    // ones earlier in the file.
+    for (id, members, tags) in relations {
+        if doc.relations.contains_key(&id) {
+            bail!("Duplicate {}, your .osm.pbf is corrupt", id);
+        }
+        let members = members
+            .into_iter()
+            .filter(|(_, member)| match member {
+                OsmID::Node(n) => doc.nodes.contains_key(n),
+                OsmID::Way(w) => doc.ways.contains_key(w),
+                OsmID::Relation(r) => doc.relations.contains_key(r),
+            })
+            .collect();
+        doc.relations.insert(id, Relation { tags, members });
+    }
+    timer.stop("scrape objects");
+
+    info!(
+        "Found {} nodes, {} ways, {} relations",
+        prettyprint_usize(doc.nodes.len()),
+        prettyprint_usize(doc.ways.len()),
+        prettyprint_usize(doc.relations.len())
+    );
+
+    Ok(doc)
+}
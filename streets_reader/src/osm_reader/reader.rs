@@ -177,8 +177,7 @@ fn read_tags(obj: roxmltree::Node) -> Tags {
     for child in obj.children() {
         if child.tag_name().name() == "tag" {
             let key = child.attribute("k").unwrap();
-            // Filter out really useless data
-            if key.starts_with("tiger:") || key.starts_with("old_name:") {
+            if is_useless_tag(key) {
                 continue;
             }
             tags.insert(key, child.attribute("v").unwrap());
@@ -187,6 +186,11 @@ fn read_tags(obj: roxmltree::Node) -> Tags {
     tags
 }
 
+/// Filter out really useless data
+pub(crate) fn is_useless_tag(key: &str) -> bool {
+    key.starts_with("tiger:") || key.starts_with("old_name:")
+}
+
 fn scrape_bounds(doc: &roxmltree::Document) -> GPSBounds {
     let mut b = GPSBounds::new();
     for obj in doc.descendants() {
@@ -0,0 +1,369 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+
+use abstutil::Tags;
+use geom::{GPSBounds, LonLat};
+use osm2streets::osm::{NodeID, OsmID, RelationID, WayID};
+
+use super::reader::is_useless_tag;
+
+/// One object from OSM input, without resolving references to other objects. Large inputs can be
+/// processed in several passes over these, without ever building a full `Document`.
+pub enum Element {
+    /// The `<bounds>` element of an `.osm.xml` file
+    Bounds(GPSBounds),
+    Node {
+        id: NodeID,
+        pt: LonLat,
+        tags: Tags,
+    },
+    Way {
+        id: WayID,
+        nodes: Vec<NodeID>,
+        tags: Tags,
+    },
+    Relation {
+        id: RelationID,
+        tags: Tags,
+        /// Role, member
+        members: Vec<(String, OsmID)>,
+    },
+}
+
+pub enum OsmInput<'a> {
+    /// The contents of an `.osm.xml` file
+    Xml(&'a str),
+    /// A path to an `.osm.pbf` file
+    #[cfg(not(target_arch = "wasm32"))]
+    Pbf(&'a str),
+}
+
+impl<'a> OsmInput<'a> {
+    /// Calls `cb` on every object in the input, in file order. Nodes and everything else can be
+    /// skipped, to avoid parsing tags that'll be thrown away.
+    pub fn for_each<F: FnMut(Element)>(
+        &self,
+        read_nodes: bool,
+        read_ways_and_relations: bool,
+        cb: F,
+    ) -> Result<()> {
+        match self {
+            OsmInput::Xml(raw) => stream_xml(raw, read_nodes, read_ways_and_relations, cb),
+            #[cfg(not(target_arch = "wasm32"))]
+            OsmInput::Pbf(path) => stream_pbf(path, read_nodes, read_ways_and_relations, cb),
+        }
+    }
+}
+
+type Attributes<'a> = Vec<(&'a str, Cow<'a, str>)>;
+
+// The object currently being parsed from XML
+struct PartialElement<'a> {
+    name: &'a str,
+    attributes: Attributes<'a>,
+    tags: Tags,
+    nodes: Vec<NodeID>,
+    members: Vec<(String, OsmID)>,
+}
+
+impl<'a> PartialElement<'a> {
+    fn get(&self, key: &str) -> Result<&str> {
+        match self.attributes.iter().find(|(k, _)| *k == key) {
+            Some((_, v)) => Ok(v),
+            None => bail!("<{}> is missing the {} attribute", self.name, key),
+        }
+    }
+
+    fn get_f64(&self, key: &str) -> Result<f64> {
+        Ok(self.get(key)?.parse::<f64>()?)
+    }
+
+    fn get_i64(&self, key: &str) -> Result<i64> {
+        Ok(self.get(key)?.parse::<i64>()?)
+    }
+
+    fn into_element(self) -> Result<Option<Element>> {
+        Ok(Some(match self.name {
+            "bounds" => {
+                let mut gps_bounds = GPSBounds::new();
+                gps_bounds.update(LonLat::new(
+                    self.get_f64("minlon")?,
+                    self.get_f64("minlat")?,
+                ));
+                gps_bounds.update(LonLat::new(
+                    self.get_f64("maxlon")?,
+                    self.get_f64("maxlat")?,
+                ));
+                Element::Bounds(gps_bounds)
+            }
+            "node" => Element::Node {
+                id: NodeID(self.get_i64("id")?),
+                pt: LonLat::new(self.get_f64("lon")?, self.get_f64("lat")?),
+                tags: self.tags,
+            },
+            "way" => Element::Way {
+                id: WayID(self.get_i64("id")?),
+                nodes: self.nodes,
+                tags: self.tags,
+            },
+            "relation" => Element::Relation {
+                id: RelationID(self.get_i64("id")?),
+                tags: self.tags,
+                members: self.members,
+            },
+            _ => return Ok(None),
+        }))
+    }
+}
+
+fn stream_xml<F: FnMut(Element)>(
+    raw: &str,
+    read_nodes: bool,
+    read_ways_and_relations: bool,
+    mut cb: F,
+) -> Result<()> {
+    // How many elements are open. Objects are directly inside <osm>.
+    let mut depth = 0;
+    let mut current: Option<PartialElement> = None;
+    // The name and attributes of a <tag>, <nd>, or <member> inside the current object
+    let mut child: Option<(&str, Attributes)> = None;
+
+    for token in xmlparser::Tokenizer::from(raw) {
+        match token? {
+            xmlparser::Token::ElementStart { local, .. } => {
+                let name = local.as_str();
+                if depth == 1 {
+                    let wanted = match name {
+                        "bounds" => true,
+                        "node" => read_nodes,
+                        "way" | "relation" => read_ways_and_relations,
+                        _ => false,
+                    };
+                    current = if wanted {
+                        Some(PartialElement {
+                            name,
+                            attributes: Vec::new(),
+                            tags: Tags::empty(),
+                            nodes: Vec::new(),
+                            members: Vec::new(),
+                        })
+                    } else {
+                        None
+                    };
+                } else if depth == 2 && current.is_some() {
+                    child = Some((name, Vec::new()));
+                }
+            }
+            xmlparser::Token::Attribute { local, value, .. } => {
+                if depth == 1 {
+                    if let Some(ref mut obj) = current {
+                        obj.attributes
+                            .push((local.as_str(), unescape(value.as_str())));
+                    }
+                } else if depth == 2 {
+                    if let Some((_, ref mut attributes)) = child {
+                        attributes.push((local.as_str(), unescape(value.as_str())));
+                    }
+                }
+            }
+            xmlparser::Token::ElementEnd { end, .. } => match end {
+                xmlparser::ElementEnd::Open => {
+                    if depth == 2 {
+                        if let Some((name, attributes)) = child.take() {
+                            handle_child(current.as_mut().unwrap(), name, attributes)?;
+                        }
+                    }
+                    depth += 1;
+                }
+                xmlparser::ElementEnd::Empty => {
+                    if depth == 1 {
+                        if let Some(elem) = current.take().map(|obj| obj.into_element()) {
+                            if let Some(elem) = elem? {
+                                cb(elem);
+                            }
+                        }
+                    } else if depth == 2 {
+                        if let Some((name, attributes)) = child.take() {
+                            handle_child(current.as_mut().unwrap(), name, attributes)?;
+                        }
+                    }
+                }
+                xmlparser::ElementEnd::Close(_, _) => {
+                    depth -= 1;
+                    if depth == 1 {
+                        if let Some(elem) = current.take().map(|obj| obj.into_element()) {
+                            if let Some(elem) = elem? {
+                                cb(elem);
+                            }
+                        }
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn handle_child(obj: &mut PartialElement, name: &str, attributes: Attributes) -> Result<()> {
+    let get = |key| get_attribute(name, &attributes, key);
+
+    match name {
+        "tag" => {
+            let key = get("k")?;
+            if !is_useless_tag(key) {
+                obj.tags.insert(key, get("v")?);
+            }
+        }
+        "nd" => {
+            obj.nodes.push(NodeID(get("ref")?.parse::<i64>()?));
+        }
+        "member" => {
+            let id = get("ref")?.parse::<i64>()?;
+            let member = match get("type")? {
+                "node" => OsmID::Node(NodeID(id)),
+                "way" => OsmID::Way(WayID(id)),
+                "relation" => OsmID::Relation(RelationID(id)),
+                _ => return Ok(()),
+            };
+            obj.members.push((get("role")?.to_string(), member));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn get_attribute<'b>(name: &str, attributes: &'b [(&str, Cow<str>)], key: &str) -> Result<&'b str> {
+    match attributes.iter().find(|(k, _)| *k == key) {
+        Some((_, v)) => Ok(v),
+        None => bail!("<{}> is missing the {} attribute", name, key),
+    }
+}
+
+// Attribute values have to be unescaped and normalized the same way roxmltree does, so the
+// streaming reader and `read` produce identical tags.
+fn unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains(['&', '\t', '\n', '\r']) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find(['&', '\t', '\n', '\r']) {
+        result.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if rest.starts_with("\r\n") {
+            result.push(' ');
+            rest = &rest[2..];
+            continue;
+        }
+        if !rest.starts_with('&') {
+            result.push(' ');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => {
+                    if let Some(hex) = entity.strip_prefix("#x") {
+                        u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                    } else if let Some(dec) = entity.strip_prefix('#') {
+                        dec.parse::<u32>().ok().and_then(char::from_u32)
+                    } else {
+                        None
+                    }
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        if let Some((c, end)) = decoded {
+            result.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            result.push('&');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn stream_pbf<F: FnMut(Element)>(
+    path: &str,
+    read_nodes: bool,
+    read_ways_and_relations: bool,
+    mut cb: F,
+) -> Result<()> {
+    use osmpbf::{ElementReader, RelMemberType};
+
+    ElementReader::from_path(path)?.for_each(|element| match element {
+        osmpbf::Element::Node(node) => {
+            if read_nodes {
+                cb(Element::Node {
+                    id: NodeID(node.id()),
+                    pt: LonLat::new(node.lon(), node.lat()),
+                    tags: read_pbf_tags(node.tags()),
+                });
+            }
+        }
+        osmpbf::Element::DenseNode(node) => {
+            if read_nodes {
+                cb(Element::Node {
+                    id: NodeID(node.id()),
+                    pt: LonLat::new(node.lon(), node.lat()),
+                    tags: read_pbf_tags(node.tags()),
+                });
+            }
+        }
+        osmpbf::Element::Way(way) => {
+            if read_ways_and_relations {
+                cb(Element::Way {
+                    id: WayID(way.id()),
+                    nodes: way.refs().map(NodeID).collect(),
+                    tags: read_pbf_tags(way.tags()),
+                });
+            }
+        }
+        osmpbf::Element::Relation(rel) => {
+            if read_ways_and_relations {
+                let mut members = Vec::new();
+                for member in rel.members() {
+                    let role = member.role().unwrap_or("").to_string();
+                    let id = match member.member_type {
+                        RelMemberType::Node => OsmID::Node(NodeID(member.member_id)),
+                        RelMemberType::Way => OsmID::Way(WayID(member.member_id)),
+                        RelMemberType::Relation => OsmID::Relation(RelationID(member.member_id)),
+                    };
+                    members.push((role, id));
+                }
+                cb(Element::Relation {
+                    id: RelationID(rel.id()),
+                    tags: read_pbf_tags(rel.tags()),
+                    members,
+                });
+            }
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_pbf_tags<'a, I: Iterator<Item = (&'a str, &'a str)>>(iter: I) -> Tags {
+    let mut tags = Tags::empty();
+    for (key, value) in iter {
+        if is_useless_tag(key) {
+            continue;
+        }
+        tags.insert(key, value);
+    }
+    tags
+}
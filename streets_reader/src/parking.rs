@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+
+use abstutil::{Tags, Timer};
+use anyhow::Result;
+use geojson::{GeoJson, JsonObject, JsonValue};
+use geom::{Distance, FindClosest, LonLat, PolyLine};
+use osm2streets::{osm, Direction, DrivingSide, LaneSpec, OriginalRoad, StreetNetwork};
+
+/// Conflate curb segments from a GeoJSON file onto the sides of roads without any parking tagged
+/// in OSM. Each LineString needs a `parking` property (or `PARKING_CATEGORY`, as in Seattle's
+/// blockface dataset). Values like "no", "none", and "No Parking Allowed" mean there's no parking
+/// along that curb; anything else means there is. Where curbs disagree about one side of a road,
+/// the longest total length wins.
+pub fn use_parking_hints(streets: &mut StreetNetwork, path: &str, timer: &mut Timer) -> Result<()> {
+    timer.start("read parking hints");
+    let curbs = read_curbs(streets, path)?;
+    timer.stop("read parking hints");
+
+    // Curbs are offset from the center line by up to half the road's width
+    let max_dist = Distance::meters(20.0);
+    let step_size = Distance::meters(5.0);
+    let parallel_threshold = 30.0;
+
+    let mut center_lines = BTreeMap::new();
+    let mut closest = FindClosest::new(&streets.gps_bounds.to_bounds());
+    for (id, road) in &streets.roads {
+        if !road.is_driveable() || has_parking_data(&road.osm_tags) {
+            continue;
+        }
+        if let Ok(pl) = PolyLine::new(road.osm_center_points.clone()) {
+            closest.add(*id, pl.points());
+            center_lines.insert(*id, pl);
+        }
+    }
+
+    // For each side of a road, the length of curb with and without parking
+    let mut votes: BTreeMap<(OriginalRoad, bool), (Distance, Distance)> = BTreeMap::new();
+    timer.start_iter("match parking hints", curbs.len());
+    for (curb, parking) in curbs {
+        timer.next();
+        let mut dist = step_size / 2.0;
+        while dist < curb.length() {
+            let (pt, curb_angle) = curb.must_dist_along(dist);
+            dist += step_size;
+
+            // Near intersections, the closest road may be a perpendicular one
+            let best = closest
+                .all_close_pts(pt, max_dist)
+                .into_iter()
+                .filter_map(|(road, _, _)| {
+                    let pl = &center_lines[&road];
+                    let projected = pl.project_pt(pt);
+                    let (_, road_angle) = pl.dist_along_of_point(projected)?;
+                    if !road_angle.approx_parallel(curb_angle, parallel_threshold) {
+                        return None;
+                    }
+                    let mut diff = projected.angle_to(pt).normalized_degrees()
+                        - road_angle.normalized_degrees();
+                    if diff > 180.0 {
+                        diff -= 360.0;
+                    } else if diff <= -180.0 {
+                        diff += 360.0;
+                    }
+                    // Angles increase clockwise, so a negative difference means the left
+                    Some((projected.dist_to(pt), road, diff < 0.0))
+                })
+                .min_by_key(|(dist_to_road, _, _)| *dist_to_road);
+            if let Some((_, road, left_side)) = best {
+                let (with, without) = votes
+                    .entry((road, left_side))
+                    .or_insert((Distance::ZERO, Distance::ZERO));
+                if parking {
+                    *with += step_size;
+                } else {
+                    *without += step_size;
+                }
+            }
+        }
+    }
+
+    let mut added = 0;
+    for ((id, left_side), (with, without)) in votes {
+        if with > without {
+            let road = streets.roads.get_mut(&id).unwrap();
+            if LaneSpec::add_parking_lane(&mut road.lane_specs_ltr, left_side, &road.osm_tags) {
+                added += 1;
+            }
+        }
+    }
+    info!("Added {} parking lanes from {}", added, path);
+    Ok(())
+}
+
+/// Add parallel parking to about `pct`% of the `highway=residential` roads without any parking
+/// tagged in OSM. Whether a road is picked only depends on `seed` and its OSM way, so the same
+/// roads are picked for every import and every boundary. One-way roads only get parking on the
+/// curb side for their direction of travel.
+pub fn add_inferred_parking(streets: &mut StreetNetwork, pct: usize, seed: u64) {
+    let driving_side = streets.config.driving_side;
+    let mut added = 0;
+    for (id, road) in &mut streets.roads {
+        if !road.osm_tags.is(osm::HIGHWAY, "residential")
+            || !road.is_driveable()
+            || has_parking_data(&road.osm_tags)
+            || roll(seed, id.osm_way_id.0) >= pct
+        {
+            continue;
+        }
+        let sides = match road.oneway_for_driving() {
+            Some(dir) => {
+                // The curb side is on the right when driving on the right
+                let right = (driving_side == DrivingSide::Right) == (dir == Direction::Fwd);
+                vec![!right]
+            }
+            None => vec![true, false],
+        };
+        for left_side in sides {
+            if LaneSpec::add_parking_lane(&mut road.lane_specs_ltr, left_side, &road.osm_tags) {
+                added += 1;
+            }
+        }
+    }
+    info!("Inferred {} parking lanes", added);
+}
+
+fn has_parking_data(tags: &Tags) -> bool {
+    [
+        osm::PARKING_LEFT,
+        osm::PARKING_RIGHT,
+        osm::PARKING_BOTH,
+        "parking:lane",
+        "parking:left",
+        "parking:right",
+        "parking:both",
+    ]
+    .into_iter()
+    .any(|key| tags.contains_key(key))
+}
+
+// A deterministic number in [0, 100), evenly distributed over OSM ways
+fn roll(seed: u64, osm_way_id: i64) -> usize {
+    // The splitmix64 finalizer
+    let mut x = seed ^ (osm_way_id as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+    (x % 100) as usize
+}
+
+fn read_curbs(streets: &StreetNetwork, path: &str) -> Result<Vec<(PolyLine, bool)>> {
+    let geojson: GeoJson = std::fs::read_to_string(path)?.parse()?;
+    let features = match geojson {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        _ => bail!("{} isn't a GeoJSON FeatureCollection", path),
+    };
+
+    let mut curbs = Vec::new();
+    for feature in features {
+        let parking = match feature.properties.as_ref().and_then(curb_has_parking) {
+            Some(parking) => parking,
+            None => continue,
+        };
+        let lines = match feature.geometry.map(|g| g.value) {
+            Some(geojson::Value::LineString(line)) => vec![line],
+            Some(geojson::Value::MultiLineString(lines)) => lines,
+            _ => continue,
+        };
+        for line in lines {
+            let mut pts = Vec::new();
+            for pos in line {
+                if pos.len() < 2 {
+                    continue;
+                }
+                let lonlat = LonLat::new(pos[0], pos[1]);
+                // Curbs outside the map are useless
+                if streets.gps_bounds.contains(lonlat) {
+                    pts.push(lonlat.to_pt(&streets.gps_bounds));
+                }
+            }
+            if let Ok(pl) = PolyLine::deduping_new(pts) {
+                curbs.push((pl, parking));
+            }
+        }
+    }
+    Ok(curbs)
+}
+
+// None if the curb doesn't say
+fn curb_has_parking(props: &JsonObject) -> Option<bool> {
+    let value = props.iter().find_map(|(key, value)| {
+        if key.eq_ignore_ascii_case("parking") || key.eq_ignore_ascii_case("parking_category") {
+            Some(value)
+        } else {
+            None
+        }
+    })?;
+    let value = match value {
+        JsonValue::Bool(x) => return Some(*x),
+        JsonValue::Null => return None,
+        JsonValue::String(x) => x.trim().to_lowercase(),
+        x => x.to_string(),
+    };
+    if value == "unknown" {
+        return None;
+    }
+    Some(
+        ![
+            "",
+            "0",
+            "false",
+            "no",
+            "no parking",
+            "no parking allowed",
+            "no_parking",
+            "no_stopping",
+            "none",
+        ]
+        .contains(&value.as_str()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curb_has_parking() {
+        let props = |key: &str, value: JsonValue| {
+            let mut props = JsonObject::new();
+            props.insert(key.to_string(), value);
+            props
+        };
+        assert_eq!(
+            curb_has_parking(&props("PARKING_CATEGORY", "Paid Parking".into())),
+            Some(true)
+        );
+        assert_eq!(
+            curb_has_parking(&props("PARKING_CATEGORY", "No Parking Allowed".into())),
+            Some(false)
+        );
+        assert_eq!(
+            curb_has_parking(&props("parking", "yes".into())),
+            Some(true)
+        );
+        assert_eq!(
+            curb_has_parking(&props("parking", false.into())),
+            Some(false)
+        );
+        assert_eq!(curb_has_parking(&props("parking", "unknown".into())), None);
+        assert_eq!(curb_has_parking(&props("name", "Main St".into())), None);
+    }
+
+    #[test]
+    fn test_roll() {
+        // The same seed always picks the same ways, and about the right number of them
+        let picked = (0..10_000).filter(|id| roll(42, *id) < 30).count();
+        assert_eq!(picked, (0..10_000).filter(|id| roll(42, *id) < 30).count());
+        assert!((2700..3300).contains(&picked));
+        assert_ne!(
+            (0..100).map(|id| roll(1, id)).collect::<Vec<_>>(),
+            (0..100).map(|id| roll(2, id)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_use_parking_hints() -> Result<()> {
+        // One two-way road, heading east
+        let osm = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="47.6" minlon="-122.34" maxlat="47.61" maxlon="-122.33"/>
+  <node id="1" lat="47.605" lon="-122.338"/>
+  <node id="2" lat="47.605" lon="-122.332"/>
+  <way id="100">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+    <tag k="sidewalk" v="both"/>
+  </way>
+</osm>"#;
+        // Curbs about 11m to either side. The north (left) side has 120m with parking and 37.5m
+        // without; the south (right) side has 120m without and 60m with.
+        let curb = |lat: f64, lon1: f64, lon2: f64, key: &str, value: &str| {
+            format!(
+                r#"{{"type": "Feature", "properties": {{"{key}": "{value}"}}, "geometry": {{"type": "LineString", "coordinates": [[{lon1}, {lat}], [{lon2}, {lat}]]}}}}"#
+            )
+        };
+        let curbs = [
+            curb(47.6051, -122.3375, -122.3359, "parking", "yes"),
+            curb(47.6051, -122.3350, -122.3345, "parking", "no"),
+            curb(
+                47.6049,
+                -122.3375,
+                -122.3359,
+                "PARKING_CATEGORY",
+                "No Parking Allowed",
+            ),
+            curb(
+                47.6049,
+                -122.3350,
+                -122.3342,
+                "PARKING_CATEGORY",
+                "Paid Parking",
+            ),
+        ];
+        let path = std::env::temp_dir().join("streets_reader_test_use_parking_hints.geojson");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            format!(
+                r#"{{"type": "FeatureCollection", "features": [{}]}}"#,
+                curbs.join(", ")
+            ),
+        )?;
+
+        let mut opts = crate::Options::default_for_side(DrivingSide::Right);
+        opts.onstreet_parking = crate::OnstreetParking::Blockface(path.to_string());
+        let streets = crate::osm_to_street_network(osm, None, opts, &mut Timer::throwaway())?;
+        std::fs::remove_file(path)?;
+
+        let lanes = &streets.roads[&OriginalRoad::new(100, (1, 2))].lane_specs_ltr;
+        let parking: Vec<_> = lanes
+            .iter()
+            .enumerate()
+            .filter(|(_, lane)| lane.lt == osm2streets::LaneType::Parking)
+            .map(|(idx, lane)| (idx, lane.dir))
+            .collect();
+        // Only on the left, next to the sidewalk, facing westbound traffic
+        assert_eq!(parking, vec![(1, Direction::Back)]);
+        Ok(())
+    }
+}
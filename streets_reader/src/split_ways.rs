@@ -3,15 +3,16 @@ use std::collections::{hash_map::Entry, HashMap, HashSet};
 use abstutil::{Counter, Tags, Timer};
 use geom::{Distance, HashablePt2D, PolyLine, Pt2D};
 use osm2streets::{
-    osm, ControlType, CrossingType, Direction, Intersection, IntersectionComplexity, OriginalRoad,
-    Road, StreetNetwork,
+    osm, ApproachControl, ControlType, CrossingType, Direction, Intersection,
+    IntersectionComplexity, OriginalRoad, Road, StopType, StreetNetwork,
 };
 
 use super::OsmExtract;
 
 pub struct Output {
-    pub crossing_nodes: HashSet<(HashablePt2D, CrossingType)>,
+    pub crossing_nodes: HashSet<(osm::NodeID, HashablePt2D, CrossingType)>,
     pub barrier_nodes: HashSet<HashablePt2D>,
+    pub stop_nodes: Vec<(osm::NodeID, Pt2D, StopType, Tags)>,
     /// A mapping of all points to the split road. Some internal points on roads get removed in
     /// `split_up_roads`, so this mapping isn't redundant.
     pub pt_to_road: HashMap<HashablePt2D, OriginalRoad>,
@@ -59,7 +60,13 @@ pub fn split_up_roads(
         }
     }
 
+    // Signals and signs mapped on the intersection node itself, and whether they apply to every
+    // approach
+    let mut controlled_intersections: Vec<(osm::NodeID, ApproachControl, bool)> = Vec::new();
     for (pt, id) in &pt_to_intersection {
+        if let Some((sign, _, all_way)) = input.traffic_signs.remove(pt) {
+            controlled_intersections.push((*id, sign, all_way));
+        }
         streets.intersections.insert(
             *id,
             Intersection::new(
@@ -67,10 +74,11 @@ pub fn split_up_roads(
                 // Guess a safe generic complexity, specialise later.
                 IntersectionComplexity::Crossing,
                 if input.traffic_signals.remove(pt).is_some() {
+                    controlled_intersections.push((*id, ApproachControl::TrafficSignal, true));
                     ControlType::TrafficSignal
                 } else {
-                    // TODO default to uncontrolled, guess StopSign as a transform
-                    ControlType::StopSign
+                    // Transformation::InferIntersectionControl works out the rest
+                    ControlType::Uncontrolled
                 },
             ),
         );
@@ -83,7 +91,7 @@ pub fn split_up_roads(
             Intersection::new(
                 point,
                 IntersectionComplexity::Crossing,
-                ControlType::StopSign,
+                ControlType::Uncontrolled,
             ),
         );
     }
@@ -127,7 +135,18 @@ pub fn split_up_roads(
 
                 let osm_center_pts = simplify_linestring(std::mem::take(&mut pts));
                 match Road::new(osm_center_pts, tags, &streets.config) {
-                    Ok(road) => {
+                    Ok(mut road) => {
+                        // Turn lanes describe the end of the whole way, not every piece of it
+                        for lane in &mut road.lane_specs_ltr {
+                            let at_end = if lane.dir == Direction::Fwd {
+                                *i2 == endpt2
+                            } else {
+                                i1 == endpt1
+                            };
+                            if !at_end {
+                                lane.allowed_turns.clear();
+                            }
+                        }
                         streets.insert_road(id, road);
                     }
                     Err(err) => {
@@ -148,7 +167,7 @@ pub fn split_up_roads(
 
     // Resolve simple turn restrictions (via a node)
     let mut restrictions = Vec::new();
-    for (restriction, from_osm, via_osm, to_osm) in input.simple_turn_restrictions {
+    for (restriction, conditions, from_osm, via_osm, to_osm) in input.simple_turn_restrictions {
         if !streets.intersections.contains_key(&via_osm) {
             continue;
         }
@@ -159,22 +178,22 @@ pub fn split_up_roads(
             roads.iter().find(|r| r.osm_way_id == from_osm),
             roads.iter().find(|r| r.osm_way_id == to_osm),
         ) {
-            restrictions.push((*from, restriction, *to));
+            restrictions.push((*from, restriction, *to, conditions));
         }
     }
-    for (from, rt, to) in restrictions {
+    for (from, rt, to, conditions) in restrictions {
         streets
             .roads
             .get_mut(&from)
             .unwrap()
             .turn_restrictions
-            .push((rt, to));
+            .push((rt, to, conditions));
     }
 
     // Resolve complicated turn restrictions (via a way). TODO Only handle via ways immediately
     // connected to both roads, for now
     let mut complicated_restrictions = Vec::new();
-    for (rel_osm, from_osm, via_osm, to_osm) in input.complicated_turn_restrictions {
+    for (rel_osm, conditions, from_osm, via_osm, to_osm) in input.complicated_turn_restrictions {
         let via_candidates: Vec<OriginalRoad> = streets
             .roads
             .keys()
@@ -203,7 +222,7 @@ pub fn split_up_roads(
             .find(|r| r.osm_way_id == to_osm);
         match (maybe_from, maybe_to) {
             (Some(from), Some(to)) => {
-                complicated_restrictions.push((from, via, to));
+                complicated_restrictions.push((from, via, to, conditions));
             }
             _ => {
                 warn!(
@@ -213,13 +232,13 @@ pub fn split_up_roads(
             }
         }
     }
-    for (from, via, to) in complicated_restrictions {
+    for (from, via, to, conditions) in complicated_restrictions {
         streets
             .roads
             .get_mut(&from)
             .unwrap()
             .complicated_turn_restrictions
-            .push((via, to));
+            .push((via, to, conditions));
     }
 
     timer.start("match traffic signals to intersections");
@@ -233,16 +252,67 @@ pub fn split_up_roads(
                 if !road.osm_tags.is(osm::HIGHWAY, "construction") {
                     let i = if dir == Direction::Fwd { r.i2 } else { r.i1 };
                     streets.intersections.get_mut(&i).unwrap().control = ControlType::TrafficSignal;
+                    streets.set_approach_control(i, *r, ApproachControl::TrafficSignal);
                 }
             }
         }
     }
     timer.stop("match traffic signals to intersections");
 
+    timer.start("match traffic signs to approaches");
+    // Unless it's an all-way stop, a sign at the intersection node applies to the minor roads.
+    // When every road has the same rank, there's no telling which those are, so use all of them.
+    for (i, control, all_way) in controlled_intersections {
+        let mut approaches = if all_way {
+            Vec::new()
+        } else {
+            streets.minor_approaches(i)
+        };
+        if approaches.is_empty() {
+            approaches = streets.roads_per_intersection(i);
+        }
+        for r in approaches {
+            streets.set_approach_control(i, r, control);
+        }
+    }
+    // Signs on a way apply to the end they're facing. Without a direction, assume the closest
+    // end.
+    for (pt, (control, dir, all_way)) in input.traffic_signs {
+        let r = match pt_to_road.get(&pt) {
+            Some(r) => *r,
+            None => continue,
+        };
+        let road = match streets.roads.get(&r) {
+            Some(road) => road,
+            None => continue,
+        };
+        let i = match dir {
+            Some(Direction::Fwd) => r.i2,
+            Some(Direction::Back) => r.i1,
+            None => {
+                let pl = PolyLine::unchecked_new(road.osm_center_points.clone());
+                match pl.dist_along_of_point(pl.project_pt(pt.to_pt2d())) {
+                    Some((dist, _)) if dist > pl.length() / 2.0 => r.i2,
+                    Some(_) => r.i1,
+                    None => continue,
+                }
+            }
+        };
+        if all_way {
+            for r in streets.roads_per_intersection(i) {
+                streets.set_approach_control(i, r, control);
+            }
+        } else {
+            streets.set_approach_control(i, r, control);
+        }
+    }
+    timer.stop("match traffic signs to approaches");
+
     timer.stop("splitting up roads");
     Output {
         crossing_nodes: input.crossing_nodes,
         barrier_nodes: input.barrier_nodes,
+        stop_nodes: input.stop_nodes,
         pt_to_road,
     }
 }
@@ -273,3 +343,166 @@ fn should_collapse_roundabout(pts: &[Pt2D], tags: &Tags) -> bool {
         && pts[0] == *pts.last().unwrap()
         && PolyLine::unchecked_new(pts.to_vec()).length() < Distance::meters(50.0)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use osm2streets::{DrivingSide, Transformation};
+
+    use super::*;
+
+    #[test]
+    fn test_controls_on_ways() {
+        // A crossroads at node 2. Signals and signs are mapped on the ways approaching it, except
+        // for a give way sign without a direction near the north end.
+        let osm = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="47.6" minlon="-122.34" maxlat="47.61" maxlon="-122.33"/>
+  <node id="1" lat="47.605" lon="-122.338"/>
+  <node id="2" lat="47.605" lon="-122.335"/>
+  <node id="3" lat="47.605" lon="-122.332"/>
+  <node id="4" lat="47.602" lon="-122.335"/>
+  <node id="5" lat="47.608" lon="-122.335"/>
+  <node id="21" lat="47.605" lon="-122.3352">
+    <tag k="highway" v="traffic_signals"/>
+  </node>
+  <node id="22" lat="47.605" lon="-122.3348">
+    <tag k="highway" v="traffic_signals"/>
+    <tag k="traffic_signals:direction" v="backward"/>
+  </node>
+  <node id="23" lat="47.6048" lon="-122.335">
+    <tag k="highway" v="stop"/>
+    <tag k="direction" v="backward"/>
+  </node>
+  <node id="24" lat="47.6052" lon="-122.335">
+    <tag k="highway" v="stop"/>
+    <tag k="direction" v="forward"/>
+  </node>
+  <node id="25" lat="47.6078" lon="-122.335">
+    <tag k="highway" v="give_way"/>
+  </node>
+  <way id="100">
+    <nd ref="1"/>
+    <nd ref="21"/>
+    <nd ref="2"/>
+    <tag k="highway" v="primary"/>
+  </way>
+  <way id="101">
+    <nd ref="2"/>
+    <nd ref="22"/>
+    <nd ref="3"/>
+    <tag k="highway" v="primary"/>
+  </way>
+  <way id="102">
+    <nd ref="2"/>
+    <nd ref="23"/>
+    <nd ref="4"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <way id="103">
+    <nd ref="5"/>
+    <nd ref="25"/>
+    <nd ref="24"/>
+    <nd ref="2"/>
+    <tag k="highway" v="residential"/>
+  </way>
+</osm>"#;
+        let streets = crate::osm_to_street_network(
+            osm,
+            None,
+            crate::Options::default_for_side(DrivingSide::Right),
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+
+        let west = OriginalRoad::new(100, (1, 2));
+        let east = OriginalRoad::new(101, (2, 3));
+        let south = OriginalRoad::new(102, (2, 4));
+        let north = OriginalRoad::new(103, (5, 2));
+        let center = &streets.intersections[&osm::NodeID(2)];
+        assert_eq!(center.control, ControlType::TrafficSignal);
+        assert_eq!(
+            center.approach_controls,
+            BTreeMap::from([
+                (west, ApproachControl::TrafficSignal),
+                (east, ApproachControl::TrafficSignal),
+                (south, ApproachControl::Stop),
+                (north, ApproachControl::Stop),
+            ])
+        );
+        // The give way sign is closest to the other end of the road
+        assert_eq!(
+            streets.intersections[&osm::NodeID(5)].approach_controls,
+            BTreeMap::from([(north, ApproachControl::Yield)])
+        );
+    }
+
+    #[test]
+    fn test_stop_sign_on_intersection() {
+        // A primary road crossing a residential one at node 2, with a stop sign on that node
+        let import = |stop_tags: &str| {
+            let osm = format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="47.6" minlon="-122.34" maxlat="47.61" maxlon="-122.33"/>
+  <node id="1" lat="47.605" lon="-122.338"/>
+  <node id="2" lat="47.605" lon="-122.335">
+    <tag k="highway" v="stop"/>
+    {stop_tags}
+  </node>
+  <node id="3" lat="47.605" lon="-122.332"/>
+  <node id="4" lat="47.602" lon="-122.335"/>
+  <node id="5" lat="47.608" lon="-122.335"/>
+  <way id="100">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="primary"/>
+  </way>
+  <way id="101">
+    <nd ref="4"/>
+    <nd ref="2"/>
+    <nd ref="5"/>
+    <tag k="highway" v="residential"/>
+  </way>
+</osm>"#
+            );
+            crate::osm_to_street_network(
+                &osm,
+                None,
+                crate::Options::default_for_side(DrivingSide::Right),
+                &mut Timer::throwaway(),
+            )
+            .unwrap()
+        };
+        let i = osm::NodeID(2);
+        // Ordered by ID
+        let minor = vec![
+            OriginalRoad::new(101, (2, 5)),
+            OriginalRoad::new(101, (4, 2)),
+        ];
+        let all = vec![
+            OriginalRoad::new(100, (1, 2)),
+            OriginalRoad::new(100, (2, 3)),
+            OriginalRoad::new(101, (2, 5)),
+            OriginalRoad::new(101, (4, 2)),
+        ];
+
+        // Without stop=all, only the minor road stops
+        for stop_tags in ["", r#"<tag k="stop" v="minor"/>"#] {
+            let streets = import(stop_tags);
+            assert_eq!(streets.must_stop(i), minor);
+            assert!(!streets.is_all_way_stop(i));
+        }
+
+        let mut streets = import(r#"<tag k="stop" v="all"/>"#);
+        assert_eq!(streets.must_stop(i), all);
+        assert!(streets.is_all_way_stop(i));
+        streets.apply_transformations(
+            vec![Transformation::InferIntersectionControl],
+            &mut Timer::throwaway(),
+        );
+        assert_eq!(streets.intersections[&i].control, ControlType::StopSign);
+    }
+}
@@ -3,7 +3,7 @@ mod tests {
     use abstutil::Timer;
     use anyhow::{bail, Result};
     use experimental::RoadNetwork;
-    use osm2streets::{DrivingSide, Transformation};
+    use osm2streets::{DrivingSide, LaneType, Transformation};
     use serde::Deserialize;
     use std::fs::File;
 
@@ -23,17 +23,34 @@ mod tests {
         let prior_dot = std::fs::read_to_string(format!("{path}/road_network.dot"))
             .unwrap_or_else(|_| String::new());
 
+        let options = || {
+            let mut opts = streets_reader::Options::default_for_side(cfg.driving_side);
+            if let Some(inferred_sidewalks) = cfg.inferred_sidewalks {
+                opts.map_config.inferred_sidewalks = inferred_sidewalks;
+            }
+            opts
+        };
+
+        let input = std::fs::read_to_string(format!("{path}/input.osm"))?;
         let clip_pts = None;
-        let mut street_network = streets_reader::osm_to_street_network(
-            &std::fs::read_to_string(format!("{path}/input.osm"))?,
-            clip_pts,
-            streets_reader::Options::default_for_side(cfg.driving_side),
-            &mut timer,
-        )?;
+        let mut street_network =
+            streets_reader::osm_to_street_network(&input, clip_pts, options(), &mut timer)?;
         street_network
             .apply_transformations(Transformation::standard_for_clipped_areas(), &mut timer);
         street_network.save_to_geojson(format!("{path}/geometry.json"), &mut timer)?;
 
+        // Reading the input in streaming mode must produce the same result
+        let mut opts = options();
+        opts.streaming = true;
+        let mut streamed = streets_reader::osm_to_street_network(&input, None, opts, &mut timer)?;
+        streamed.apply_transformations(Transformation::standard_for_clipped_areas(), &mut timer);
+        if streamed.to_geojson(&mut timer)? != street_network.to_geojson(&mut timer)? {
+            bail!(
+                "./{}/input.osm gives a different result in streaming mode",
+                path
+            );
+        }
+
         let road_network: RoadNetwork = street_network.into();
         std::fs::write(format!("{path}/road_network.dot"), road_network.to_dot())?;
 
@@ -56,9 +73,146 @@ mod tests {
         Ok(())
     }
 
+    // The expected output for these cases shows where separate cycletracks were snapped, but it's
+    // hard to read from the geometry. Check the tracks wind up as lanes on their roads.
+    #[test]
+    fn test_snap_cycleways() {
+        for path in [
+            "src/st_georges_cycletrack",
+            "src/roosevelt_cycletrack",
+            "src/leeds_cycleway",
+        ] {
+            snap_cycleways(path).unwrap();
+        }
+    }
+
+    fn snap_cycleways(path: &str) -> Result<()> {
+        let mut timer = Timer::new("test cycleway snapping");
+        let cfg: TestCase = serde_json::from_reader(File::open(format!("{path}/test.json"))?)?;
+        let input = std::fs::read_to_string(format!("{path}/input.osm"))?;
+        let mut street_network = streets_reader::osm_to_street_network(
+            &input,
+            None,
+            streets_reader::Options::default_for_side(cfg.driving_side),
+            &mut timer,
+        )?;
+
+        // Roads where OSM says the cycleway is mapped separately
+        let separate: Vec<_> = street_network
+            .roads
+            .iter()
+            .filter(|(_, road)| {
+                road.osm_tags
+                    .inner()
+                    .iter()
+                    .any(|(k, v)| k.starts_with("cycleway") && v == "separate")
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        let num_cycleways = street_network
+            .roads
+            .values()
+            .filter(|road| road.is_cycleway())
+            .count();
+
+        street_network
+            .apply_transformations(Transformation::standard_for_clipped_areas(), &mut timer);
+
+        if street_network
+            .roads
+            .values()
+            .filter(|road| road.is_cycleway())
+            .count()
+            >= num_cycleways
+        {
+            bail!("No cycleways in {path} were snapped");
+        }
+        let snapped = separate.into_iter().any(|segment| {
+            street_network
+                .road_for_original_segment(segment)
+                .map(|r| {
+                    street_network.roads[&r]
+                        .lane_specs_ltr
+                        .iter()
+                        .any(|lane| lane.lt == LaneType::Biking)
+                })
+                .unwrap_or(false)
+        });
+        if !snapped {
+            bail!("None of the roads with separate cycleways in {path} got a bike lane");
+        }
+        Ok(())
+    }
+
+    // input.osm.pbf was converted from input.osm. Coordinates are stored differently in the two
+    // formats, so points only have to match approximately.
+    #[test]
+    fn test_pbf_matches_xml() -> Result<()> {
+        let path = "src/oneway_loop";
+        let mut timer = Timer::new("test reading .osm.pbf");
+        let cfg: TestCase = serde_json::from_reader(File::open(format!("{path}/test.json"))?)?;
+
+        let input = std::fs::read_to_string(format!("{path}/input.osm"))?;
+        let mut from_xml = streets_reader::osm_to_street_network(
+            &input,
+            None,
+            streets_reader::Options::default_for_side(cfg.driving_side),
+            &mut timer,
+        )?;
+        from_xml.apply_transformations(Transformation::standard_for_clipped_areas(), &mut timer);
+
+        for streaming in [false, true] {
+            let mut opts = streets_reader::Options::default_for_side(cfg.driving_side);
+            opts.streaming = streaming;
+            let mut from_pbf = streets_reader::pbf_to_street_network(
+                &format!("{path}/input.osm.pbf"),
+                None,
+                opts,
+                &mut timer,
+            )?;
+            from_pbf
+                .apply_transformations(Transformation::standard_for_clipped_areas(), &mut timer);
+
+            if !from_xml.roads.keys().eq(from_pbf.roads.keys())
+                || !from_xml
+                    .intersections
+                    .keys()
+                    .eq(from_pbf.intersections.keys())
+            {
+                bail!("{path}/input.osm.pbf has different roads or intersections than the XML");
+            }
+            for (id, road) in &from_xml.roads {
+                let other = &from_pbf.roads[id];
+                if road.osm_tags != other.osm_tags
+                    || road.lane_specs_ltr != other.lane_specs_ltr
+                    || road.osm_center_points.len() != other.osm_center_points.len()
+                    || road
+                        .osm_center_points
+                        .iter()
+                        .zip(&other.osm_center_points)
+                        .any(|(pt1, pt2)| pt1.dist_to(*pt2).inner_meters() > 0.01)
+                {
+                    bail!("{id} from {path}/input.osm.pbf doesn't match the XML");
+                }
+            }
+            for (id, intersection) in &from_xml.intersections {
+                let other = &from_pbf.intersections[id];
+                if intersection.control != other.control
+                    || intersection.point.dist_to(other.point).inner_meters() > 0.01
+                {
+                    bail!("Intersection {id} from {path}/input.osm.pbf doesn't match the XML");
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[derive(Deserialize)]
     struct TestCase {
         driving_side: DrivingSide,
+        // Overrides `MapConfig::inferred_sidewalks`, to import separately mapped sidewalks
+        #[serde(default)]
+        inferred_sidewalks: Option<bool>,
         // There's also a notes field that's ignored
     }
 }